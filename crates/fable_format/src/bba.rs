@@ -1,43 +1,242 @@
-/// Animation format.
-///
-/// ## Format Description
-///
-/// A placeholder description from <http://fabletlcmod.com>:
-///
-/// ```txt
-/// 3DAF: 3D Sequence File
-///     ANRT: (File Size of all Chunks)
-///     AOBJ: Animated Object
-///         AMSK: Animation Mask
-///         XSEQ: Compressed Animation Sequence
-///     HLPR: Helpers
-///         TMEV: Timing Event
-///         MVEC: Movement Vector
-///         XALO: Allocation Size Helper Chunk??
-///
-///
-/// 0 - No bytes (My personal favorite)
-/// 1 - 2 Ints (No Strange Stuff)
-/// 2 - INT, Byte, Byte (flag)
-///                    Flag = 1: Int, Byte, Float, Int
-///                    Flag = 0: Short, Int Int
-/// 3 - Int, Int, Int, Int (Flag?)
-///                    Flag = 6: Byte, Byte
-///                    Flag = 3: Int Int
-///                    Flag = anything else?: Int
-/// 4 - Int, Int, Int, Int, Int, Int, Int (Flag)
-///                    Flag = 0: Int
-///                    Flag = 4: Int, Int
-///                    Flag = 5: Int
-///                    Flag = anything else?: No further data
-/// 5 - Int, Int, Int, Int, Int, Byte, Byte (Flag)
-///                    Flag = 1: Int, Byte, Float, Float, Int, Int (String Size), ~String
-///                    Flag = 0: Short, Int, Int, Int (Flag?)
-///                                        Flag = 4294966519: Int, Int, Byte, Byte
-///                                        Flag !=4294966519: Int, Int, Int
-///
-/// I believe I cracked the format for animations a while ago. If I remember correctly it was a quaternion based system.
-///
-/// Lets start it off simple. CAppearanceDef application, first off what does this do? It basically is the list of animations the hero users when called upon. This is essentially how I created the "Piss" Expression, you could change animation IDs for say when your hero runs with a large sword, add jumps etc... This Cdef couldn't be done with the xml so something custom was made.
-/// ```
-pub struct Bba {}
+//! Animation format (`3DAF`), one animation of a skeleton's bones.
+//!
+//! ## Format Description
+//!
+//! A placeholder description from <http://fabletlcmod.com>:
+//!
+//! ```txt
+//! 3DAF: 3D Sequence File
+//!     ANRT: (File Size of all Chunks)
+//!     AOBJ: Animated Object
+//!         AMSK: Animation Mask
+//!         XSEQ: Compressed Animation Sequence
+//!     HLPR: Helpers
+//!         TMEV: Timing Event
+//!         MVEC: Movement Vector
+//!         XALO: Allocation Size Helper Chunk??
+//!
+//!
+//! 0 - No bytes (My personal favorite)
+//! 1 - 2 Ints (No Strange Stuff)
+//! 2 - INT, Byte, Byte (flag)
+//!                    Flag = 1: Int, Byte, Float, Int
+//!                    Flag = 0: Short, Int Int
+//! 3 - Int, Int, Int, Int (Flag?)
+//!                    Flag = 6: Byte, Byte
+//!                    Flag = 3: Int Int
+//!                    Flag = anything else?: Int
+//! 4 - Int, Int, Int, Int, Int, Int, Int (Flag)
+//!                    Flag = 0: Int
+//!                    Flag = 4: Int, Int
+//!                    Flag = 5: Int
+//!                    Flag = anything else?: No further data
+//! 5 - Int, Int, Int, Int, Int, Byte, Byte (Flag)
+//!                    Flag = 1: Int, Byte, Float, Float, Int, Int (String Size), ~String
+//!                    Flag = 0: Short, Int, Int, Int (Flag?)
+//!                                        Flag = 4294966519: Int, Int, Byte, Byte
+//!                                        Flag !=4294966519: Int, Int, Int
+//!
+//! I believe I cracked the format for animations a while ago. If I remember correctly it was a quaternion based system.
+//!
+//! Lets start it off simple. CAppearanceDef application, first off what does this do? It basically is the list of animations the hero users when called upon. This is essentially how I created the "Piss" Expression, you could change animation IDs for say when your hero runs with a large sword, add jumps etc... This Cdef couldn't be done with the xml so something custom was made.
+//! ```
+//!
+//! ## Reading
+//!
+//! Chunks are read with [`crate::chunk`], the same way as [`crate::tagged_model`]:
+//!
+//! - The file is one `3DAF` chunk holding the others. `ANRT` is either a dword or, when its data
+//!   is nothing but chunks, a container of the chunks listed beside it.
+//! - Each `AOBJ` is the track of one bone. Unless its data is nothing but chunks, it starts with
+//!   the bone's nul terminated name, followed by its chunks.
+//! - `HLPR` holds the helpers of the whole animation, wherever it is.
+//!
+//! The contents of the leaf chunks are undocumented and kept as they are, as are chunks with tags
+//! the description doesn't list, so nothing is lost.
+//!
+//! ## Sequences
+//!
+//! The notes suggest `XSEQ` holds quaternion keyframes, but its layout hasn't been confirmed
+//! against the game's files, so sequences are kept as raw bytes.
+
+use crate::common::{
+    bytes::{take_bytes_nul_terminated, TakeError, UnexpectedEnd},
+    chunk::{ChildError, Chunk, ChunkError, TagOrder},
+};
+use crate::tagged_model::TaggedChunk;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Bba {
+    /// The dword `ANRT` holds, unless it's a container.
+    pub size: Option<u32>,
+    /// `AOBJ` chunks, one per animated bone.
+    pub tracks: Vec<BbaTrack>,
+    pub helpers: BbaHelpers,
+    pub other: Vec<TaggedChunk>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BbaTrack {
+    /// The bone's name, when the `AOBJ` has one.
+    pub name: Option<Vec<u8>>,
+    /// `AMSK` chunks.
+    pub masks: Vec<Vec<u8>>,
+    /// `XSEQ` chunks.
+    pub sequences: Vec<Vec<u8>>,
+    pub other: Vec<TaggedChunk>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BbaHelpers {
+    /// `TMEV` chunks.
+    pub timing_events: Vec<Vec<u8>>,
+    /// `MVEC` chunks.
+    pub movement_vectors: Vec<Vec<u8>>,
+    /// `XALO` chunks.
+    pub allocation_sizes: Vec<Vec<u8>>,
+    pub other: Vec<TaggedChunk>,
+}
+
+#[derive(Debug)]
+pub enum BbaError<E> {
+    /// The file doesn't start with `3DAF` in either tag order.
+    Magic,
+    Root(ChunkError<E>),
+    Chunk(ChildError<E>),
+    /// An `AOBJ` that isn't a container has no nul terminated name.
+    TrackName(UnexpectedEnd),
+}
+
+impl Bba {
+    pub fn parse(inp: &mut &[u8]) -> Result<Bba, BbaError<TakeError>> {
+        let order = TagOrder::detect(inp, b"3DAF").ok_or(BbaError::Magic)?;
+
+        let root = Chunk::parse(inp, order).map_err(BbaError::Root)?;
+
+        let mut bba = Bba::default();
+        bba.read(&root)?;
+
+        Ok(bba)
+    }
+
+    /// The track of the bone named `name`.
+    pub fn track(&self, name: &[u8]) -> Option<&BbaTrack> {
+        self.tracks.iter().find(|x| x.name.as_deref() == Some(name))
+    }
+
+    /// Reads the chunks inside `parent` into the animation.
+    fn read(&mut self, parent: &Chunk) -> Result<(), BbaError<TakeError>> {
+        for chunk in parent.collect_children().map_err(BbaError::Chunk)? {
+            match &chunk.tag {
+                b"ANRT" if chunk.is_container() => self.read(&chunk)?,
+                b"ANRT" if chunk.data.len() == 4 => {
+                    self.size = Some(u32::from_le_bytes(chunk.data.try_into().unwrap()));
+                }
+                b"AOBJ" => self.tracks.push(BbaTrack::parse(chunk)?),
+                b"HLPR" => self.helpers.read(&chunk)?,
+                _ => self.other.push(chunk.into()),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl BbaTrack {
+    fn parse(mut chunk: Chunk) -> Result<BbaTrack, BbaError<TakeError>> {
+        let mut track = BbaTrack::default();
+
+        if !chunk.is_container() {
+            let name = take_bytes_nul_terminated(&mut chunk.data).map_err(BbaError::TrackName)?;
+            track.name = Some(name.to_vec());
+            chunk.data = &chunk.data[1..];
+        }
+
+        for chunk in chunk.collect_children().map_err(BbaError::Chunk)? {
+            match &chunk.tag {
+                b"AMSK" => track.masks.push(chunk.data.to_vec()),
+                b"XSEQ" => track.sequences.push(chunk.data.to_vec()),
+                _ => track.other.push(chunk.into()),
+            }
+        }
+
+        Ok(track)
+    }
+}
+
+impl BbaHelpers {
+    fn read(&mut self, parent: &Chunk) -> Result<(), BbaError<TakeError>> {
+        for chunk in parent.collect_children().map_err(BbaError::Chunk)? {
+            match &chunk.tag {
+                b"TMEV" => self.timing_events.push(chunk.data.to_vec()),
+                b"MVEC" => self.movement_vectors.push(chunk.data.to_vec()),
+                b"XALO" => self.allocation_sizes.push(chunk.data.to_vec()),
+                _ => self.other.push(chunk.into()),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::chunk::chunk_bytes;
+
+    #[test]
+    fn parse_bba() {
+        let arm = [
+            b"ARM\0".to_vec(),
+            chunk_bytes(b"AMSK", &[1]),
+            chunk_bytes(b"XSEQ", &[2, 3]),
+        ]
+        .concat();
+
+        let root = [chunk_bytes(b"AMSK", &[4]), chunk_bytes(b"XSEQ", &[5])].concat();
+
+        let helpers = [
+            chunk_bytes(b"TMEV", &[6]),
+            chunk_bytes(b"MVEC", &[7]),
+            chunk_bytes(b"XALO", &[8]),
+        ]
+        .concat();
+
+        let file = chunk_bytes(
+            b"3DAF",
+            &[
+                chunk_bytes(b"ANRT", &10u32.to_le_bytes()),
+                chunk_bytes(b"AOBJ", &arm),
+                chunk_bytes(b"AOBJ", &root),
+                chunk_bytes(b"HLPR", &helpers),
+            ]
+            .concat(),
+        );
+
+        let bba = Bba::parse(&mut &file[..]).unwrap();
+
+        assert_eq!(bba.size, Some(10));
+        assert_eq!(bba.tracks.len(), 2);
+
+        let arm = bba.track(b"ARM").unwrap();
+        assert_eq!(arm.masks, [vec![1]]);
+        assert_eq!(arm.sequences, [vec![2, 3]]);
+
+        assert_eq!(bba.tracks[1].name, None);
+        assert_eq!(bba.tracks[1].sequences, [vec![5]]);
+
+        assert_eq!(bba.helpers.timing_events, [vec![6]]);
+        assert_eq!(bba.helpers.movement_vectors, [vec![7]]);
+        assert_eq!(bba.helpers.allocation_sizes, [vec![8]]);
+
+        let unnamed = chunk_bytes(b"3DAF", &chunk_bytes(b"AOBJ", b"ARM"));
+        assert!(matches!(
+            Bba::parse(&mut &unnamed[..]),
+            Err(BbaError::TrackName(UnexpectedEnd))
+        ));
+        assert!(matches!(
+            Bba::parse(&mut &b"3DMF"[..]),
+            Err(BbaError::Magic)
+        ));
+    }
+}
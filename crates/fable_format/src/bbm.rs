@@ -1,3 +1,15 @@
+use std::{borrow::Cow, num::TryFromIntError};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    big::BigSubHeaderMesh,
+    common::{
+        bytes::{push, take, take_bytes, take_bytes_nul_terminated, TakeError, UnexpectedEnd},
+        lzo::{self, LzoError},
+    },
+};
+
 /// Mesh format.
 ///
 /// ## Format Description
@@ -36,19 +48,19 @@
 /// word         HPNT_Count;
 /// word         HDMY_Count;
 /// dword        HLPR_Index_Uncompressed;
-/// word			padding;
-/// word			HPNT_Compressed;
+/// word            padding;
+/// word            HPNT_Compressed;
 /// Helper Points[HPNT_Count];
 ///   float         Matrix[4]; //No Rotation
 ///   long          hierarchy;
-/// word			HDMY_Compressed;
+/// word            HDMY_Compressed;
 /// Helper Dummies[HDMY_Count];
 ///   float        Matrix[13];
 ///   long         hierarchy;
-/// word			HLPR_Index_Compressed;
-/// word			HPNT_IndexSize;
-/// char			HPNT_Index[HPNT_IndexSize-2]; //Subtract the size
-/// char		    HDMY_Index[HLPR_Index_Uncompressed-HPNT_IndexSize]; //Rest of helper index deduced
+/// word            HLPR_Index_Compressed;
+/// word            HPNT_IndexSize;
+/// char            HPNT_Index[HPNT_IndexSize-2]; //Subtract the size
+/// char            HDMY_Index[HLPR_Index_Uncompressed-HPNT_IndexSize]; //Rest of helper index deduced
 /// dword        NumberMaterials;
 /// dword        NumberSurfaces;
 /// dword        NumberBones;
@@ -62,55 +74,55 @@
 /// char         BoneIndex[SizeOfBoneIndex];
 /// word         CompressedSize;
 /// Bones SUB CHUNK 1[NumberBones];
-/// word					CompressedSize;
+/// word                    CompressedSize;
 /// Bones SUB CHUNK 2[NumberBones];
-/// word					CompressedSize;
+/// word                    CompressedSize;
 /// Bones SUB CHUNK 3[NumberBones];
-/// float					Matrix[12]; //Transform Matrix
+/// float                   Matrix[12]; //Transform Matrix
 ///
 ///  Bones
 ///      SUB CHUNK 1
-///          long					Index;
-///          long					Parent;
-///          long					nChild;
-///          float					Matrix[12];
+///          long                   Index;
+///          long                   Parent;
+///          long                   nChild;
+///          float                  Matrix[12];
 ///      SUB CHUNK 2
-///          float					Matrix[12];
+///          float                  Matrix[12];
 ///      SUB CHUNK 3
-///          float					Matrix[16];
+///          float                  Matrix[16];
 ///
 ///  Material List
-///      dword					Material_Index;
-///      char					NullTerminatedString[x];
-///      dword					Padding;
-///      dword					BASE_Texture_ID; //From Texture.big
-///      dword					BUMPMAP_Texture_ID; //From Texture.big
-///      dword					REFLECT_Texture_ID; //From Texture.big
-///      dword					Unknown;
-///      dword					Max_Texture_Layers;
-///      dword					Glow_Strength;
-///      byte					Unknown;
-///      byte					Alpha_Enabled;
-///      byte					Unknown;
-///      word					Ignored; //For degenerate Tri's
+///      dword                  Material_Index;
+///      char                   NullTerminatedString[x];
+///      dword                  Padding;
+///      dword                  BASE_Texture_ID; //From Texture.big
+///      dword                  BUMPMAP_Texture_ID; //From Texture.big
+///      dword                  REFLECT_Texture_ID; //From Texture.big
+///      dword                  Unknown;
+///      dword                  Max_Texture_Layers;
+///      dword                  Glow_Strength;
+///      byte                   Unknown;
+///      byte                   Alpha_Enabled;
+///      byte                   Unknown;
+///      word                   Ignored; //For degenerate Tri's
 ///
 ///  Sub-Mesh
-///  dword							Hierarchy;
-///  dword							DestroyableMeshLevels;
-///  float							floats[5];
-///  dword							nFaceVertexIndices;
-///  dword							nFaceVertexIndices_BoneIndice;
-///  dword							nVerts;
-///  dword							nFaces;
-///  dword							nSourceVerts;
-///  dword							Unknown;
-///  dword							Unknown;
-///  dword							Unknown;
-///  struct structMTRLnFaceVertexIndices			FaceIndices[nFaceVertexIndices];
-///  struct structMTRLnFaceVertexIndicesBoneIndice		Face_BoneIndices[nFaceVertexIndices_BoneIndice];
-///  float							floats[8];
-///  dword							sVert;
-///  dword							padding;
+///  dword                          Hierarchy;
+///  dword                          DestroyableMeshLevels;
+///  float                          floats[5];
+///  dword                          nFaceVertexIndices;
+///  dword                          nFaceVertexIndices_BoneIndice;
+///  dword                          nVerts;
+///  dword                          nFaces;
+///  dword                          nSourceVerts;
+///  dword                          Unknown;
+///  dword                          Unknown;
+///  dword                          Unknown;
+///  struct structMTRLnFaceVertexIndices            FaceIndices[nFaceVertexIndices];
+///  struct structMTRLnFaceVertexIndicesBoneIndice      Face_BoneIndices[nFaceVertexIndices_BoneIndice];
+///  float                          floats[8];
+///  dword                          sVert;
+///  dword                          padding;
 ///  //Start of Mesh
 ///
 ///  Quick notes on sVert “Size Vertice Blocks”….
@@ -126,443 +138,1703 @@
 ///
 ///   struct CLTH
 ///  {
-///  	//DWORD				SubMesh_ClothCount;
-///  	DWORD				Cloth_ID;
-///  	DWORD				??_ID; //possibly material ID
-///  	DWORD				sChunk; //Size of full clothing data
-///  	DWORD				Unknown5;
-///  	DWORD				sDistanceIndice;
-///  	CLTH_Distance*			DistanceIndice;//[sDistanceIndice/28]; //Distance between two particles
-///  	float				Unknown8;
-///  	float				Unknown9;
-///  	float				Unknown10;
-///  	DWORD				sParticleIndice;
-///  	CLTH_Particle*			ParticleIndice;//[sParticleIndice];
-///  	float*				ParticleAlphaIndice;//[sParticleIndice]; //How "free" they are. 0.0 = Static and gets a duped //  entry in verts
-///  	DWORD				Unknown11;
-///  	float				WindStrength; //strength
-///  	char				EnableDragging; //enable
-///  	char				RotationalDragging; //rotational
-///  	float				StrengthDragging; //strength
-///  	char				EnableAcceleration; //enable
-///  	float				AccelerationDampening; //damping
-///  	DWORD				nTriIndice;
-///  	CLTH_Tri*			TriIndice;//[nTriIndice] Particles+"Unique" Verts
-///  	DWORD				Unknown12; // looks like padding it
-///  	DWORD				sTexIndice;
-///  	CLTH_Tex*			TexIndice;//[sTexIndice]; //v1 = Particle/"unique" Vert, v2 = TexIndice
-///  	DWORD				sVertexIndice;
-///  	CLTH_Vertex*			VertexIndice;//[sVertexIndice];
-///  	DWORD				sTexCoordIndice;
-///  	CLTH_TexCoord*			TexCoordIndice;//[sTexCoordIndice];
-///  	DWORD				sParticleMask;
-///  	CLTH_PartMask*			ParticleMask;//[sParticleMask]; //Unique Particles in TriIndice
-///  	DWORD				sVertMask;
-///  	CLTH_VertMask*			Vertmask;//[sVertMask]; //Unique Verts in TriIndice
-///  	//9 bytes of padding
-///  	// 1 group for particles, 1 for verts
-///  	DWORD				VGRPCount; // = Number of Bones
-///  	VGRP**				VGRPs;
+///     //DWORD             SubMesh_ClothCount;
+///     DWORD               Cloth_ID;
+///     DWORD               ??_ID; //possibly material ID
+///     DWORD               sChunk; //Size of full clothing data
+///     DWORD               Unknown5;
+///     DWORD               sDistanceIndice;
+///     CLTH_Distance*          DistanceIndice;//[sDistanceIndice/28]; //Distance between two particles
+///     float               Unknown8;
+///     float               Unknown9;
+///     float               Unknown10;
+///     DWORD               sParticleIndice;
+///     CLTH_Particle*          ParticleIndice;//[sParticleIndice];
+///     float*              ParticleAlphaIndice;//[sParticleIndice]; //How "free" they are. 0.0 = Static and gets a duped //  entry in verts
+///     DWORD               Unknown11;
+///     float               WindStrength; //strength
+///     char                EnableDragging; //enable
+///     char                RotationalDragging; //rotational
+///     float               StrengthDragging; //strength
+///     char                EnableAcceleration; //enable
+///     float               AccelerationDampening; //damping
+///     DWORD               nTriIndice;
+///     CLTH_Tri*           TriIndice;//[nTriIndice] Particles+"Unique" Verts
+///     DWORD               Unknown12; // looks like padding it
+///     DWORD               sTexIndice;
+///     CLTH_Tex*           TexIndice;//[sTexIndice]; //v1 = Particle/"unique" Vert, v2 = TexIndice
+///     DWORD               sVertexIndice;
+///     CLTH_Vertex*            VertexIndice;//[sVertexIndice];
+///     DWORD               sTexCoordIndice;
+///     CLTH_TexCoord*          TexCoordIndice;//[sTexCoordIndice];
+///     DWORD               sParticleMask;
+///     CLTH_PartMask*          ParticleMask;//[sParticleMask]; //Unique Particles in TriIndice
+///     DWORD               sVertMask;
+///     CLTH_VertMask*          Vertmask;//[sVertMask]; //Unique Verts in TriIndice
+///     //9 bytes of padding
+///     // 1 group for particles, 1 for verts
+///     DWORD               VGRPCount; // = Number of Bones
+///     VGRP**              VGRPs;
 ///  };
 /// ```
-
-#[derive(Debug, PartialEq)]
+///
+/// ## Reading
+///
+/// This module reads the compiled format; the tagged format is read by [`crate::tagged_model`].
+///
+/// Blocks whose size is known up front (helpers, the helper and bone indexes and the bone sub
+/// chunks) are preceded by a word holding their LZO compressed size, or 0 when the block is stored
+/// as is. The helper and bone indexes are NUL terminated names, in the order of the helpers and
+/// bones they name.
+///
+/// Where the description above is vague this parser reads it as:
+///
+/// - Bone blocks are always present and empty without bones, and `Bone_Index_Reference` holds
+///   one fewer entries than there are bones.
+/// - `structMTRLnFaceVertexIndices` is four dwords: material, repeating mesh reps, first index
+///   and index count. `structMTRLnFaceVertexIndicesBoneIndice` is four dwords: first index,
+///   index count, bone and an unknown, attaching whole triangles to one bone.
+/// - The 8 floats before `sVert` are a position offset and a position scale, as two vec4s, used
+///   by packed coordinates.
+/// - After the vertices come `nFaces * 3` word indices of a triangle list, then a dword count of
+///   `CLTH` chunks, each holding its two ids and `sChunk` bytes.
+/// - `CLTH` is decoded by [`BbmCloth::decode`], with the sizes of its elements guessed. `sChunk`
+///   counts the bytes after it, `sDistanceIndice` is a size in bytes and the other `s` and `n`
+///   fields count elements: particles are a float position, triangles and texture indices are
+///   dwords, vertices and texture coordinates are floats, masks are dwords, and a `VGRP` is a
+///   dword count of dword vertex indices.
+/// - `sVert` codes shared by several layouts are told apart by whether the mesh has bones and,
+///   for code 4 without bones, whether the sub-mesh has destroyable mesh levels. See
+///   [`BbmVertexLayout::new`].
+///
+/// ## Writing
+///
+/// [`Bbm::compile`] writes the same layout back. Blocks are LZO compressed when that makes them
+/// smaller, and stored as is otherwise.
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Bbm {
-    pub header: BbmHeader,
-}
-
-#[derive(Debug, PartialEq)]
-pub struct BbmHeader {
-    pub name: String,
+    pub name: Vec<u8>,
     pub has_skeleton: u8,
-    pub model_origin: Vec<f32>,
-    pub hpnt_count: u16,
-    pub hdmy_count: u16,
-    pub hlpr_index_uncompressed: u32,
+    pub origin: [f32; 10],
     pub padding: u16,
-    pub hpnt_compressed: u16,
     pub helper_points: Vec<BbmHelperPoint>,
-    pub hdmy_compressed: u16,
     pub helper_dummies: Vec<BbmHelperDummy>,
-    // pub hlpr_index_compressed: u16,
-    pub hpnt_index_size: u16,
-    pub hpnt_index: Vec<u8>,
-    // pub hdmy_index: Vec<u8>,
-    // pub material_count: u32,
-    // pub surface_count: u32,
-    // pub bone_count: u32,
-    // pub bone_index_size: u32,
-    // pub unknown3: u8,
-    // pub unknown4: u16,
-    // pub unknown5: u16,
-    // pub compressed: u16,
-    // pub bone_index_reference: Vec<u16>,
-    // pub bone_index_compressed: u16,
-    // pub bone_index: Vec<u8>,
-    // pub compressed_size: u16,
-}
-
-#[derive(Debug, PartialEq)]
+    pub helper_point_names: Vec<Vec<u8>>,
+    pub helper_dummy_names: Vec<Vec<u8>>,
+    pub unknown1: u8,
+    pub unknown2: u16,
+    pub unknown3: u16,
+    pub bone_index_reference: Vec<u16>,
+    pub bones: Vec<BbmBone>,
+    pub transform: [f32; 12],
+    pub materials: Vec<BbmMaterial>,
+    pub sub_meshes: Vec<BbmSubMesh>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct BbmHelperPoint {
-    pub matrix: Vec<f32>, // 2x2 matrix?
+    pub matrix: [f32; 4],
     pub hierarchy: i32,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct BbmHelperDummy {
-    pub matrix: Vec<f32>, // 13 float matrix?
+    pub matrix: [f32; 13],
     pub hierarchy: i32,
 }
 
-// use common::{
-//     parser::{
-//         all_consuming, count, decode_null_terminated_string, le_f32, le_i32, le_u16, le_u32, le_u8,
-//         IResult,
-//     },
-//     Error,
-// };
-
-// use crate::{Bbm, BbmHeader, BbmHelperDummy, BbmHelperPoint};
-
-// impl Bbm {
-//     pub fn decode<Source>(source: &mut Source) -> Result<Bbm, Error>
-//     where
-//         Source: Read + Seek,
-//     {
-//         let mut data = Vec::new();
-//         source.read_to_end(&mut data)?;
-//         // let (_, bbm) = all_consuming(Bbm::decode_bbm)(&data)?;
-//         let (_, bbm) = Bbm::decode_bbm(&data)?;
-//         Ok(bbm)
-//     }
-
-//     pub fn decode_bbm(input: &[u8]) -> IResult<&[u8], Bbm, Error> {
-//         let (input, header) = Self::decode_header(input)?;
-
-//         Ok((input, Bbm { header: header }))
-//     }
-
-//     pub fn decode_header(input: &[u8]) -> IResult<&[u8], BbmHeader, Error> {
-//         let (input, name) = decode_null_terminated_string(input)?;
-//         let (input, has_skeleton) = le_u8(input)?;
-//         let (input, model_origin) = count(le_f32, 10usize)(input)?;
-//         let (input, hpnt_count) = le_u16(input)?;
-//         let (input, hdmy_count) = le_u16(input)?;
-//         let (input, hlpr_index_uncompressed) = le_u32(input)?;
-//         let (input, padding) = le_u16(input)?;
-//         let (input, hpnt_compressed) = le_u16(input)?;
-//         let (input, helper_points) = count(Self::decode_helper_point, hpnt_count as usize)(input)?;
-//         let (input, hdmy_compressed) = le_u16(input)?;
-//         let (input, helper_dummies) = count(Self::decode_helper_dummy, hdmy_count as usize)(input)?;
-//         // let (input, hlpr_index_compressed) = le_u16(input)?;
-//         let (input, hpnt_index_size) = le_u16(input)?;
-//         let (input, hpnt_index) = count(le_u8, hpnt_index_size.saturating_sub(2) as usize)(input)?;
-//         // let (input, hdmy_index) = count(le_u8, (hlpr_index_uncompressed.checked_sub(hpnt_index_size.into()).unwrap_or(0)) as usize)(input)?;
-//         // let (input, material_count) = le_u32(input)?;
-//         // let (input, surface_count) = le_u32(input)?;
-//         // let (input, bone_count) = le_u32(input)?;
-//         // let (input, bone_index_size) = le_u32(input)?;
-//         // let (input, unknown3) = le_u8(input)?;
-//         // let (input, unknown4) = le_u16(input)?;
-//         // let (input, unknown5) = le_u16(input)?;
-//         // let (input, compressed) = le_u16(input)?;
-//         // let (input, bone_index_reference) = count(le_u16, (bone_count - 1) as usize)(input)?;
-//         // let (input, bone_index_compressed) = le_u16(input)?;
-//         // let (input, bone_index) = count(le_u8, bone_index_size as usize)(input)?;
-//         // let (input, compressed_size) = le_u16(input)?;
-
-//         dbg!(&name);
-//         dbg!(&has_skeleton);
-//         dbg!(&model_origin);
-//         dbg!(&hpnt_count);
-//         dbg!(&hdmy_count);
-//         dbg!(&hlpr_index_uncompressed);
-//         dbg!(&padding);
-//         dbg!(&hpnt_compressed);
-//         dbg!(&helper_points);
-//         dbg!(&hdmy_compressed);
-//         dbg!(&helper_dummies);
-//         // dbg!(&hlpr_index_compressed);
-//         dbg!(&hpnt_index_size);
-//         dbg!(hpnt_index.len()); // dbg!(&hpnt_index);
-//                                 // dbg!(&hdmy_index);
-//                                 // dbg!(&material_count);
-//                                 // dbg!(&surface_count);
-//                                 // dbg!(&bone_count);
-//                                 // dbg!(&bone_index_size);
-//                                 // dbg!(&unknown3);
-//                                 // dbg!(&unknown4);
-//                                 // dbg!(&unknown5);
-//                                 // dbg!(&compressed);
-
-//         hex_table::HexTable::default()
-//             .format(&input[..256], &mut std::io::stdout())
-//             .unwrap();
-//         dbg!(input.len());
-//         println!("");
-
-//         Ok((
-//             input,
-//             BbmHeader {
-//                 name,
-//                 has_skeleton,
-//                 model_origin,
-//                 hpnt_count,
-//                 hdmy_count,
-//                 hlpr_index_uncompressed,
-//                 padding,
-//                 hpnt_compressed,
-//                 helper_points,
-//                 hdmy_compressed,
-//                 helper_dummies,
-//                 // hlpr_index_compressed,
-//                 hpnt_index_size,
-//                 hpnt_index,
-//                 // hdmy_index,
-//                 // material_count,
-//                 // surface_count,
-//                 // bone_count,
-//                 // bone_index_size,
-//                 // unknown3,
-//                 // unknown4,
-//                 // unknown5,
-//                 // compressed,
-//                 // bone_index_reference,
-//                 // bone_index_compressed,
-//                 // bone_index,
-//                 // compressed_size,
-//             },
-//         ))
-//     }
-
-//     pub fn decode_helper_point(input: &[u8]) -> IResult<&[u8], BbmHelperPoint, Error> {
-//         let (input, matrix) = count(le_f32, 4usize)(input)?;
-//         let (input, hierarchy) = le_i32(input)?;
-
-//         Ok((
-//             input,
-//             BbmHelperPoint {
-//                 matrix: matrix,
-//                 hierarchy: hierarchy,
-//             },
-//         ))
-//     }
-
-//     pub fn decode_helper_dummy(input: &[u8]) -> IResult<&[u8], BbmHelperDummy, Error> {
-//         let (input, matrix) = count(le_f32, 13usize)(input)?;
-//         let (input, hierarchy) = le_i32(input)?;
-
-//         Ok((
-//             input,
-//             BbmHelperDummy {
-//                 matrix: matrix,
-//                 hierarchy: hierarchy,
-//             },
-//         ))
-//     }
-// }
-
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-//     use crate::Entry;
-//     use std::env;
-//     use std::fs::File;
-//     use std::path::PathBuf;
-
-//     #[test]
-//     fn test_bbm_print_meshes() {
-//         let file_path = PathBuf::from(env::var("FABLE_DIR").expect("Missing FABLE_DIR"))
-//             .join("Data/graphics/graphics.big");
-
-//         let mut file = File::open(&file_path).unwrap();
-//         let big = crate::Big::decode(&mut file).unwrap();
-
-//         let entries: Vec<crate::big::BigFileEntry> = big
-//             .entries
-//             .entries
-//             .into_iter()
-//             .filter(|x| {
-//                 [
-//                     "MESH_CREATURE_NEW_CHICKEN_01",
-//                     "MESH_OBJECT_BARREL",
-//                     "MESH_OBJECT_BRAZIER_TORCH_LIT",
-//                     "MESH_OBJECT_STATUE_BEAR",
-//                     "MESH_SWORD_BLAST_07",
-//                     "MESH_HERO_WEAPON_SMALL",
-//                 ]
-//                 .contains(&x.symbol_name.as_str())
-//             })
-//             .collect();
-
-//         for entry in &entries {
-//             let mut reader = entry.to_sub_source(&mut file).unwrap();
-
-//             let bbm = Bbm::decode(&mut reader).unwrap();
-//         }
-//     }
-
-//     #[test]
-//     fn test_bbm_print_all_meshes() {
-//         let file_path = PathBuf::from(env::var("FABLE_DIR").expect("Missing FABLE_DIR"))
-//             .join("Data/graphics/graphics.big");
-
-//         let mut file = File::open(&file_path).unwrap();
-//         let big = crate::Big::decode(&mut file).unwrap();
-
-//         let mesh_entries: Vec<crate::BigFileEntry> = big
-//             .entries
-//             .entries
-//             .into_iter()
-//             .filter(|x| x.symbol_name.starts_with("MESH_"))
-//             .collect();
-
-//         for entry in mesh_entries {
-//             let mut barrel_reader = entry.to_sub_source(&mut file).unwrap();
-
-//             let bbm = Bbm::decode(&mut barrel_reader).unwrap();
-
-//             println!("");
-//             // println!("{:#?}", bbm);
-//         }
-//     }
-
-//     #[test]
-//     fn test_bbm_print_non_physics_meshes() {
-//         let file_path = PathBuf::from(env::var("FABLE_DIR").expect("Missing FABLE_DIR"))
-//             .join("Data/graphics/graphics.big");
-
-//         let mut file = File::open(&file_path).unwrap();
-//         let big = crate::Big::decode(&mut file).unwrap();
-
-//         let mesh_entries: Vec<crate::BigFileEntry> = big
-//             .entries
-//             .entries
-//             .into_iter()
-//             .filter(|x| x.symbol_name.starts_with("MESH_") && !x.symbol_name.ends_with("[PHYSICS]"))
-//             .collect();
-
-//         for entry in mesh_entries {
-//             let mut barrel_reader = entry.to_sub_source(&mut file).unwrap();
-
-//             let bbm = Bbm::decode(&mut barrel_reader).unwrap();
-
-//             println!("");
-//             // println!("{:#?}", bbm);
-//         }
-//     }
-
-//     #[test]
-//     fn test_bbm_print_20_random_meshes() {
-//         use rand::Rng;
-
-//         let file_path = PathBuf::from(env::var("FABLE_DIR").expect("Missing FABLE_DIR"))
-//             .join("Data/graphics/graphics.big");
+/// A bone, gathered from the bone index and the three bone sub chunks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BbmBone {
+    pub name: Vec<u8>,
+    pub index: i32,
+    /// Index of the parent bone, or -1 for a root.
+    pub parent: i32,
+    pub child_count: i32,
+    /// Transform relative to the parent, Direct3D style: the x, y and z axes then the translation
+    /// (sub chunk 1).
+    pub transform: [f32; 12],
+    /// Transform relative to the model (sub chunk 2).
+    pub world_transform: [f32; 12],
+    /// Inverse bind matrix, a Direct3D style 4x4 (sub chunk 3).
+    pub inverse_bind: [f32; 16],
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BbmMaterial {
+    pub index: u32,
+    pub name: Vec<u8>,
+    pub padding: u32,
+    /// Id of the texture in `textures.big`, or 0.
+    pub base_texture_id: u32,
+    pub bump_map_texture_id: u32,
+    pub reflection_texture_id: u32,
+    pub unknown1: u32,
+    pub max_texture_layers: u32,
+    pub glow_strength: u32,
+    pub unknown2: u8,
+    pub alpha_enabled: u8,
+    pub unknown3: u8,
+    pub degenerate_triangles: u16,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BbmSubMesh {
+    pub hierarchy: u32,
+    pub destroyable_mesh_levels: u32,
+    pub unknown1: [f32; 5],
+    pub source_vertex_count: u32,
+    pub unknown2: [u32; 3],
+    pub primitives: Vec<BbmPrimitive>,
+    pub bone_primitives: Vec<BbmBonePrimitive>,
+    /// Position offset (first 3) and scale (5th to 7th) of packed coordinates.
+    pub bounds: [f32; 8],
+    /// The raw `sVert` code.
+    pub vertex_layout: u32,
+    pub padding: u32,
+    pub vertices: Vec<BbmVertex>,
+    /// Triangle list indices into `vertices`.
+    pub indices: Vec<u16>,
+    pub cloths: Vec<BbmCloth>,
+}
+
+/// A range of indices drawn with one material.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BbmPrimitive {
+    pub material_index: u32,
+    pub repeating_mesh_reps: u32,
+    pub start_index: u32,
+    pub index_count: u32,
+}
+
+/// A range of indices whose triangles move with one bone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BbmBonePrimitive {
+    pub start_index: u32,
+    pub index_count: u32,
+    pub bone: u32,
+    pub unknown: u32,
+}
+
+/// A vertex decoded to floats, whatever its layout. Fields the layout lacks are 0.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BbmVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+    pub bone_indices: [u8; 4],
+    /// Bone weights out of 255.
+    pub bone_weights: [u8; 4],
+    pub unknown: [u32; 2],
+    pub mesh_level: u32,
+}
+
+/// A dynamic clothing chunk, kept undecoded so it's written back as it was read. See
+/// [`BbmCloth::decode`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BbmCloth {
+    pub id: u32,
+    pub material_id: u32,
+    pub data: Vec<u8>,
+}
+
+/// The decoded data of a [`BbmCloth`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BbmClothData {
+    pub unknown1: u32,
+    /// Constraints keeping pairs of particles apart.
+    pub distances: Vec<BbmClothDistance>,
+    pub unknown2: [f32; 3],
+    pub particles: Vec<BbmClothParticle>,
+    pub unknown3: u32,
+    pub wind_strength: f32,
+    pub enable_dragging: u8,
+    pub rotational_dragging: u8,
+    pub dragging_strength: f32,
+    pub enable_acceleration: u8,
+    pub acceleration_damping: f32,
+    /// Triangles of particles and unique vertices.
+    pub triangles: Vec<[u32; 3]>,
+    pub unknown4: u32,
+    /// Pairs of a particle or unique vertex and a texture coordinate.
+    pub texture_indices: Vec<[u32; 2]>,
+    pub vertices: Vec<[f32; 3]>,
+    pub texture_coordinates: Vec<[f32; 2]>,
+    /// The unique particles in `triangles`.
+    pub particle_mask: Vec<u32>,
+    /// The unique vertices in `triangles`.
+    pub vertex_mask: Vec<u32>,
+    pub padding: [u8; 9],
+    /// Vertex indices of each group, one per bone.
+    pub vertex_groups: Vec<Vec<u32>>,
+    /// Bytes after the vertex groups, if any.
+    pub rest: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BbmClothDistance {
+    pub particles: [u32; 2],
+    pub distance: f32,
+    pub unknown: [u32; 4],
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BbmClothParticle {
+    pub position: [f32; 3],
+    /// How free the particle is to move, where 0 is static.
+    pub alpha: f32,
+}
+
+/// Layout of a vertex, from the sub-mesh's `sVert` code.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BbmVertexLayout {
+    /// Code 20 without bones, 20 bytes: float position, packed normal, s11e4 UV.
+    Float,
+    /// Code 20 with bones, 28 bytes: float position, bones, packed normal, s11e4 UV.
+    FloatSkinned,
+    /// Code 22, 36 bytes: float position, bones, packed normal, s11e4 UV, 2 dwords.
+    FloatSkinnedExtra,
+    /// Code 4 without bones, 12 bytes: packed position, packed normal, s11e4 UV.
+    Packed,
+    /// Code 4 with bones, 20 bytes: packed position, bones, packed normal, s11e4 UV.
+    PackedSkinned,
+    /// Code 6, 28 bytes: packed position, bones, packed normal, s11e4 UV, 2 dwords.
+    PackedSkinnedExtra,
+    /// Code 4 with destroyable mesh levels, 36 bytes: float position, normal and UV and a
+    /// mesh level dword.
+    Full,
+}
+
+impl BbmVertexLayout {
+    pub fn new(code: u32, skinned: bool, mesh_levels: bool) -> Option<Self> {
+        use BbmVertexLayout::*;
+
+        match code {
+            20 if skinned => Some(FloatSkinned),
+            20 => Some(Float),
+            22 => Some(FloatSkinnedExtra),
+            6 => Some(PackedSkinnedExtra),
+            4 if skinned => Some(PackedSkinned),
+            4 if mesh_levels => Some(Full),
+            4 => Some(Packed),
+            _ => None,
+        }
+    }
+
+    pub fn byte_size(self) -> usize {
+        use BbmVertexLayout::*;
+
+        match self {
+            Packed => 12,
+            Float | PackedSkinned => 20,
+            FloatSkinned | PackedSkinnedExtra => 28,
+            FloatSkinnedExtra | Full => 36,
+        }
+    }
+
+    pub fn has_bones(self) -> bool {
+        use BbmVertexLayout::*;
+
+        matches!(
+            self,
+            FloatSkinned | FloatSkinnedExtra | PackedSkinned | PackedSkinnedExtra
+        )
+    }
+
+    /// The `sVert` code of the layout. Which layout a code means also depends on the model and
+    /// sub-mesh, see [`BbmVertexLayout::new`].
+    pub fn code(self) -> u32 {
+        use BbmVertexLayout::*;
+
+        match self {
+            Float | FloatSkinned => 20,
+            FloatSkinnedExtra => 22,
+            Packed | PackedSkinned | Full => 4,
+            PackedSkinnedExtra => 6,
+        }
+    }
+
+    fn has_float_position(self) -> bool {
+        use BbmVertexLayout::*;
+
+        matches!(self, Float | FloatSkinned | FloatSkinnedExtra | Full)
+    }
+
+    fn has_extra(self) -> bool {
+        matches!(
+            self,
+            BbmVertexLayout::FloatSkinnedExtra | BbmVertexLayout::PackedSkinnedExtra
+        )
+    }
+}
+
+/// Error reading a block that may be LZO compressed.
+#[derive(Debug)]
+pub enum BbmBlockError<E> {
+    CompressedSize(E),
+    Data(UnexpectedEnd),
+    Lzo(LzoError),
+    /// The block decompressed to a different size than expected.
+    Size {
+        expected: usize,
+        actual: usize,
+    },
+}
+
+#[derive(Debug)]
+pub enum BbmError<E> {
+    Name(UnexpectedEnd),
+    NameNul(UnexpectedEnd),
+    HasSkeleton(E),
+    Origin(E),
+    HelperPointCount(E),
+    HelperDummyCount(E),
+    HelperIndexSize(E),
+    Padding(E),
+    HelperPoints(BbmBlockError<E>),
+    HelperPoint(E),
+    HelperDummies(BbmBlockError<E>),
+    HelperDummy(E),
+    HelperIndex(BbmBlockError<E>),
+    HelperPointIndexSize(E),
+    HelperPointIndex(UnexpectedEnd),
+    MaterialCount(E),
+    SubMeshCount(E),
+    BoneCount(E),
+    BoneIndexSize(E),
+    Unknown1(E),
+    Unknown2(E),
+    Unknown3(E),
+    BoneIndexReference(BbmBlockError<E>),
+    BoneIndex(BbmBlockError<E>),
+    BoneSubChunk1(BbmBlockError<E>),
+    BoneSubChunk2(BbmBlockError<E>),
+    BoneSubChunk3(BbmBlockError<E>),
+    Bone(E),
+    Transform(E),
+    Material {
+        index: usize,
+        error: BbmMaterialError<E>,
+    },
+    SubMesh {
+        index: usize,
+        error: BbmSubMeshError<E>,
+    },
+}
+
+#[derive(Debug)]
+pub enum BbmMaterialError<E> {
+    Index(E),
+    Name(UnexpectedEnd),
+    NameNul(UnexpectedEnd),
+    Padding(E),
+    BaseTextureId(E),
+    BumpMapTextureId(E),
+    ReflectionTextureId(E),
+    Unknown1(E),
+    MaxTextureLayers(E),
+    GlowStrength(E),
+    Unknown2(E),
+    AlphaEnabled(E),
+    Unknown3(E),
+    DegenerateTriangles(E),
+}
+
+#[derive(Debug)]
+pub enum BbmSubMeshError<E> {
+    Hierarchy(E),
+    DestroyableMeshLevels(E),
+    Unknown1(E),
+    PrimitiveCount(E),
+    BonePrimitiveCount(E),
+    VertexCount(E),
+    FaceCount(E),
+    SourceVertexCount(E),
+    Unknown2(E),
+    Primitive(E),
+    BonePrimitive(E),
+    Bounds(E),
+    VertexLayout(E),
+    /// The `sVert` code is not a known layout.
+    UnknownVertexLayout(u32),
+    Padding(E),
+    Vertex {
+        index: usize,
+        error: E,
+    },
+    Indices(E),
+    ClothCount(E),
+    ClothId(E),
+    ClothMaterialId(E),
+    ClothSize(E),
+    ClothData(UnexpectedEnd),
+}
+
+#[derive(Debug)]
+pub enum BbmClothError<E> {
+    Unknown1(E),
+    DistancesSize(E),
+    /// The distances' size isn't a whole number of distances.
+    DistancesSizeRemainder(usize),
+    Distance(E),
+    Unknown2(E),
+    ParticleCount(E),
+    Particle(E),
+    ParticleAlpha(E),
+    Unknown3(E),
+    WindStrength(E),
+    Dragging(E),
+    Acceleration(E),
+    TriangleCount(E),
+    Triangle(E),
+    Unknown4(E),
+    TextureIndexCount(E),
+    TextureIndex(E),
+    VertexCount(E),
+    Vertex(E),
+    TextureCoordinateCount(E),
+    TextureCoordinate(E),
+    ParticleMaskCount(E),
+    ParticleMask(E),
+    VertexMaskCount(E),
+    VertexMask(E),
+    Padding(E),
+    VertexGroupCount(E),
+    VertexGroupSize(E),
+    VertexGroup(E),
+}
+
+#[derive(Debug)]
+pub enum BbmCompileError {
+    HelperPointCount(TryFromIntError),
+    HelperDummyCount(TryFromIntError),
+    HelperPointIndexSize(TryFromIntError),
+    /// `bone_index_reference` must hold one fewer entries than there are bones.
+    BoneIndexReferenceCount {
+        expected: usize,
+        actual: usize,
+    },
+    SubMesh {
+        index: usize,
+        error: BbmSubMeshCompileError,
+    },
+}
+
+#[derive(Debug)]
+pub enum BbmSubMeshCompileError {
+    /// The `sVert` code is not a known layout.
+    UnknownVertexLayout(u32),
+    /// The indices don't make whole triangles.
+    IndexCount(usize),
+    /// A cloth chunk is larger than a dword can say.
+    ClothSize(TryFromIntError),
+}
+
+impl Bbm {
+    pub fn parse(inp: &mut &[u8]) -> Result<Bbm, BbmError<TakeError>> {
+        use BbmError::*;
+
+        let name = take_bytes_nul_terminated(inp).map_err(Name)?.to_vec();
+        take_bytes(inp, 1).map_err(NameNul)?;
+
+        let has_skeleton = take::<u8>(inp).map_err(HasSkeleton)?;
+        let origin = take::<[f32; 10]>(inp).map_err(Origin)?;
+
+        let helper_point_count = take::<u16>(inp).map_err(HelperPointCount)?.to_le() as usize;
+        let helper_dummy_count = take::<u16>(inp).map_err(HelperDummyCount)?.to_le() as usize;
+        let helper_index_size = take::<u32>(inp).map_err(HelperIndexSize)?.to_le() as usize;
+        let padding = take::<u16>(inp).map_err(Padding)?.to_le();
+
+        let block = take_block(inp, helper_point_count * 20).map_err(HelperPoints)?;
+        let mut block_inp = &block[..];
+        let mut helper_points = Vec::with_capacity(helper_point_count);
+
+        for _ in 0..helper_point_count {
+            let matrix = take::<[f32; 4]>(&mut block_inp).map_err(HelperPoint)?;
+            let hierarchy = take::<i32>(&mut block_inp).map_err(HelperPoint)?.to_le();
+            helper_points.push(BbmHelperPoint { matrix, hierarchy });
+        }
+
+        let block = take_block(inp, helper_dummy_count * 56).map_err(HelperDummies)?;
+        let mut block_inp = &block[..];
+        let mut helper_dummies = Vec::with_capacity(helper_dummy_count);
+
+        for _ in 0..helper_dummy_count {
+            let matrix = take::<[f32; 13]>(&mut block_inp).map_err(HelperDummy)?;
+            let hierarchy = take::<i32>(&mut block_inp).map_err(HelperDummy)?.to_le();
+            helper_dummies.push(BbmHelperDummy { matrix, hierarchy });
+        }
+
+        let block = take_block(inp, helper_index_size).map_err(HelperIndex)?;
+        let mut block_inp = &block[..];
+
+        let (helper_point_names, helper_dummy_names) = if helper_index_size == 0 {
+            (Vec::new(), Vec::new())
+        } else {
+            let helper_point_index_size = take::<u16>(&mut block_inp)
+                .map_err(HelperPointIndexSize)?
+                .to_le() as usize;
+
+            let helper_point_index =
+                take_bytes(&mut block_inp, helper_point_index_size.saturating_sub(2))
+                    .map_err(HelperPointIndex)?;
+
+            (split_names(helper_point_index), split_names(block_inp))
+        };
+
+        let material_count = take::<u32>(inp).map_err(MaterialCount)?.to_le() as usize;
+        let sub_mesh_count = take::<u32>(inp).map_err(SubMeshCount)?.to_le() as usize;
+        let bone_count = take::<u32>(inp).map_err(BoneCount)?.to_le() as usize;
+        let bone_index_size = take::<u32>(inp).map_err(BoneIndexSize)?.to_le() as usize;
+        let unknown1 = take::<u8>(inp).map_err(Unknown1)?;
+        let unknown2 = take::<u16>(inp).map_err(Unknown2)?.to_le();
+        let unknown3 = take::<u16>(inp).map_err(Unknown3)?.to_le();
+
+        let reference_count = bone_count.saturating_sub(1);
+        let block = take_block(inp, reference_count * 2).map_err(BoneIndexReference)?;
+        let bone_index_reference = block
+            .chunks_exact(2)
+            .map(|x| u16::from_le_bytes([x[0], x[1]]))
+            .collect();
+
+        let block = take_block(inp, bone_index_size).map_err(BoneIndex)?;
+        let mut bone_names = split_names(&block).into_iter();
+
+        let sub_chunk_1 = take_block(inp, bone_count * 60).map_err(BoneSubChunk1)?;
+        let sub_chunk_2 = take_block(inp, bone_count * 48).map_err(BoneSubChunk2)?;
+        let sub_chunk_3 = take_block(inp, bone_count * 64).map_err(BoneSubChunk3)?;
+
+        let mut sub_chunk_1 = &sub_chunk_1[..];
+        let mut sub_chunk_2 = &sub_chunk_2[..];
+        let mut sub_chunk_3 = &sub_chunk_3[..];
+
+        let mut bones = Vec::with_capacity(bone_count);
+
+        for _ in 0..bone_count {
+            bones.push(BbmBone {
+                name: bone_names.next().unwrap_or_default(),
+                index: take::<i32>(&mut sub_chunk_1).map_err(Bone)?.to_le(),
+                parent: take::<i32>(&mut sub_chunk_1).map_err(Bone)?.to_le(),
+                child_count: take::<i32>(&mut sub_chunk_1).map_err(Bone)?.to_le(),
+                transform: take::<[f32; 12]>(&mut sub_chunk_1).map_err(Bone)?,
+                world_transform: take::<[f32; 12]>(&mut sub_chunk_2).map_err(Bone)?,
+                inverse_bind: take::<[f32; 16]>(&mut sub_chunk_3).map_err(Bone)?,
+            });
+        }
+
+        let transform = take::<[f32; 12]>(inp).map_err(Transform)?;
+
+        let mut materials = Vec::with_capacity(material_count.min(inp.len()));
+
+        for index in 0..material_count {
+            let material = BbmMaterial::parse(inp).map_err(|error| Material { index, error })?;
+            materials.push(material);
+        }
+
+        let mut sub_meshes = Vec::with_capacity(sub_mesh_count.min(inp.len()));
+
+        for index in 0..sub_mesh_count {
+            let sub_mesh =
+                BbmSubMesh::parse(inp, bone_count > 0).map_err(|error| SubMesh { index, error })?;
+            sub_meshes.push(sub_mesh);
+        }
+
+        Ok(Bbm {
+            name,
+            has_skeleton,
+            origin,
+            padding,
+            helper_points,
+            helper_dummies,
+            helper_point_names,
+            helper_dummy_names,
+            unknown1,
+            unknown2,
+            unknown3,
+            bone_index_reference,
+            bones,
+            transform,
+            materials,
+            sub_meshes,
+        })
+    }
+
+    /// Splits a mesh entry's payload into its levels of detail, largest first, by the sizes in its
+    /// sub-header. Each level is a whole compiled model.
+    pub fn lods<'a>(header: &BigSubHeaderMesh, payload: &'a [u8]) -> Option<Vec<&'a [u8]>> {
+        let mut inp = payload;
+
+        header
+            .size_compressed_lod
+            .iter()
+            .map(|&size| take_bytes(&mut inp, size as usize).ok())
+            .collect()
+    }
+
+    /// Writes the model in the compiled format, which [`Bbm::parse`] reads back.
+    pub fn compile(&self) -> Result<Vec<u8>, BbmCompileError> {
+        use BbmCompileError::*;
+
+        let mut out = Vec::new();
+
+        out.extend_from_slice(&self.name);
+        out.push(0);
+        out.push(self.has_skeleton);
+        push(&mut out, &self.origin);
+
+        let helper_point_count =
+            u16::try_from(self.helper_points.len()).map_err(HelperPointCount)?;
+        let helper_dummy_count =
+            u16::try_from(self.helper_dummies.len()).map_err(HelperDummyCount)?;
+
+        let helper_point_index = join_names(&self.helper_point_names);
+        let helper_dummy_index = join_names(&self.helper_dummy_names);
+
+        // The helper index starts with the size of its helper point part, counting itself.
+        let helper_index = if helper_point_index.is_empty() && helper_dummy_index.is_empty() {
+            Vec::new()
+        } else {
+            let helper_point_index_size =
+                u16::try_from(helper_point_index.len() + 2).map_err(HelperPointIndexSize)?;
+
+            let mut helper_index = helper_point_index_size.to_le_bytes().to_vec();
+            helper_index.extend(helper_point_index);
+            helper_index.extend(helper_dummy_index);
+            helper_index
+        };
+
+        push(&mut out, &helper_point_count.to_le());
+        push(&mut out, &helper_dummy_count.to_le());
+        push(&mut out, &(helper_index.len() as u32).to_le());
+        push(&mut out, &self.padding.to_le());
+
+        let mut block = Vec::with_capacity(self.helper_points.len() * 20);
+
+        for helper in &self.helper_points {
+            push(&mut block, &helper.matrix);
+            push(&mut block, &helper.hierarchy.to_le());
+        }
+
+        put_block(&mut out, &block);
+
+        let mut block = Vec::with_capacity(self.helper_dummies.len() * 56);
+
+        for helper in &self.helper_dummies {
+            push(&mut block, &helper.matrix);
+            push(&mut block, &helper.hierarchy.to_le());
+        }
+
+        put_block(&mut out, &block);
+        put_block(&mut out, &helper_index);
+
+        let bone_count = self.bones.len();
+        let reference_count = bone_count.saturating_sub(1);
+
+        if self.bone_index_reference.len() != reference_count {
+            return Err(BoneIndexReferenceCount {
+                expected: reference_count,
+                actual: self.bone_index_reference.len(),
+            });
+        }
+
+        let bone_names = self
+            .bones
+            .iter()
+            .map(|x| x.name.clone())
+            .collect::<Vec<_>>();
+        let bone_index = join_names(&bone_names);
+
+        push(&mut out, &(self.materials.len() as u32).to_le());
+        push(&mut out, &(self.sub_meshes.len() as u32).to_le());
+        push(&mut out, &(bone_count as u32).to_le());
+        push(&mut out, &(bone_index.len() as u32).to_le());
+        out.push(self.unknown1);
+        push(&mut out, &self.unknown2.to_le());
+        push(&mut out, &self.unknown3.to_le());
+
+        let mut block = Vec::with_capacity(reference_count * 2);
+
+        for reference in &self.bone_index_reference {
+            push(&mut block, &reference.to_le());
+        }
+
+        put_block(&mut out, &block);
+        put_block(&mut out, &bone_index);
+
+        let mut sub_chunk_1 = Vec::with_capacity(bone_count * 60);
+        let mut sub_chunk_2 = Vec::with_capacity(bone_count * 48);
+        let mut sub_chunk_3 = Vec::with_capacity(bone_count * 64);
+
+        for bone in &self.bones {
+            push(&mut sub_chunk_1, &bone.index.to_le());
+            push(&mut sub_chunk_1, &bone.parent.to_le());
+            push(&mut sub_chunk_1, &bone.child_count.to_le());
+            push(&mut sub_chunk_1, &bone.transform);
+            push(&mut sub_chunk_2, &bone.world_transform);
+            push(&mut sub_chunk_3, &bone.inverse_bind);
+        }
+
+        put_block(&mut out, &sub_chunk_1);
+        put_block(&mut out, &sub_chunk_2);
+        put_block(&mut out, &sub_chunk_3);
+
+        push(&mut out, &self.transform);
+
+        for material in &self.materials {
+            material.compile(&mut out);
+        }
+
+        for (index, sub_mesh) in self.sub_meshes.iter().enumerate() {
+            sub_mesh
+                .compile(&mut out, bone_count > 0)
+                .map_err(|error| SubMesh { index, error })?;
+        }
+
+        Ok(out)
+    }
+
+    /// The sub-header of a mesh entry whose levels of detail, largest first, are this model and
+    /// others with the given compiled sizes.
+    ///
+    /// The texture ids are the model's, and the fields whose meaning is unknown are 0.
+    pub fn sub_header(&self, size_compressed_lod: Vec<u32>) -> BigSubHeaderMesh {
+        let mut texture_ids = Vec::new();
+
+        for material in &self.materials {
+            for id in [
+                material.base_texture_id,
+                material.bump_map_texture_id,
+                material.reflection_texture_id,
+            ] {
+                if id != 0 && !texture_ids.contains(&id) {
+                    texture_ids.push(id);
+                }
+            }
+        }
+
+        BigSubHeaderMesh {
+            physics_mesh: 0,
+            unknown1: self.origin,
+            unknown2: vec![0; size_compressed_lod.len().saturating_sub(1)],
+            size_compressed_lod,
+            padding: 0,
+            texture_ids,
+        }
+    }
+}
+
+impl BbmMaterial {
+    pub fn parse(inp: &mut &[u8]) -> Result<BbmMaterial, BbmMaterialError<TakeError>> {
+        use BbmMaterialError::*;
+
+        let index = take::<u32>(inp).map_err(Index)?.to_le();
+        let name = take_bytes_nul_terminated(inp).map_err(Name)?.to_vec();
+        take_bytes(inp, 1).map_err(NameNul)?;
+
+        Ok(BbmMaterial {
+            index,
+            name,
+            padding: take::<u32>(inp).map_err(Padding)?.to_le(),
+            base_texture_id: take::<u32>(inp).map_err(BaseTextureId)?.to_le(),
+            bump_map_texture_id: take::<u32>(inp).map_err(BumpMapTextureId)?.to_le(),
+            reflection_texture_id: take::<u32>(inp).map_err(ReflectionTextureId)?.to_le(),
+            unknown1: take::<u32>(inp).map_err(Unknown1)?.to_le(),
+            max_texture_layers: take::<u32>(inp).map_err(MaxTextureLayers)?.to_le(),
+            glow_strength: take::<u32>(inp).map_err(GlowStrength)?.to_le(),
+            unknown2: take::<u8>(inp).map_err(Unknown2)?,
+            alpha_enabled: take::<u8>(inp).map_err(AlphaEnabled)?,
+            unknown3: take::<u8>(inp).map_err(Unknown3)?,
+            degenerate_triangles: take::<u16>(inp).map_err(DegenerateTriangles)?.to_le(),
+        })
+    }
+
+    pub fn compile(&self, out: &mut Vec<u8>) {
+        push(out, &self.index.to_le());
+        out.extend_from_slice(&self.name);
+        out.push(0);
+        push(out, &self.padding.to_le());
+        push(out, &self.base_texture_id.to_le());
+        push(out, &self.bump_map_texture_id.to_le());
+        push(out, &self.reflection_texture_id.to_le());
+        push(out, &self.unknown1.to_le());
+        push(out, &self.max_texture_layers.to_le());
+        push(out, &self.glow_strength.to_le());
+        out.push(self.unknown2);
+        out.push(self.alpha_enabled);
+        out.push(self.unknown3);
+        push(out, &self.degenerate_triangles.to_le());
+    }
+}
+
+impl BbmSubMesh {
+    /// Parses a sub-mesh. `skinned` is whether the model has bones, which decides some vertex
+    /// layouts.
+    pub fn parse(inp: &mut &[u8], skinned: bool) -> Result<BbmSubMesh, BbmSubMeshError<TakeError>> {
+        use BbmSubMeshError::*;
+
+        let hierarchy = take::<u32>(inp).map_err(Hierarchy)?.to_le();
+        let destroyable_mesh_levels = take::<u32>(inp).map_err(DestroyableMeshLevels)?.to_le();
+        let unknown1 = take::<[f32; 5]>(inp).map_err(Unknown1)?;
+        let primitive_count = take::<u32>(inp).map_err(PrimitiveCount)?.to_le() as usize;
+        let bone_primitive_count = take::<u32>(inp).map_err(BonePrimitiveCount)?.to_le() as usize;
+        let vertex_count = take::<u32>(inp).map_err(VertexCount)?.to_le() as usize;
+        let face_count = take::<u32>(inp).map_err(FaceCount)?.to_le() as usize;
+        let source_vertex_count = take::<u32>(inp).map_err(SourceVertexCount)?.to_le();
+        let unknown2 = take::<[u32; 3]>(inp).map_err(Unknown2)?.map(u32::to_le);
+
+        let mut primitives = Vec::with_capacity(primitive_count.min(inp.len()));
+
+        for _ in 0..primitive_count {
+            primitives.push(BbmPrimitive {
+                material_index: take::<u32>(inp).map_err(Primitive)?.to_le(),
+                repeating_mesh_reps: take::<u32>(inp).map_err(Primitive)?.to_le(),
+                start_index: take::<u32>(inp).map_err(Primitive)?.to_le(),
+                index_count: take::<u32>(inp).map_err(Primitive)?.to_le(),
+            });
+        }
+
+        let mut bone_primitives = Vec::with_capacity(bone_primitive_count.min(inp.len()));
+
+        for _ in 0..bone_primitive_count {
+            bone_primitives.push(BbmBonePrimitive {
+                start_index: take::<u32>(inp).map_err(BonePrimitive)?.to_le(),
+                index_count: take::<u32>(inp).map_err(BonePrimitive)?.to_le(),
+                bone: take::<u32>(inp).map_err(BonePrimitive)?.to_le(),
+                unknown: take::<u32>(inp).map_err(BonePrimitive)?.to_le(),
+            });
+        }
+
+        let bounds = take::<[f32; 8]>(inp).map_err(Bounds)?;
+        let vertex_layout = take::<u32>(inp).map_err(VertexLayout)?.to_le();
+        let padding = take::<u32>(inp).map_err(Padding)?.to_le();
+
+        let layout = BbmVertexLayout::new(vertex_layout, skinned, destroyable_mesh_levels > 0)
+            .ok_or(UnknownVertexLayout(vertex_layout))?;
+
+        let mut vertices = Vec::with_capacity(vertex_count.min(inp.len() / layout.byte_size()));
+
+        for index in 0..vertex_count {
+            let vertex =
+                BbmVertex::parse(inp, layout, &bounds).map_err(|error| Vertex { index, error })?;
+            vertices.push(vertex);
+        }
+
+        let mut indices = Vec::with_capacity((face_count * 3).min(inp.len() / 2));
+
+        for _ in 0..face_count * 3 {
+            indices.push(take::<u16>(inp).map_err(Indices)?.to_le());
+        }
+
+        let cloth_count = take::<u32>(inp).map_err(ClothCount)?.to_le() as usize;
+        let mut cloths = Vec::with_capacity(cloth_count.min(inp.len()));
+
+        for _ in 0..cloth_count {
+            let id = take::<u32>(inp).map_err(ClothId)?.to_le();
+            let material_id = take::<u32>(inp).map_err(ClothMaterialId)?.to_le();
+            let size = take::<u32>(inp).map_err(ClothSize)?.to_le() as usize;
+            let data = take_bytes(inp, size).map_err(ClothData)?.to_vec();
+
+            cloths.push(BbmCloth {
+                id,
+                material_id,
+                data,
+            });
+        }
+
+        Ok(BbmSubMesh {
+            hierarchy,
+            destroyable_mesh_levels,
+            unknown1,
+            source_vertex_count,
+            unknown2,
+            primitives,
+            bone_primitives,
+            bounds,
+            vertex_layout,
+            padding,
+            vertices,
+            indices,
+            cloths,
+        })
+    }
+
+    /// Writes the sub-mesh, with its vertices in the layout `vertex_layout` names. `skinned` is
+    /// whether the model has bones.
+    pub fn compile(&self, out: &mut Vec<u8>, skinned: bool) -> Result<(), BbmSubMeshCompileError> {
+        use BbmSubMeshCompileError::*;
+
+        let layout = self
+            .layout(skinned)
+            .ok_or(UnknownVertexLayout(self.vertex_layout))?;
+
+        if !self.indices.len().is_multiple_of(3) {
+            return Err(IndexCount(self.indices.len()));
+        }
+
+        push(out, &self.hierarchy.to_le());
+        push(out, &self.destroyable_mesh_levels.to_le());
+        push(out, &self.unknown1);
+        push(out, &(self.primitives.len() as u32).to_le());
+        push(out, &(self.bone_primitives.len() as u32).to_le());
+        push(out, &(self.vertices.len() as u32).to_le());
+        push(out, &((self.indices.len() / 3) as u32).to_le());
+        push(out, &self.source_vertex_count.to_le());
+        push(out, &self.unknown2.map(u32::to_le));
+
+        for primitive in &self.primitives {
+            push(out, &primitive.material_index.to_le());
+            push(out, &primitive.repeating_mesh_reps.to_le());
+            push(out, &primitive.start_index.to_le());
+            push(out, &primitive.index_count.to_le());
+        }
+
+        for primitive in &self.bone_primitives {
+            push(out, &primitive.start_index.to_le());
+            push(out, &primitive.index_count.to_le());
+            push(out, &primitive.bone.to_le());
+            push(out, &primitive.unknown.to_le());
+        }
+
+        push(out, &self.bounds);
+        push(out, &self.vertex_layout.to_le());
+        push(out, &self.padding.to_le());
+
+        for vertex in &self.vertices {
+            vertex.compile(out, layout, &self.bounds);
+        }
+
+        for index in &self.indices {
+            push(out, &index.to_le());
+        }
+
+        push(out, &(self.cloths.len() as u32).to_le());
+
+        for cloth in &self.cloths {
+            let size = u32::try_from(cloth.data.len()).map_err(ClothSize)?;
+
+            push(out, &cloth.id.to_le());
+            push(out, &cloth.material_id.to_le());
+            push(out, &size.to_le());
+            out.extend_from_slice(&cloth.data);
+        }
+
+        Ok(())
+    }
+
+    /// The layout of `vertices` in the file. `skinned` is whether the model has bones.
+    pub fn layout(&self, skinned: bool) -> Option<BbmVertexLayout> {
+        BbmVertexLayout::new(
+            self.vertex_layout,
+            skinned,
+            self.destroyable_mesh_levels > 0,
+        )
+    }
+
+    /// Makes `layout` the layout of `vertices` in the file, fitting `bounds` to the vertices when
+    /// it packs coordinates.
+    ///
+    /// [`BbmVertexLayout::Full`] is told apart by destroyable mesh levels, so those are made at
+    /// least 1 for it and 0 for [`BbmVertexLayout::Packed`].
+    pub fn set_layout(&mut self, layout: BbmVertexLayout) {
+        self.vertex_layout = layout.code();
+
+        match layout {
+            BbmVertexLayout::Full => {
+                self.destroyable_mesh_levels = self.destroyable_mesh_levels.max(1);
+            }
+            BbmVertexLayout::Packed => {
+                self.destroyable_mesh_levels = 0;
+            }
+            _ => {}
+        }
+
+        if layout.has_float_position() {
+            return;
+        }
+
+        let (min, max) = self.vertices.iter().fold(
+            ([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]),
+            |(min, max), vertex| {
+                (
+                    [0, 1, 2].map(|i| min[i].min(vertex.position[i])),
+                    [0, 1, 2].map(|i| max[i].max(vertex.position[i])),
+                )
+            },
+        );
+
+        if self.vertices.is_empty() {
+            self.bounds = [0.0; 8];
+            return;
+        }
+
+        let [x, y, z] = [0, 1, 2].map(|i| (min[i] + max[i]) / 2.0);
+        let [sx, sy, sz] = [0, 1, 2].map(|i| (max[i] - min[i]) / 2.0);
+
+        self.bounds = [x, y, z, 0.0, sx, sy, sz, 0.0];
+    }
+}
+
+impl BbmVertex {
+    /// Parses a vertex, unpacking packed coordinates with the sub-mesh's `bounds`.
+    pub fn parse(
+        inp: &mut &[u8],
+        layout: BbmVertexLayout,
+        bounds: &[f32; 8],
+    ) -> Result<BbmVertex, TakeError> {
+        let position = if layout.has_float_position() {
+            take::<[f32; 3]>(inp)?
+        } else {
+            let packed = unpack_11_11_10(take::<u32>(inp)?.to_le());
+            [0, 1, 2].map(|i| bounds[i] + packed[i] * bounds[4 + i])
+        };
+
+        let mut vertex = BbmVertex {
+            position,
+            ..Default::default()
+        };
+
+        if layout == BbmVertexLayout::Full {
+            vertex.normal = take::<[f32; 3]>(inp)?;
+            vertex.uv = take::<[f32; 2]>(inp)?;
+            vertex.mesh_level = take::<u32>(inp)?.to_le();
+            return Ok(vertex);
+        }
+
+        if layout.has_bones() {
+            vertex.bone_indices = take::<[u8; 4]>(inp)?;
+            vertex.bone_weights = take::<[u8; 4]>(inp)?;
+        }
+
+        vertex.normal = unpack_11_11_10(take::<u32>(inp)?.to_le());
+        vertex.uv = take::<[u16; 2]>(inp)?.map(|x| s11e4_to_f32(u16::from_le(x)));
+
+        if layout.has_extra() {
+            vertex.unknown = take::<[u32; 2]>(inp)?.map(u32::to_le);
+        }
+
+        Ok(vertex)
+    }
+
+    /// Writes the vertex in `layout`, packing coordinates into the sub-mesh's `bounds`. What the
+    /// layout has no room for is dropped.
+    pub fn compile(&self, out: &mut Vec<u8>, layout: BbmVertexLayout, bounds: &[f32; 8]) {
+        if layout.has_float_position() {
+            push(out, &self.position);
+        } else {
+            let packed = [0, 1, 2].map(|i| {
+                let scale = bounds[4 + i];
+
+                if scale == 0.0 {
+                    0.0
+                } else {
+                    (self.position[i] - bounds[i]) / scale
+                }
+            });
+
+            push(out, &pack_11_11_10(packed).to_le());
+        }
+
+        if layout == BbmVertexLayout::Full {
+            push(out, &self.normal);
+            push(out, &self.uv);
+            push(out, &self.mesh_level.to_le());
+            return;
+        }
+
+        if layout.has_bones() {
+            push(out, &self.bone_indices);
+            push(out, &self.bone_weights);
+        }
+
+        push(out, &pack_11_11_10(self.normal).to_le());
+        push(out, &self.uv.map(|x| f32_to_s11e4(x).to_le()));
+
+        if layout.has_extra() {
+            push(out, &self.unknown.map(u32::to_le));
+        }
+    }
+}
+
+impl BbmCloth {
+    /// Decodes the cloth's data. The element sizes are guesses, see [`Bbm`].
+    pub fn decode(&self) -> Result<BbmClothData, BbmClothError<TakeError>> {
+        BbmClothData::parse(&mut &self.data[..])
+    }
+}
+
+impl BbmClothData {
+    pub fn parse(inp: &mut &[u8]) -> Result<BbmClothData, BbmClothError<TakeError>> {
+        use BbmClothError::*;
+
+        let unknown1 = take::<u32>(inp).map_err(Unknown1)?.to_le();
+
+        let distances_size = take::<u32>(inp).map_err(DistancesSize)?.to_le() as usize;
+
+        if !distances_size.is_multiple_of(28) {
+            return Err(DistancesSizeRemainder(distances_size));
+        }
+
+        let mut distances = Vec::with_capacity((distances_size / 28).min(inp.len()));
+
+        for _ in 0..distances_size / 28 {
+            distances.push(BbmClothDistance {
+                particles: take::<[u32; 2]>(inp).map_err(Distance)?.map(u32::to_le),
+                distance: take::<f32>(inp).map_err(Distance)?,
+                unknown: take::<[u32; 4]>(inp).map_err(Distance)?.map(u32::to_le),
+            });
+        }
+
+        let unknown2 = take::<[f32; 3]>(inp).map_err(Unknown2)?;
+
+        let particle_count = take::<u32>(inp).map_err(ParticleCount)?.to_le() as usize;
+        let mut positions = Vec::with_capacity(particle_count.min(inp.len()));
+
+        for _ in 0..particle_count {
+            positions.push(take::<[f32; 3]>(inp).map_err(Particle)?);
+        }
+
+        let mut particles = Vec::with_capacity(positions.len());
+
+        for position in positions {
+            let alpha = take::<f32>(inp).map_err(ParticleAlpha)?;
+            particles.push(BbmClothParticle { position, alpha });
+        }
+
+        let unknown3 = take::<u32>(inp).map_err(Unknown3)?.to_le();
+        let wind_strength = take::<f32>(inp).map_err(WindStrength)?;
+        let enable_dragging = take::<u8>(inp).map_err(Dragging)?;
+        let rotational_dragging = take::<u8>(inp).map_err(Dragging)?;
+        let dragging_strength = take::<f32>(inp).map_err(Dragging)?;
+        let enable_acceleration = take::<u8>(inp).map_err(Acceleration)?;
+        let acceleration_damping = take::<f32>(inp).map_err(Acceleration)?;
+
+        let triangles = take_dwords(inp, TriangleCount, Triangle)?;
+        let unknown4 = take::<u32>(inp).map_err(Unknown4)?.to_le();
+        let texture_indices = take_dwords(inp, TextureIndexCount, TextureIndex)?;
+        let vertices = take_floats(inp, VertexCount, Vertex)?;
+        let texture_coordinates = take_floats(inp, TextureCoordinateCount, TextureCoordinate)?;
+        let particle_mask = take_dwords::<1, _>(inp, ParticleMaskCount, ParticleMask)?;
+        let vertex_mask = take_dwords::<1, _>(inp, VertexMaskCount, VertexMask)?;
+        let padding = take::<[u8; 9]>(inp).map_err(Padding)?;
+
+        let vertex_group_count = take::<u32>(inp).map_err(VertexGroupCount)?.to_le() as usize;
+        let mut vertex_groups = Vec::with_capacity(vertex_group_count.min(inp.len()));
+
+        for _ in 0..vertex_group_count {
+            let group = take_dwords::<1, _>(inp, VertexGroupSize, VertexGroup)?;
+            vertex_groups.push(group.into_iter().map(|[x]| x).collect());
+        }
+
+        Ok(BbmClothData {
+            unknown1,
+            distances,
+            unknown2,
+            particles,
+            unknown3,
+            wind_strength,
+            enable_dragging,
+            rotational_dragging,
+            dragging_strength,
+            enable_acceleration,
+            acceleration_damping,
+            triangles,
+            unknown4,
+            texture_indices,
+            vertices,
+            texture_coordinates,
+            particle_mask: particle_mask.into_iter().map(|[x]| x).collect(),
+            vertex_mask: vertex_mask.into_iter().map(|[x]| x).collect(),
+            padding,
+            vertex_groups,
+            rest: std::mem::take(inp).to_vec(),
+        })
+    }
+
+    /// Writes the data [`BbmClothData::parse`] reads, to be kept in [`BbmCloth::data`].
+    pub fn compile(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        push(&mut out, &self.unknown1.to_le());
+        push(&mut out, &((self.distances.len() * 28) as u32).to_le());
+
+        for distance in &self.distances {
+            push(&mut out, &distance.particles.map(u32::to_le));
+            push(&mut out, &distance.distance);
+            push(&mut out, &distance.unknown.map(u32::to_le));
+        }
+
+        push(&mut out, &self.unknown2);
+        push(&mut out, &(self.particles.len() as u32).to_le());
+
+        for particle in &self.particles {
+            push(&mut out, &particle.position);
+        }
+
+        for particle in &self.particles {
+            push(&mut out, &particle.alpha);
+        }
+
+        push(&mut out, &self.unknown3.to_le());
+        push(&mut out, &self.wind_strength);
+        out.push(self.enable_dragging);
+        out.push(self.rotational_dragging);
+        push(&mut out, &self.dragging_strength);
+        out.push(self.enable_acceleration);
+        push(&mut out, &self.acceleration_damping);
+
+        push_dwords(&mut out, &self.triangles);
+        push(&mut out, &self.unknown4.to_le());
+        push_dwords(&mut out, &self.texture_indices);
+        push_floats(&mut out, &self.vertices);
+        push_floats(&mut out, &self.texture_coordinates);
+        push_dwords(
+            &mut out,
+            &self.particle_mask.iter().map(|&x| [x]).collect::<Vec<_>>(),
+        );
+        push_dwords(
+            &mut out,
+            &self.vertex_mask.iter().map(|&x| [x]).collect::<Vec<_>>(),
+        );
+        push(&mut out, &self.padding);
+
+        push(&mut out, &(self.vertex_groups.len() as u32).to_le());
+
+        for group in &self.vertex_groups {
+            push_dwords(&mut out, &group.iter().map(|&x| [x]).collect::<Vec<_>>());
+        }
+
+        out.extend_from_slice(&self.rest);
+
+        out
+    }
+}
+
+/// Takes a dword count, then that many groups of `N` dwords.
+fn take_dwords<const N: usize, E>(
+    inp: &mut &[u8],
+    count_error: impl Fn(TakeError) -> E,
+    error: impl Fn(TakeError) -> E,
+) -> Result<Vec<[u32; N]>, E> {
+    let count = take::<u32>(inp).map_err(count_error)?.to_le() as usize;
+    let mut out = Vec::with_capacity(count.min(inp.len()));
 
-//         let mut file = File::open(&file_path).unwrap();
-//         let big = crate::Big::decode(&mut file).unwrap();
+    for _ in 0..count {
+        let mut value = [0; N];
 
-//         let mesh_entries: Vec<crate::BigFileEntry> = big
-//             .entries
-//             .entries
-//             .into_iter()
-//             .filter(|x| x.symbol_name.starts_with("MESH_"))
-//             .collect();
+        for x in &mut value {
+            *x = take::<u32>(inp).map_err(&error)?.to_le();
+        }
 
-//         let mut rng = rand::thread_rng();
+        out.push(value);
+    }
 
-//         let mut rand_mesh_entries: Vec<&crate::BigFileEntry> = Vec::new();
+    Ok(out)
+}
+
+/// Takes a dword count, then that many groups of `N` floats.
+fn take_floats<const N: usize, E>(
+    inp: &mut &[u8],
+    count_error: impl Fn(TakeError) -> E,
+    error: impl Fn(TakeError) -> E,
+) -> Result<Vec<[f32; N]>, E> {
+    let count = take::<u32>(inp).map_err(count_error)?.to_le() as usize;
+    let mut out = Vec::with_capacity(count.min(inp.len()));
 
-//         while rand_mesh_entries.len() != 20 {
-//             let rand_mesh_idx = rng.gen_range(0, mesh_entries.len());
-//             let rand_mesh_entry = &mesh_entries[rand_mesh_idx];
+    for _ in 0..count {
+        let mut value = [0.0; N];
 
-//             if !rand_mesh_entries.as_slice().contains(&rand_mesh_entry) {
-//                 rand_mesh_entries.push(rand_mesh_entry);
-//             }
-//         }
+        for x in &mut value {
+            *x = take::<f32>(inp).map_err(&error)?;
+        }
 
-//         for entry in rand_mesh_entries {
-//             let mut reader = entry.to_sub_source(&mut file).unwrap();
+        out.push(value);
+    }
 
-//             dbg!(entry.id);
-//             dbg!(&entry.symbol_name);
-//             let bbm = Bbm::decode(&mut reader).unwrap();
+    Ok(out)
+}
 
-//             println!("");
-//             // println!("{:#?}", bbm);
-//         }
-//     }
+fn push_dwords<const N: usize>(out: &mut Vec<u8>, values: &[[u32; N]]) {
+    push(out, &(values.len() as u32).to_le());
+
+    for x in values.iter().flatten() {
+        push(out, &x.to_le());
+    }
+}
 
-//     #[test]
-//     fn test_bbm_print_20_random_non_physics_meshes() {
-//         use rand::Rng;
+fn push_floats<const N: usize>(out: &mut Vec<u8>, values: &[[f32; N]]) {
+    push(out, &(values.len() as u32).to_le());
 
-//         let file_path = PathBuf::from(env::var("FABLE_DIR").expect("Missing FABLE_DIR"))
-//             .join("Data/graphics/graphics.big");
+    for x in values.iter().flatten() {
+        push(out, x);
+    }
+}
 
-//         let mut file = File::open(&file_path).unwrap();
-//         let big = crate::Big::decode(&mut file).unwrap();
+/// Takes a block of `size` bytes, preceded by a word with its LZO compressed size or 0.
+fn take_block<'a>(
+    inp: &mut &'a [u8],
+    size: usize,
+) -> Result<Cow<'a, [u8]>, BbmBlockError<TakeError>> {
+    use BbmBlockError::*;
 
-//         let mesh_entries: Vec<crate::BigFileEntry> = big
-//             .entries
-//             .entries
-//             .into_iter()
-//             .filter(|x| x.symbol_name.starts_with("MESH_") && !x.symbol_name.ends_with("[PHYSICS]"))
-//             .collect();
+    let compressed_size = take::<u16>(inp).map_err(CompressedSize)?.to_le() as usize;
 
-//         let mut rng = rand::thread_rng();
+    if compressed_size == 0 {
+        return Ok(Cow::Borrowed(take_bytes(inp, size).map_err(Data)?));
+    }
 
-//         let mut rand_mesh_entries: Vec<&crate::BigFileEntry> = Vec::new();
+    let compressed = take_bytes(inp, compressed_size).map_err(Data)?;
+    let block = lzo::decompress(compressed).map_err(Lzo)?;
 
-//         while rand_mesh_entries.len() != 20 {
-//             let rand_mesh_idx = rng.gen_range(0, mesh_entries.len());
-//             let rand_mesh_entry = &mesh_entries[rand_mesh_idx];
+    if block.len() != size {
+        return Err(Size {
+            expected: size,
+            actual: block.len(),
+        });
+    }
 
-//             if !rand_mesh_entries.as_slice().contains(&rand_mesh_entry) {
-//                 rand_mesh_entries.push(rand_mesh_entry);
-//             }
-//         }
+    Ok(Cow::Owned(block))
+}
 
-//         for entry in rand_mesh_entries {
-//             let mut reader = entry.to_sub_source(&mut file).unwrap();
+/// Puts a block, preceded by its LZO compressed size, or by 0 when compressing doesn't help.
+fn put_block(out: &mut Vec<u8>, block: &[u8]) {
+    let compressed = lzo::compress(block);
+
+    match u16::try_from(compressed.len()) {
+        Ok(compressed_size) if compressed.len() < block.len() => {
+            push(out, &compressed_size.to_le());
+            out.extend_from_slice(&compressed);
+        }
+        _ => {
+            push(out, &0u16);
+            out.extend_from_slice(block);
+        }
+    }
+}
 
-//             dbg!(entry.id);
-//             dbg!(&entry.symbol_name);
-//             let bbm = Bbm::decode(&mut reader).unwrap();
+/// Joins names into an index of NUL terminated names.
+fn join_names(names: &[Vec<u8>]) -> Vec<u8> {
+    let mut index = Vec::new();
 
-//             println!("");
-//             // println!("{:#?}", bbm);
-//         }
-//     }
+    for name in names {
+        index.extend_from_slice(name);
+        index.push(0);
+    }
 
-//     // NOTE: This entry ends in [PHYSICS] and breaks the parser. In fact all entries ending in [PHYSICS] seem to break the parser. Look into more thoroughly.
-//     #[test]
-//     fn test_bbm_print_physics_mesh() {
-//         let file_path = PathBuf::from(env::var("FABLE_DIR").expect("Missing FABLE_DIR"))
-//             .join("Data/graphics/graphics.big");
+    index
+}
 
-//         let mut file = File::open(&file_path).unwrap();
-//         let big = crate::Big::decode(&mut file).unwrap();
+/// Splits an index of NUL terminated names.
+fn split_names(index: &[u8]) -> Vec<Vec<u8>> {
+    let mut names: Vec<Vec<u8>> = index.split(|&x| x == 0).map(<[u8]>::to_vec).collect();
 
-//         let entry = big.entries.entries.get(306).unwrap(); // MESH_OBJECT_STEPS_SMALL_DOUBLE_01
+    // The last name's NUL leaves an empty piece behind.
+    if names.last().is_some_and(Vec::is_empty) {
+        names.pop();
+    }
 
-//         println!("{:#?}", entry);
+    names
+}
 
-//         let mut reader = entry.to_sub_source(&mut file).unwrap();
+/// Unpacks a normal packed as signed 11, 11 and 10 bit x, y and z, each in -1 to 1.
+fn unpack_11_11_10(packed: u32) -> [f32; 3] {
+    let x = ((packed << 21) as i32 >> 21) as f32 / 1023.0;
+    let y = ((packed << 10) as i32 >> 21) as f32 / 1023.0;
+    let z = (packed as i32 >> 22) as f32 / 511.0;
+    [x, y, z]
+}
 
-//         let bbm = Bbm::decode(&mut reader).unwrap();
+/// Packs a vector, each in -1 to 1, as signed 11, 11 and 10 bit x, y and z.
+fn pack_11_11_10(vector: [f32; 3]) -> u32 {
+    let pack =
+        |x: f32, max: f32, mask: u32| ((x.clamp(-1.0, 1.0) * max).round() as i32) as u32 & mask;
 
-//         println!("{:#?}", bbm);
+    pack(vector[0], 1023.0, 0x7ff)
+        | pack(vector[1], 1023.0, 0x7ff) << 11
+        | pack(vector[2], 511.0, 0x3ff) << 22
+}
 
-//         let entry = big.entries.entries.get(305).unwrap(); // MESH_OBJECT_STEPS_SMALL_DOUBLE_01[PHYSICS]
+/// Converts a float packed as a signed 12 bit mantissa in the top bits and a 4 bit exponent in the
+/// low bits, `mantissa * 2^(exponent - 11)`.
+fn s11e4_to_f32(packed: u16) -> f32 {
+    let mantissa = (packed as i16) >> 4;
+    let exponent = (packed & 0xf) as i32;
+    mantissa as f32 * 2f32.powi(exponent - 11)
+}
 
-//         println!("{:#?}", entry);
+/// Packs a float as s11e4, with the smallest exponent its value fits, for the most precision.
+/// Values past the largest exponent are clamped.
+fn f32_to_s11e4(x: f32) -> u16 {
+    for exponent in 0..16 {
+        let mantissa = (x * 2f32.powi(11 - exponent)).round();
 
-//         let mut reader = entry.to_sub_source(&mut file).unwrap();
+        if (-2048.0..=2047.0).contains(&mantissa) {
+            return ((mantissa as i16 as u16) << 4) | exponent as u16;
+        }
+    }
 
-//         let bbm = Bbm::decode(&mut reader).unwrap();
+    let mantissa = (x * 2f32.powi(11 - 15)).round().clamp(-2048.0, 2047.0);
+    ((mantissa as i16 as u16) << 4) | 15
+}
 
-//         println!("{:#?}", bbm);
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn floats(out: &mut Vec<u8>, floats: &[f32]) {
+        for x in floats {
+            out.extend(x.to_le_bytes());
+        }
+    }
+
+    fn dwords(out: &mut Vec<u8>, dwords: &[u32]) {
+        for x in dwords {
+            out.extend(x.to_le_bytes());
+        }
+    }
+
+    fn block(out: &mut Vec<u8>, block: &[u8], compress: bool) {
+        if compress {
+            let compressed = lzo::compress(block);
+            out.extend((compressed.len() as u16).to_le_bytes());
+            out.extend(compressed);
+        } else {
+            out.extend(0u16.to_le_bytes());
+            out.extend(block);
+        }
+    }
+
+    fn sub_mesh(out: &mut Vec<u8>, destroyable_mesh_levels: u32, layout: u32, vertices: &[u8]) {
+        dwords(out, &[0, destroyable_mesh_levels]);
+        floats(out, &[0.0; 5]);
+        // Primitives, bone primitives, vertices, faces, source vertices and unknowns.
+        dwords(out, &[1, 1, 3, 1, 3, 0, 0, 0]);
+        dwords(out, &[0, 1, 0, 3]);
+        dwords(out, &[0, 3, 0, 0]);
+        floats(out, &[1.0, 2.0, 3.0, 0.0, 2.0, 2.0, 2.0, 0.0]);
+        dwords(out, &[layout, 0]);
+        out.extend(vertices);
+        for index in [0u16, 1, 2] {
+            out.extend(index.to_le_bytes());
+        }
+        dwords(out, &[0]);
+    }
+
+    /// A model with a bone, a helper point, a material and a float and a packed sub-mesh.
+    fn test_bbm() -> Vec<u8> {
+        let mut bbm = Vec::new();
+
+        bbm.extend(b"MESH_TEST\0");
+        bbm.push(1);
+        floats(&mut bbm, &[0.0; 10]);
+        bbm.extend(1u16.to_le_bytes());
+        bbm.extend(0u16.to_le_bytes());
+        // Helper index: its own size word, then "HPNT_A\0".
+        bbm.extend(9u32.to_le_bytes());
+        bbm.extend(0u16.to_le_bytes());
+
+        let mut helper_points = Vec::new();
+        floats(&mut helper_points, &[1.0, 2.0, 3.0, 4.0]);
+        helper_points.extend((-1i32).to_le_bytes());
+        block(&mut bbm, &helper_points, false);
+        block(&mut bbm, &[], false);
+        block(&mut bbm, b"\x09\x00HPNT_A\0", false);
+
+        // Materials, sub-meshes, bones, bone index size and unknowns.
+        dwords(&mut bbm, &[1, 2, 1, 5]);
+        bbm.extend([0, 0, 0, 0, 0]);
+
+        block(&mut bbm, &[], false);
+        block(&mut bbm, b"ROOT\0", false);
+
+        let mut sub_chunk_1 = Vec::new();
+        dwords(&mut sub_chunk_1, &[0, u32::MAX, 0]);
+        floats(&mut sub_chunk_1, &[1.0; 12]);
+        block(&mut bbm, &sub_chunk_1, true);
+        block(&mut bbm, &[0; 48], true);
+        block(&mut bbm, &[0; 64], false);
+        floats(&mut bbm, &[0.0; 12]);
+
+        dwords(&mut bbm, &[0]);
+        bbm.extend(b"MAT\0");
+        dwords(&mut bbm, &[0, 7, 0, 0, 0, 1, 0]);
+        bbm.extend([0, 1, 0, 0, 0]);
+
+        // Float skinned vertices, with a normal of +x and a UV of (0.5, 1.0).
+        let mut vertices = Vec::new();
+        for i in 0..3 {
+            floats(&mut vertices, &[i as f32, 0.0, 0.0]);
+            vertices.extend([0, 0, 0, 0, 255, 0, 0, 0]);
+            dwords(&mut vertices, &[1023]);
+            vertices.extend((1024u16 << 4).to_le_bytes());
+            vertices.extend(((1024u16 << 4) | 1).to_le_bytes());
+        }
+        sub_mesh(&mut bbm, 0, 20, &vertices);
+
+        // Packed skinned vertices, at the far corner of the bounds.
+        let mut vertices = Vec::new();
+        for _ in 0..3 {
+            dwords(&mut vertices, &[1023 | (1023 << 11) | (511 << 22)]);
+            vertices.extend([0, 0, 0, 0, 255, 0, 0, 0]);
+            dwords(&mut vertices, &[511 << 22]);
+            vertices.extend([0; 4]);
+        }
+        sub_mesh(&mut bbm, 0, 4, &vertices);
+
+        bbm
+    }
+
+    #[test]
+    fn parse_bbm() {
+        let bbm = test_bbm();
+        let mut inp = &bbm[..];
+        let bbm = Bbm::parse(&mut inp).unwrap();
+
+        assert!(inp.is_empty());
+        assert_eq!(bbm.name, b"MESH_TEST");
+        assert_eq!(bbm.helper_points[0].matrix, [1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(bbm.helper_point_names, [b"HPNT_A".to_vec()]);
+        assert!(bbm.helper_dummy_names.is_empty());
+
+        assert_eq!(bbm.bones.len(), 1);
+        assert_eq!(bbm.bones[0].name, b"ROOT");
+        assert_eq!(bbm.bones[0].parent, -1);
+        assert_eq!(bbm.bones[0].transform, [1.0; 12]);
+
+        assert_eq!(bbm.materials[0].name, b"MAT");
+        assert_eq!(bbm.materials[0].base_texture_id, 7);
+        assert_eq!(bbm.materials[0].alpha_enabled, 1);
+
+        let float = &bbm.sub_meshes[0];
+        assert_eq!(float.layout(true), Some(BbmVertexLayout::FloatSkinned));
+        assert_eq!(float.vertices[2].position, [2.0, 0.0, 0.0]);
+        assert_eq!(float.vertices[2].normal, [1.0, 0.0, 0.0]);
+        assert_eq!(float.vertices[2].uv, [0.5, 1.0]);
+        assert_eq!(float.vertices[2].bone_weights, [255, 0, 0, 0]);
+        assert_eq!(float.indices, [0, 1, 2]);
+        assert_eq!(float.primitives[0].index_count, 3);
+
+        let packed = &bbm.sub_meshes[1];
+        assert_eq!(packed.layout(true), Some(BbmVertexLayout::PackedSkinned));
+        assert_eq!(packed.vertices[0].position, [3.0, 4.0, 5.0]);
+        assert_eq!(packed.vertices[0].normal, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn compile_round_trip() {
+        let bbm = Bbm::parse(&mut &test_bbm()[..]).unwrap();
+        let compiled = bbm.compile().unwrap();
+
+        assert_eq!(Bbm::parse(&mut &compiled[..]).unwrap(), bbm);
+
+        let mut packed = bbm.clone();
+        packed.sub_meshes[0].set_layout(BbmVertexLayout::PackedSkinnedExtra);
+
+        assert_eq!(
+            packed.sub_meshes[0].bounds,
+            [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0]
+        );
+
+        let compiled = packed.compile().unwrap();
+        let reparsed = Bbm::parse(&mut &compiled[..]).unwrap();
+
+        assert_eq!(
+            reparsed.sub_meshes[0].layout(true),
+            Some(BbmVertexLayout::PackedSkinnedExtra)
+        );
+        assert_eq!(
+            reparsed.sub_meshes[0].vertices,
+            packed.sub_meshes[0].vertices
+        );
+
+        let mut broken = bbm;
+        broken.sub_meshes[1].indices.pop();
+
+        assert!(matches!(
+            broken.compile(),
+            Err(BbmCompileError::SubMesh {
+                index: 1,
+                error: BbmSubMeshCompileError::IndexCount(2)
+            })
+        ));
+    }
+
+    #[test]
+    fn decode_cloth() {
+        let mut data = Vec::new();
+        dwords(&mut data, &[7, 28, 0, 1]);
+        floats(&mut data, &[0.5]);
+        dwords(&mut data, &[0; 4]);
+        floats(&mut data, &[0.0; 3]);
+        // Two particles, then their alphas.
+        dwords(&mut data, &[2]);
+        floats(&mut data, &[0.0, 0.0, 0.0, 0.0, -0.5, 0.0, 0.0, 1.0]);
+        dwords(&mut data, &[0]);
+        floats(&mut data, &[2.0]);
+        data.extend([1, 0]);
+        floats(&mut data, &[0.25]);
+        data.push(1);
+        floats(&mut data, &[0.75]);
+        // A triangle, no texture indices, a vertex, no texture coordinates and the masks.
+        dwords(&mut data, &[1, 0, 1, 2, 0, 0, 1]);
+        floats(&mut data, &[1.0, 2.0, 3.0]);
+        dwords(&mut data, &[0, 2, 0, 1, 1, 2]);
+        data.extend([0; 9]);
+        // One vertex group of one vertex.
+        dwords(&mut data, &[1, 1, 2]);
+
+        let cloth = BbmCloth {
+            id: 1,
+            material_id: 0,
+            data,
+        };
+
+        let decoded = cloth.decode().unwrap();
+
+        assert_eq!(decoded.unknown1, 7);
+        assert_eq!(decoded.distances[0].particles, [0, 1]);
+        assert_eq!(decoded.distances[0].distance, 0.5);
+        assert_eq!(decoded.particles[1].position, [0.0, -0.5, 0.0]);
+        assert_eq!(decoded.particles[1].alpha, 1.0);
+        assert_eq!(decoded.wind_strength, 2.0);
+        assert_eq!(decoded.acceleration_damping, 0.75);
+        assert_eq!(decoded.triangles, [[0, 1, 2]]);
+        assert_eq!(decoded.vertices, [[1.0, 2.0, 3.0]]);
+        assert_eq!(decoded.particle_mask, [0, 1]);
+        assert_eq!(decoded.vertex_mask, [2]);
+        assert_eq!(decoded.vertex_groups, [vec![2]]);
+        assert!(decoded.rest.is_empty());
+
+        assert_eq!(decoded.compile(), cloth.data);
+    }
+
+    #[test]
+    fn vertex_layouts() {
+        use BbmVertexLayout::*;
+
+        assert_eq!(BbmVertexLayout::new(20, false, false), Some(Float));
+        assert_eq!(BbmVertexLayout::new(20, true, false), Some(FloatSkinned));
+        assert_eq!(
+            BbmVertexLayout::new(6, true, false),
+            Some(PackedSkinnedExtra)
+        );
+        assert_eq!(BbmVertexLayout::new(4, false, false), Some(Packed));
+        assert_eq!(BbmVertexLayout::new(4, true, false), Some(PackedSkinned));
+        assert_eq!(BbmVertexLayout::new(4, false, true), Some(Full));
+        assert_eq!(BbmVertexLayout::new(5, false, false), None);
+
+        assert_eq!(s11e4_to_f32((-1024i16 as u16) << 4 | 1), -1.0);
+        assert_eq!(f32_to_s11e4(-1.0), (-2048i16 as u16) << 4);
+        assert_eq!(f32_to_s11e4(1.0), 1024 << 4 | 1);
+        assert_eq!(f32_to_s11e4(0.5), 1024 << 4);
+
+        for x in [0.0, 0.25, -3.75, 100.0, 2047.0] {
+            assert_eq!(s11e4_to_f32(f32_to_s11e4(x)), x);
+        }
+
+        assert_eq!(
+            pack_11_11_10([1.0, -1.0, 1.0]),
+            1023 | (1025 << 11) | (511 << 22)
+        );
+        assert_eq!(unpack_11_11_10(1 << 10), [-1024.0 / 1023.0, 0.0, 0.0]);
+    }
+}
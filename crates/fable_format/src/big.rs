@@ -1,10 +1,20 @@
-use std::num::TryFromIntError;
+use std::{
+    borrow::Cow,
+    io::{self, Read, Seek, SeekFrom, Write},
+    mem,
+    num::TryFromIntError,
+};
+
+use serde::{Deserialize, Serialize};
 
-use crate::common::bytes::{
-    put, put_bytes, take, take_bytes, take_bytes_nul_terminated, TakeError, UnexpectedEnd,
+use crate::common::{
+    byte_str,
+    bytes::{
+        put, put_bytes, take, take_bytes, take_bytes_nul_terminated, TakeError, UnexpectedEnd,
+    },
 };
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BigHeader {
     pub magic: [u8; 4],
     pub version: u32,
@@ -21,6 +31,12 @@ pub enum BigHeaderError<E> {
 }
 
 impl BigHeader {
+    pub const MAGIC: [u8; 4] = *b"BIGB";
+    pub const VERSION: u32 = 100;
+
+    /// Where `bank_address` starts in the header.
+    pub const BANK_ADDRESS_OFFSET: usize = mem::size_of::<[u8; 4]>() + mem::size_of::<u32>();
+
     pub fn parse(inp: &mut &[u8]) -> Result<Self, BigHeaderError<TakeError>> {
         use BigHeaderError::*;
 
@@ -48,14 +64,21 @@ impl BigHeader {
         Ok(())
     }
 
-    pub fn byte_size(&self) -> usize {
-        16
+    /// Computes the minimum size of an output buffer needed for serialization.
+    pub const fn byte_size() -> usize {
+        // Magic
+        mem::size_of::<[u8; 4]>() +
+        // Version
+        mem::size_of::<u32>() +
+        // Bank address
+        mem::size_of::<u32>() +
+        // Unknown 1
+        mem::size_of::<u32>()
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct BigBankIndex<'a> {
-    pub banks_count: u32,
     // Null-terminated string
     pub name: &'a [u8],
     pub bank_id: u32,
@@ -65,9 +88,21 @@ pub struct BigBankIndex<'a> {
     pub block_size: u32,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BigBankIndexOwned {
+    #[serde(with = "byte_str")]
+    pub name: Vec<u8>,
+    pub bank_id: u32,
+    pub bank_entries_count: u32,
+    pub index_start: u32,
+    pub index_size: u32,
+    pub block_size: u32,
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum BigBankIndexError<E> {
     BanksCount(E),
+    BanksCountInt(TryFromIntError),
     Name(UnexpectedEnd),
     BankId(E),
     BankEntriesCount(E),
@@ -77,11 +112,27 @@ pub enum BigBankIndexError<E> {
 }
 
 impl<'a> BigBankIndex<'a> {
-    pub fn parse(inp: &mut &'a [u8]) -> Result<Self, BigBankIndexError<TakeError>> {
+    /// Parses the list of banks found at the header's `bank_address`.
+    pub fn parse_all(inp: &mut &'a [u8]) -> Result<Vec<Self>, BigBankIndexError<TakeError>> {
         use BigBankIndexError::*;
 
         let banks_count = take::<u32>(inp).map_err(BanksCount)?.to_le();
+        let banks_count = usize::try_from(banks_count).map_err(BanksCountInt)?;
+
+        let mut banks = Vec::with_capacity(banks_count.min(inp.len()));
+
+        for _ in 0..banks_count {
+            banks.push(Self::parse(inp)?);
+        }
+
+        Ok(banks)
+    }
+
+    pub fn parse(inp: &mut &'a [u8]) -> Result<Self, BigBankIndexError<TakeError>> {
+        use BigBankIndexError::*;
+
         let name = take_bytes_nul_terminated(inp).map_err(Name)?;
+        take_bytes(inp, 1).map_err(Name)?;
         let bank_id = take::<u32>(inp).map_err(BankId)?.to_le();
         let bank_entries_count = take::<u32>(inp).map_err(BankEntriesCount)?.to_le();
         let index_start = take::<u32>(inp).map_err(IndexStart)?.to_le();
@@ -89,7 +140,6 @@ impl<'a> BigBankIndex<'a> {
         let block_size = take::<u32>(inp).map_err(BlockSize)?.to_le();
 
         Ok(BigBankIndex {
-            banks_count,
             name,
             bank_id,
             bank_entries_count,
@@ -99,12 +149,27 @@ impl<'a> BigBankIndex<'a> {
         })
     }
 
-    pub fn serialize(&self, out: &mut &mut [u8]) -> Result<(), BigBankIndexError<UnexpectedEnd>> {
+    pub fn serialize_all(
+        banks: &[Self],
+        out: &mut &mut [u8],
+    ) -> Result<(), BigBankIndexError<UnexpectedEnd>> {
         use BigBankIndexError::*;
 
-        put(out, &self.banks_count.to_le()).map_err(BanksCount)?;
+        let banks_count = u32::try_from(banks.len()).map_err(BanksCountInt)?;
 
-        put_bytes(out, &self.name).map_err(Name)?;
+        put(out, &banks_count.to_le()).map_err(BanksCount)?;
+
+        for bank in banks {
+            bank.serialize(out)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn serialize(&self, out: &mut &mut [u8]) -> Result<(), BigBankIndexError<UnexpectedEnd>> {
+        use BigBankIndexError::*;
+
+        put_bytes(out, self.name).map_err(Name)?;
         put(out, b"\0").map_err(Name)?;
 
         put(out, &self.bank_id.to_le()).map_err(BankId)?;
@@ -116,18 +181,65 @@ impl<'a> BigBankIndex<'a> {
         Ok(())
     }
 
+    /// The size of the fields after the name and its terminator.
+    pub const AFTER_NAME_SIZE: usize = 5 * mem::size_of::<u32>();
+
+    pub fn byte_size_all(banks: &[Self]) -> usize {
+        // Banks count
+        mem::size_of::<u32>() + banks.iter().map(Self::byte_size).sum::<usize>()
+    }
+
     pub fn byte_size(&self) -> usize {
-        25 + self.name.len()
+        21 + self.name.len()
+    }
+
+    pub fn kind(&self) -> BigBankKind {
+        BigBankKind::from_bank_name(self.name)
+    }
+
+    pub fn to_owned(&self) -> BigBankIndexOwned {
+        BigBankIndexOwned {
+            name: self.name.to_vec(),
+            bank_id: self.bank_id,
+            bank_entries_count: self.bank_entries_count,
+            index_start: self.index_start,
+            index_size: self.index_size,
+            block_size: self.block_size,
+        }
+    }
+}
+
+impl BigBankIndexOwned {
+    pub fn kind(&self) -> BigBankKind {
+        BigBankKind::from_bank_name(&self.name)
+    }
+
+    pub fn as_bank_index(&self) -> BigBankIndex<'_> {
+        BigBankIndex {
+            name: &self.name,
+            bank_id: self.bank_id,
+            bank_entries_count: self.bank_entries_count,
+            index_start: self.index_start,
+            index_size: self.index_size,
+            block_size: self.block_size,
+        }
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct BigFileIndex<'a> {
     pub file_type: u32,
     pub types_map: Vec<[u32; 2]>,
     pub entries: Vec<BigFileEntry<'a>>,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BigFileIndexOwned {
+    pub file_type: u32,
+    pub types_map: Vec<[u32; 2]>,
+    pub entries: Vec<BigFileEntryOwned>,
+}
+
 #[derive(Debug)]
 pub enum BigFileIndexError<E> {
     TypesCount(E),
@@ -140,16 +252,21 @@ pub enum BigFileIndexError<E> {
 }
 
 impl<'a> BigFileIndex<'a> {
-    pub fn parse(inp: &mut &'a [u8]) -> Result<Self, BigFileIndexError<TakeError>> {
+    /// Parses a bank's file index. `kind` decides how the entries' sub-headers are read.
+    pub fn parse(
+        inp: &mut &'a [u8],
+        kind: BigBankKind,
+    ) -> Result<Self, BigFileIndexError<TakeError>> {
         use BigFileIndexError::*;
 
         let types_count = take::<u32>(inp).map_err(TypesCount)?.to_le();
         let file_type = take::<u32>(inp).map_err(FileType)?.to_le();
         let entries_count = take::<u32>(inp).map_err(EntriesCount)?.to_le();
 
-        let types_map_count = usize::try_from(types_count - 1).map_err(TypesCountInt)?;
+        let types_map_count =
+            usize::try_from(types_count.saturating_sub(1)).map_err(TypesCountInt)?;
 
-        let mut types_map = Vec::with_capacity(types_map_count);
+        let mut types_map = Vec::with_capacity(types_map_count.min(inp.len()));
 
         for _ in 0..types_map_count {
             let v1 = take::<u32>(inp).map_err(TypesMap)?.to_le();
@@ -157,10 +274,12 @@ impl<'a> BigFileIndex<'a> {
             types_map.push([v1, v2]);
         }
 
-        let mut entries = Vec::with_capacity(entries_count.try_into().unwrap());
+        let entries_count = usize::try_from(entries_count).map_err(EntriesCountInt)?;
+
+        let mut entries = Vec::with_capacity(entries_count.min(inp.len()));
 
         for _ in 0..entries_count {
-            let entry = BigFileEntry::parse(inp).map_err(BigFileIndexError::Entry)?;
+            let entry = BigFileEntry::parse(inp, kind).map_err(BigFileIndexError::Entry)?;
             entries.push(entry);
         }
 
@@ -171,6 +290,14 @@ impl<'a> BigFileIndex<'a> {
         })
     }
 
+    /// The file types the index declares: `file_type` followed by the first value of each
+    /// `types_map` pair.
+    pub fn file_types(&self, kind: BigBankKind) -> impl Iterator<Item = BigFileType> + '_ {
+        std::iter::once(self.file_type)
+            .chain(self.types_map.iter().map(|[file_type, _]| *file_type))
+            .map(move |file_type| BigFileType::new(kind, file_type))
+    }
+
     pub fn serialize(&self, out: &mut &mut [u8]) -> Result<(), BigFileIndexError<UnexpectedEnd>> {
         use BigFileIndexError::*;
 
@@ -184,8 +311,8 @@ impl<'a> BigFileIndex<'a> {
 
         put(out, &entries_count.to_le()).map_err(EntriesCount)?;
 
-        for entry in &self.types_map {
-            put(out, entry).map_err(TypesMap)?;
+        for types in &self.types_map {
+            put(out, &types.map(u32::to_le)).map_err(TypesMap)?;
         }
 
         for entries in &self.entries {
@@ -196,11 +323,42 @@ impl<'a> BigFileIndex<'a> {
     }
 
     pub fn byte_size(&self) -> usize {
-        todo!()
+        // Types count
+        mem::size_of::<u32>() +
+        // File type
+        mem::size_of::<u32>() +
+        // Entries count
+        mem::size_of::<u32>() +
+        // Types map
+        self.types_map.len() * mem::size_of::<[u32; 2]>() +
+        // Entries
+        self.entries.iter().map(BigFileEntry::byte_size).sum::<usize>()
+    }
+
+    pub fn to_owned(&self) -> BigFileIndexOwned {
+        BigFileIndexOwned {
+            file_type: self.file_type,
+            types_map: self.types_map.clone(),
+            entries: self.entries.iter().map(BigFileEntry::to_owned).collect(),
+        }
+    }
+}
+
+impl BigFileIndexOwned {
+    pub fn as_file_index(&self) -> BigFileIndex<'_> {
+        BigFileIndex {
+            file_type: self.file_type,
+            types_map: self.types_map.clone(),
+            entries: self
+                .entries
+                .iter()
+                .map(BigFileEntryOwned::as_entry)
+                .collect(),
+        }
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct BigFileEntry<'a> {
     pub magic: u32,
     pub id: u32,
@@ -211,8 +369,23 @@ pub struct BigFileEntry<'a> {
     pub symbol_name: &'a [u8],
     pub crc: u32,
     pub files: Vec<&'a [u8]>,
-    pub sub_header: BigSubHeader,
-    // pub sub_header: Vec<u8>,
+    pub sub_header: BigSubHeader<'a>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BigFileEntryOwned {
+    pub magic: u32,
+    pub id: u32,
+    pub file_type: u32,
+    pub size: u32,
+    pub start: u32,
+    pub file_type_dev: u32,
+    #[serde(with = "byte_str")]
+    pub symbol_name: Vec<u8>,
+    pub crc: u32,
+    #[serde(with = "byte_str::vec")]
+    pub files: Vec<Vec<u8>>,
+    pub sub_header: BigSubHeader<'static>,
 }
 
 #[derive(Debug)]
@@ -239,7 +412,10 @@ pub enum BigFileEntryError<E> {
 }
 
 impl<'a> BigFileEntry<'a> {
-    pub fn parse(inp: &mut &'a [u8]) -> Result<Self, BigFileEntryError<TakeError>> {
+    pub fn parse(
+        inp: &mut &'a [u8],
+        kind: BigBankKind,
+    ) -> Result<Self, BigFileEntryError<TakeError>> {
         use BigFileEntryError::*;
 
         let magic = take::<u32>(inp).map_err(Magic)?.to_le();
@@ -258,7 +434,7 @@ impl<'a> BigFileEntry<'a> {
         let files_count = take::<u32>(inp).map_err(FileNamesCount)?.to_le();
         let files_count = usize::try_from(files_count).map_err(FileNamesCountInt)?;
 
-        let mut files = Vec::with_capacity(files_count);
+        let mut files = Vec::with_capacity(files_count.min(inp.len()));
 
         for _ in 0..files_count {
             let name_len = take::<u32>(inp).map_err(FileNameLen)?;
@@ -272,7 +448,8 @@ impl<'a> BigFileEntry<'a> {
         let sub_header_len = usize::try_from(sub_header_len).map_err(SubHeaderLenInt)?;
         let mut sub_header_bytes = take_bytes(inp, sub_header_len).map_err(SubHeaderBytes)?;
         let sub_header =
-            BigSubHeader::parse(&mut sub_header_bytes, file_type).map_err(SubHeader)?;
+            BigSubHeader::parse(&mut sub_header_bytes, BigFileType::new(kind, file_type))
+                .map_err(SubHeader)?;
 
         Ok(Self {
             magic,
@@ -321,17 +498,236 @@ impl<'a> BigFileEntry<'a> {
     }
 
     pub fn byte_size(&self) -> usize {
-        todo!()
+        // Magic, id, file type, size, start and file type dev
+        6 * mem::size_of::<u32>() +
+        // Symbol name
+        mem::size_of::<u32>() + self.symbol_name.len() +
+        // Crc
+        mem::size_of::<u32>() +
+        // File names
+        mem::size_of::<u32>() +
+        self.files.iter().map(|name| mem::size_of::<u32>() + name.len()).sum::<usize>() +
+        // Sub header
+        mem::size_of::<u32>() + self.sub_header.byte_size()
+    }
+
+    pub fn file_type(&self, kind: BigBankKind) -> BigFileType {
+        BigFileType::new(kind, self.file_type)
+    }
+
+    pub fn file_type_dev(&self, kind: BigBankKind) -> BigFileTypeDev {
+        BigFileTypeDev::new(kind, self.file_type, self.file_type_dev)
+    }
+
+    pub fn to_owned(&self) -> BigFileEntryOwned {
+        BigFileEntryOwned {
+            magic: self.magic,
+            id: self.id,
+            file_type: self.file_type,
+            size: self.size,
+            start: self.start,
+            file_type_dev: self.file_type_dev,
+            symbol_name: self.symbol_name.to_vec(),
+            crc: self.crc,
+            files: self.files.iter().map(|name| name.to_vec()).collect(),
+            sub_header: self.sub_header.clone().into_owned(),
+        }
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub enum BigSubHeader {
+impl BigFileEntryOwned {
+    pub fn as_entry(&self) -> BigFileEntry<'_> {
+        BigFileEntry {
+            magic: self.magic,
+            id: self.id,
+            file_type: self.file_type,
+            size: self.size,
+            start: self.start,
+            file_type_dev: self.file_type_dev,
+            symbol_name: &self.symbol_name,
+            crc: self.crc,
+            files: self.files.iter().map(Vec::as_slice).collect(),
+            sub_header: self.sub_header.as_borrowed(),
+        }
+    }
+}
+
+/// What a bank holds, which decides how its file types are read.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BigBankKind {
+    /// Banks of `textures.big`, named `GBANK_*`.
+    Textures,
+    /// Banks of `graphics.big`, named `MBANK_*`, holding meshes and animations.
+    Graphics,
+    /// Banks of `text.big`, named `LANG_*`, holding the strings of one language.
+    Text,
+    /// Banks of `dialogue.big`, named `LIPSYNC_*`, holding the lip sync data of spoken lines.
+    Dialogue,
+    Other,
+}
+
+impl BigBankKind {
+    pub fn from_bank_name(name: &[u8]) -> Self {
+        if name.starts_with(b"GBANK") {
+            Self::Textures
+        } else if name.starts_with(b"MBANK") {
+            Self::Graphics
+        } else if name.starts_with(b"LANG_") {
+            Self::Text
+        } else if name.starts_with(b"LIPSYNC_") {
+            Self::Dialogue
+        } else {
+            Self::Other
+        }
+    }
+}
+
+/// The `file_type` of an entry, read according to the kind of bank it is in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BigFileType {
+    Texture,
+    StaticMesh,
+    StaticRepeatedMesh,
+    StaticPhysicsMesh,
+    AnimatedMesh,
+    Animation,
+    DeltaAnimation,
+    LipSyncAnimation,
+    PartialAnimation,
+    RelativeAnimation,
+    /// A string of a text bank.
+    Text,
+    /// A text bank entry listing the ids of related strings.
+    TextGroup,
+    /// The lip sync animation of one spoken line.
+    LipSync,
+    /// A type not known for the bank kind.
+    Unknown(u32),
+}
+
+impl BigFileType {
+    /// Reads `file_type` according to the bank kind.
+    ///
+    /// The text and dialogue types are taken from the notes on fabletlcmod.com and haven't been
+    /// checked against the archives like the texture and graphics types.
+    pub fn new(kind: BigBankKind, file_type: u32) -> Self {
+        match (kind, file_type) {
+            (BigBankKind::Textures, 0) => Self::Texture,
+            (BigBankKind::Graphics, 1) => Self::StaticMesh,
+            (BigBankKind::Graphics, 2) => Self::StaticRepeatedMesh,
+            (BigBankKind::Graphics, 4) => Self::StaticPhysicsMesh,
+            (BigBankKind::Graphics, 5) => Self::AnimatedMesh,
+            (BigBankKind::Graphics, 6) => Self::Animation,
+            (BigBankKind::Graphics, 7) => Self::DeltaAnimation,
+            (BigBankKind::Graphics, 8) => Self::LipSyncAnimation,
+            (BigBankKind::Graphics, 9) => Self::PartialAnimation,
+            (BigBankKind::Graphics, 10) => Self::RelativeAnimation,
+            (BigBankKind::Text, 0) => Self::Text,
+            (BigBankKind::Text, 1) => Self::TextGroup,
+            (BigBankKind::Dialogue, 0) => Self::LipSync,
+            (_, file_type) => Self::Unknown(file_type),
+        }
+    }
+
+    /// The number stored in `file_type`.
+    pub fn raw(self) -> u32 {
+        match self {
+            Self::Texture => 0,
+            Self::StaticMesh => 1,
+            Self::StaticRepeatedMesh => 2,
+            Self::StaticPhysicsMesh => 4,
+            Self::AnimatedMesh => 5,
+            Self::Animation => 6,
+            Self::DeltaAnimation => 7,
+            Self::LipSyncAnimation => 8,
+            Self::PartialAnimation => 9,
+            Self::RelativeAnimation => 10,
+            Self::Text => 0,
+            Self::TextGroup => 1,
+            Self::LipSync => 0,
+            Self::Unknown(file_type) => file_type,
+        }
+    }
+
+    pub fn is_mesh(self) -> bool {
+        matches!(
+            self,
+            Self::StaticMesh
+                | Self::StaticRepeatedMesh
+                | Self::StaticPhysicsMesh
+                | Self::AnimatedMesh
+        )
+    }
+
+    pub fn is_animation(self) -> bool {
+        matches!(
+            self,
+            Self::Animation
+                | Self::DeltaAnimation
+                | Self::LipSyncAnimation
+                | Self::PartialAnimation
+                | Self::RelativeAnimation
+        )
+    }
+}
+
+/// The `file_type_dev` of an entry, a finer type recorded next to `file_type`.
+///
+/// Only texture banks are known to use it, to tell how an image is drawn. Elsewhere it repeats
+/// `file_type`. The texture numbers come from the notes on fabletlcmod.com.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BigFileTypeDev {
+    /// `file_type_dev` equals `file_type`, outside texture banks.
+    FileType,
+    GraphicSingle,
+    GraphicSequence,
+    Bumpmap,
+    BumpmapSequence,
+    VolumeTexture,
+    FlatSequence,
+    /// A texture type that isn't known, or a value that differs from `file_type` outside texture
+    /// banks.
+    Unknown(u32),
+}
+
+impl BigFileTypeDev {
+    pub fn new(kind: BigBankKind, file_type: u32, file_type_dev: u32) -> Self {
+        match (kind, file_type_dev) {
+            (BigBankKind::Textures, 1) => Self::GraphicSingle,
+            (BigBankKind::Textures, 2) => Self::GraphicSequence,
+            (BigBankKind::Textures, 3) => Self::Bumpmap,
+            (BigBankKind::Textures, 4) => Self::BumpmapSequence,
+            (BigBankKind::Textures, 5) => Self::VolumeTexture,
+            (BigBankKind::Textures, 6) => Self::FlatSequence,
+            (BigBankKind::Textures, _) => Self::Unknown(file_type_dev),
+            (_, file_type_dev) if file_type_dev == file_type => Self::FileType,
+            (_, file_type_dev) => Self::Unknown(file_type_dev),
+        }
+    }
+
+    /// The number stored in `file_type_dev` of an entry with the given `file_type`.
+    pub fn raw(self, file_type: u32) -> u32 {
+        match self {
+            Self::FileType => file_type,
+            Self::GraphicSingle => 1,
+            Self::GraphicSequence => 2,
+            Self::Bumpmap => 3,
+            Self::BumpmapSequence => 4,
+            Self::VolumeTexture => 5,
+            Self::FlatSequence => 6,
+            Self::Unknown(file_type_dev) => file_type_dev,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BigSubHeader<'a> {
     None,
     Texture(BigSubHeaderTexture),
     Mesh(BigSubHeaderMesh),
     Animation(BigSubHeaderAnimation),
-    Unknown(Vec<u8>),
+    /// The bytes of a sub-header that isn't understood, borrowed from the index when parsed.
+    Unknown(Cow<'a, [u8]>),
 }
 
 #[derive(Debug)]
@@ -342,7 +738,7 @@ pub enum BigSubHeaderError<E> {
     Unknown(E),
 }
 
-impl BigSubHeader {
+impl<'a> BigSubHeader<'a> {
     pub fn byte_size(&self) -> usize {
         match self {
             Self::None => 0,
@@ -353,20 +749,44 @@ impl BigSubHeader {
         }
     }
 
-    pub fn parse(inp: &mut &[u8], file_type: u32) -> Result<Self, BigSubHeaderError<TakeError>> {
+    pub fn parse(
+        inp: &mut &'a [u8],
+        file_type: BigFileType,
+    ) -> Result<Self, BigSubHeaderError<TakeError>> {
         use BigSubHeaderError::*;
 
         Ok(match file_type {
-            0 => Self::Texture(BigSubHeaderTexture::parse(inp).map_err(Texture)?),
-            1 | 2 | 4 | 5 => Self::Mesh(BigSubHeaderMesh::parse(inp).map_err(Mesh)?),
-            // ? =>
-            //     decode_big_sub_header_anim(input),
-            // ? =>
-            //     Ok((b"", BigSubHeader::None)),
-            _ => Self::Unknown(inp.to_vec()),
+            BigFileType::Texture => {
+                Self::Texture(BigSubHeaderTexture::parse(inp).map_err(Texture)?)
+            }
+            file_type if file_type.is_mesh() => {
+                Self::Mesh(BigSubHeaderMesh::parse(inp).map_err(Mesh)?)
+            }
+            file_type if file_type.is_animation() => {
+                Self::Animation(BigSubHeaderAnimation::parse(inp).map_err(Animation)?)
+            }
+            _ => Self::Unknown(Cow::Borrowed(inp)),
         })
     }
 
+    pub fn into_owned(self) -> BigSubHeader<'static> {
+        match self {
+            Self::None => BigSubHeader::None,
+            Self::Texture(x) => BigSubHeader::Texture(x),
+            Self::Mesh(x) => BigSubHeader::Mesh(x),
+            Self::Animation(x) => BigSubHeader::Animation(x),
+            Self::Unknown(x) => BigSubHeader::Unknown(Cow::Owned(x.into_owned())),
+        }
+    }
+
+    /// The same sub-header with unknown bytes borrowed from this one.
+    pub fn as_borrowed(&self) -> BigSubHeader<'_> {
+        match self {
+            Self::Unknown(x) => BigSubHeader::Unknown(Cow::Borrowed(x)),
+            x => x.clone(),
+        }
+    }
+
     pub fn serialize(&self, out: &mut &mut [u8]) -> Result<(), BigSubHeaderError<UnexpectedEnd>> {
         use BigSubHeaderError::*;
 
@@ -388,7 +808,7 @@ impl BigSubHeader {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BigSubHeaderTexture {
     pub width: u16,
     pub height: u16,
@@ -468,17 +888,64 @@ impl BigSubHeaderTexture {
 
     pub fn serialize(
         &self,
-        _out: &mut &mut [u8],
+        out: &mut &mut [u8],
     ) -> Result<(), BigSubHeaderTextureError<UnexpectedEnd>> {
-        todo!()
+        use BigSubHeaderTextureError::*;
+
+        put(out, &self.width.to_le()).map_err(Width)?;
+        put(out, &self.height.to_le()).map_err(Height)?;
+        put(out, &self.depth.to_le()).map_err(Depth)?;
+        put(out, &self.frame_width.to_le()).map_err(FrameWidth)?;
+        put(out, &self.frame_height.to_le()).map_err(FrameHeight)?;
+        put(out, &self.frame_count.to_le()).map_err(FrameCount)?;
+        put(out, &self.dxt_compression.to_le()).map_err(DxtCompression)?;
+        put(out, &self.unknown1.to_le()).map_err(Unknown1)?;
+        put(out, &self.transparency.to_le()).map_err(Transparency)?;
+        put(out, &self.mip_maps.to_le()).map_err(MipMaps)?;
+        put(out, &self.unknown2.to_le()).map_err(Unknown2)?;
+        put(out, &self.top_mip_map_size.to_le()).map_err(TopMipMapSize)?;
+        put(out, &self.top_mip_map_compressed_size.to_le()).map_err(TopMipMapCompressedSize)?;
+        put(out, &self.unknown3.to_le()).map_err(Unknown3)?;
+        put(out, &self.unknown4.to_le()).map_err(Unknown4)?;
+
+        Ok(())
     }
 
     pub fn byte_size(&self) -> usize {
-        todo!()
+        // Width
+        mem::size_of::<u16>() +
+        // Height
+        mem::size_of::<u16>() +
+        // Depth
+        mem::size_of::<u16>() +
+        // Frame width
+        mem::size_of::<u16>() +
+        // Frame height
+        mem::size_of::<u16>() +
+        // Frame count
+        mem::size_of::<u16>() +
+        // Dxt compression
+        mem::size_of::<u16>() +
+        // Unknown 1
+        mem::size_of::<u16>() +
+        // Transparency
+        mem::size_of::<u8>() +
+        // Mip maps
+        mem::size_of::<u8>() +
+        // Unknown 2
+        mem::size_of::<u16>() +
+        // Top mip map size
+        mem::size_of::<u32>() +
+        // Top mip map compressed size
+        mem::size_of::<u32>() +
+        // Unknown 3
+        mem::size_of::<u16>() +
+        // Unknown 4
+        mem::size_of::<u32>()
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BigSubHeaderMesh {
     pub physics_mesh: u32,
     pub unknown1: [f32; 10],
@@ -514,7 +981,7 @@ impl BigSubHeaderMesh {
         let size_compressed_lod_count =
             usize::try_from(size_compressed_lod_count).map_err(SizeCompressedLodCountInt)?;
 
-        let mut size_compressed_lod = Vec::with_capacity(size_compressed_lod_count);
+        let mut size_compressed_lod = Vec::with_capacity(size_compressed_lod_count.min(i.len()));
 
         for _ in 0..size_compressed_lod_count {
             let unknown = take::<u32>(i).map_err(SizeCompressedLod)?.to_le();
@@ -523,8 +990,8 @@ impl BigSubHeaderMesh {
 
         let padding = take::<u32>(i).map_err(Padding)?.to_le();
 
-        let unknown2_count = size_compressed_lod_count - 1;
-        let mut unknown2 = Vec::with_capacity(unknown2_count);
+        let unknown2_count = size_compressed_lod_count.saturating_sub(1);
+        let mut unknown2 = Vec::with_capacity(unknown2_count.min(i.len()));
 
         for _ in 0..unknown2_count {
             let unknown = take::<u32>(i).map_err(Unknown2)?.to_le();
@@ -533,7 +1000,7 @@ impl BigSubHeaderMesh {
 
         let texture_ids_count = take::<u32>(i).map_err(TextureIdsCount)?.to_le();
         let texture_ids_count = usize::try_from(texture_ids_count).map_err(TextureIdsCountInt)?;
-        let mut texture_ids = Vec::with_capacity(texture_ids_count);
+        let mut texture_ids = Vec::with_capacity(texture_ids_count.min(i.len()));
 
         for _ in 0..texture_ids_count {
             let texture_id = take::<u32>(i).map_err(TextureIds)?.to_le();
@@ -589,11 +1056,22 @@ impl BigSubHeaderMesh {
     }
 
     pub fn byte_size(&self) -> usize {
-        todo!()
+        // Physics mesh
+        mem::size_of::<u32>() +
+        // Unknown 1
+        mem::size_of::<[f32; 10]>() +
+        // Size compressed lod
+        mem::size_of::<u32>() + self.size_compressed_lod.len() * mem::size_of::<u32>() +
+        // Padding
+        mem::size_of::<u32>() +
+        // Unknown 2
+        self.unknown2.len() * mem::size_of::<u32>() +
+        // Texture ids
+        mem::size_of::<u32>() + self.texture_ids.len() * mem::size_of::<u32>()
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BigSubHeaderAnimation {
     pub unknown1: f32,
     pub unknown2: f32,
@@ -609,7 +1087,7 @@ pub enum BigSubHeaderAnimationError<E> {
 
 impl BigSubHeaderAnimation {
     pub fn byte_size(&self) -> usize {
-        todo!()
+        2 * mem::size_of::<f32>() + self.unknown3.len()
     }
 
     pub fn parse(i: &mut &[u8]) -> Result<Self, BigSubHeaderAnimationError<TakeError>> {
@@ -639,3 +1117,412 @@ impl BigSubHeaderAnimation {
         Ok(())
     }
 }
+
+/// Settings for a bank started with [`BigWriter::add_bank`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BigBankMetadata {
+    #[serde(with = "byte_str")]
+    pub name: Vec<u8>,
+    pub bank_id: u32,
+    pub block_size: u32,
+    pub file_type: u32,
+    pub types_map: Vec<[u32; 2]>,
+    /// Where to place the bank's file index. The writer places it after the data when this is
+    /// `None`.
+    pub index_start: Option<u32>,
+}
+
+/// Writes a `.big` archive to a sink, laying out the entry data, every bank's file index and the
+/// bank list.
+///
+/// Entry data is written as soon as an entry is added. The indexes, bank list and header are
+/// written by [`BigWriter::finish`].
+pub struct BigWriter<W> {
+    sink: W,
+    header: BigHeader,
+    banks: Vec<(BigBankMetadata, Vec<BigFileEntryOwned>)>,
+    data_end: u64,
+}
+
+#[derive(Debug)]
+pub enum BigWriterError {
+    Io(io::Error),
+    NoBank,
+    Start(TryFromIntError),
+    Size(TryFromIntError),
+    IndexStart(TryFromIntError),
+    IndexSize(TryFromIntError),
+    BankEntriesCount(TryFromIntError),
+    BankAddress(TryFromIntError),
+    Index(BigFileIndexError<UnexpectedEnd>),
+    Banks(BigBankIndexError<UnexpectedEnd>),
+    Header(BigHeaderError<UnexpectedEnd>),
+}
+
+impl<W: Write + Seek> BigWriter<W> {
+    /// Creates a writer using the usual magic and version.
+    pub fn new(sink: W) -> Self {
+        Self::with_header(
+            sink,
+            BigHeader {
+                magic: BigHeader::MAGIC,
+                version: BigHeader::VERSION,
+                bank_address: 0,
+                unknown_1: 0,
+            },
+        )
+    }
+
+    /// Creates a writer using the magic, version and unknown field of `header`. The bank address
+    /// is computed.
+    pub fn with_header(sink: W, header: BigHeader) -> Self {
+        let data_end = BigHeader::byte_size() as u64;
+
+        Self {
+            sink,
+            header,
+            banks: Vec::new(),
+            data_end,
+        }
+    }
+
+    /// Starts a new bank. Entries added afterwards belong to it.
+    pub fn add_bank(&mut self, metadata: BigBankMetadata) {
+        self.banks.push((metadata, Vec::new()));
+    }
+
+    /// Adds an entry to the current bank with the data read from `data` until its end.
+    ///
+    /// The data is written at `start`, or at the next free block when it is `None`. The `size` and
+    /// `start` of `entry` are replaced by where the data ended up.
+    pub fn add_entry<R: Read>(
+        &mut self,
+        data: &mut R,
+        entry: &BigFileEntryOwned,
+        start: Option<u32>,
+    ) -> Result<&BigFileEntryOwned, BigWriterError> {
+        use BigWriterError::*;
+
+        let block_size = match self.banks.last() {
+            Some((bank, _)) => bank.block_size.max(1) as u64,
+            None => return Err(NoBank),
+        };
+
+        let start = match start {
+            Some(start) => start as u64,
+            None => self.data_end.next_multiple_of(block_size),
+        };
+
+        self.sink.seek(SeekFrom::Start(start)).map_err(Io)?;
+
+        let size = io::copy(data, &mut self.sink).map_err(Io)?;
+
+        self.data_end = self.data_end.max(start + size);
+
+        let entry = BigFileEntryOwned {
+            size: u32::try_from(size).map_err(Size)?,
+            start: u32::try_from(start).map_err(Start)?,
+            ..entry.clone()
+        };
+
+        let (_, entries) = self.banks.last_mut().ok_or(NoBank)?;
+
+        entries.push(entry);
+
+        Ok(&entries[entries.len() - 1])
+    }
+
+    pub fn add_entry_bytes(
+        &mut self,
+        data: &[u8],
+        entry: &BigFileEntryOwned,
+        start: Option<u32>,
+    ) -> Result<&BigFileEntryOwned, BigWriterError> {
+        self.add_entry(&mut &data[..], entry, start)
+    }
+
+    /// Writes each bank's file index, then the bank list after them and the header in front, and
+    /// returns the sink.
+    pub fn finish(mut self) -> Result<W, BigWriterError> {
+        use BigWriterError::*;
+
+        let mut bank_indexes = Vec::with_capacity(self.banks.len());
+
+        for (metadata, entries) in &self.banks {
+            let index = BigFileIndex {
+                file_type: metadata.file_type,
+                types_map: metadata.types_map.clone(),
+                entries: entries.iter().map(BigFileEntryOwned::as_entry).collect(),
+            };
+
+            let mut index_bytes = vec![0; index.byte_size()];
+
+            index.serialize(&mut &mut index_bytes[..]).map_err(Index)?;
+
+            let index_start = match metadata.index_start {
+                Some(index_start) => index_start as u64,
+                None => self.data_end,
+            };
+
+            self.sink.seek(SeekFrom::Start(index_start)).map_err(Io)?;
+            self.sink.write_all(&index_bytes).map_err(Io)?;
+
+            let index_size = index_bytes.len() as u64;
+
+            self.data_end = self.data_end.max(index_start + index_size);
+
+            bank_indexes.push(BigBankIndex {
+                name: &metadata.name,
+                bank_id: metadata.bank_id,
+                bank_entries_count: u32::try_from(entries.len()).map_err(BankEntriesCount)?,
+                index_start: u32::try_from(index_start).map_err(IndexStart)?,
+                index_size: u32::try_from(index_size).map_err(IndexSize)?,
+                block_size: metadata.block_size,
+            });
+        }
+
+        let bank_address = self.data_end;
+
+        let mut banks_bytes = vec![0; BigBankIndex::byte_size_all(&bank_indexes)];
+
+        BigBankIndex::serialize_all(&bank_indexes, &mut &mut banks_bytes[..]).map_err(Banks)?;
+
+        self.sink.seek(SeekFrom::Start(bank_address)).map_err(Io)?;
+        self.sink.write_all(&banks_bytes).map_err(Io)?;
+
+        self.header.bank_address = u32::try_from(bank_address).map_err(BankAddress)?;
+
+        let mut header_bytes = vec![0; BigHeader::byte_size()];
+
+        self.header
+            .serialize(&mut &mut header_bytes[..])
+            .map_err(Header)?;
+
+        self.sink.seek(SeekFrom::Start(0)).map_err(Io)?;
+        self.sink.write_all(&header_bytes).map_err(Io)?;
+        self.sink.flush().map_err(Io)?;
+
+        Ok(self.sink)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16s(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    fn u32s(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    fn texture_sub_header_bytes() -> Vec<u8> {
+        let mut bytes = u16s(&[256, 128, 1, 256, 128, 1, 1, 0]);
+        bytes.extend([1, 9]);
+        bytes.extend(u16s(&[0]));
+        bytes.extend(u32s(&[0x8000, 0x3a21]));
+        bytes.extend(u16s(&[7]));
+        bytes.extend(u32s(&[0xdeadbeef]));
+        bytes
+    }
+
+    fn mesh_sub_header_bytes() -> Vec<u8> {
+        let mut bytes = u32s(&[1]);
+        bytes.extend((0..10).flat_map(|v| (v as f32 * 0.5).to_le_bytes()));
+        // Three lods, padding, two unknowns and two texture ids.
+        bytes.extend(u32s(&[3, 1000, 500, 250, 0, 11, 12, 2, 4001, 4002]));
+        bytes
+    }
+
+    fn animation_sub_header_bytes() -> Vec<u8> {
+        let mut bytes = 1.5f32.to_le_bytes().to_vec();
+        bytes.extend(30.0f32.to_le_bytes());
+        bytes.extend([1, 2, 3, 4, 5]);
+        bytes
+    }
+
+    fn entry_bytes(file_type: u32, sub_header: &[u8]) -> Vec<u8> {
+        let mut bytes = u32s(&[42, 7, file_type, 1234, 2048, 5]);
+        bytes.extend(u32s(&[8]));
+        bytes.extend(b"TEX_TEST");
+        bytes.extend(u32s(&[0x1234_5678, 2, 3]));
+        bytes.extend(b"a.c");
+        bytes.extend(u32s(&[5]));
+        bytes.extend(b"b.tga");
+        bytes.extend(u32s(&[sub_header.len() as u32]));
+        bytes.extend(sub_header);
+        bytes
+    }
+
+    fn assert_entry_round_trip(bytes: &[u8], kind: BigBankKind) -> BigSubHeader<'static> {
+        let entry = BigFileEntry::parse(&mut &bytes[..], kind).unwrap();
+
+        assert_eq!(entry.byte_size(), bytes.len());
+
+        let mut out = vec![0; entry.byte_size()];
+        entry.serialize(&mut &mut out[..]).unwrap();
+
+        assert_eq!(out, bytes);
+
+        entry.sub_header.into_owned()
+    }
+
+    #[test]
+    fn texture_sub_header_round_trip() {
+        let bytes = texture_sub_header_bytes();
+
+        let sub_header = BigSubHeaderTexture::parse(&mut &bytes[..]).unwrap();
+
+        assert_eq!(sub_header.width, 256);
+        assert_eq!(sub_header.mip_maps, 9);
+        assert_eq!(sub_header.byte_size(), bytes.len());
+
+        let mut out = vec![0; sub_header.byte_size()];
+        sub_header.serialize(&mut &mut out[..]).unwrap();
+
+        assert_eq!(out, bytes);
+
+        let sub_header = assert_entry_round_trip(&entry_bytes(0, &bytes), BigBankKind::Textures);
+
+        assert!(matches!(sub_header, BigSubHeader::Texture(_)));
+    }
+
+    #[test]
+    fn mesh_sub_header_round_trip() {
+        let bytes = mesh_sub_header_bytes();
+
+        let sub_header = BigSubHeaderMesh::parse(&mut &bytes[..]).unwrap();
+
+        assert_eq!(sub_header.size_compressed_lod, [1000, 500, 250]);
+        assert_eq!(sub_header.unknown2, [11, 12]);
+        assert_eq!(sub_header.texture_ids, [4001, 4002]);
+        assert_eq!(sub_header.byte_size(), bytes.len());
+
+        let mut out = vec![0; sub_header.byte_size()];
+        sub_header.serialize(&mut &mut out[..]).unwrap();
+
+        assert_eq!(out, bytes);
+
+        let sub_header = assert_entry_round_trip(&entry_bytes(2, &bytes), BigBankKind::Graphics);
+
+        assert!(matches!(sub_header, BigSubHeader::Mesh(_)));
+    }
+
+    #[test]
+    fn animation_sub_header_round_trip() {
+        let bytes = animation_sub_header_bytes();
+
+        let sub_header = BigSubHeaderAnimation::parse(&mut &bytes[..]).unwrap();
+
+        assert_eq!(sub_header.unknown3, [1, 2, 3, 4, 5]);
+        assert_eq!(sub_header.byte_size(), bytes.len());
+
+        let mut out = vec![0; sub_header.byte_size()];
+        sub_header.serialize(&mut &mut out[..]).unwrap();
+
+        assert_eq!(out, bytes);
+
+        let sub_header = assert_entry_round_trip(&entry_bytes(6, &bytes), BigBankKind::Graphics);
+
+        assert!(matches!(sub_header, BigSubHeader::Animation(_)));
+    }
+
+    #[test]
+    fn unknown_sub_header_round_trip() {
+        let sub_header =
+            assert_entry_round_trip(&entry_bytes(99, &[9, 8, 7]), BigBankKind::Graphics);
+
+        assert_eq!(sub_header, BigSubHeader::Unknown(vec![9, 8, 7].into()));
+
+        // Texture sub-headers are only read in texture banks.
+        let bytes = texture_sub_header_bytes();
+        let sub_header = assert_entry_round_trip(&entry_bytes(0, &bytes), BigBankKind::Other);
+
+        assert_eq!(sub_header, BigSubHeader::Unknown(bytes.into()));
+    }
+
+    #[test]
+    fn file_types() {
+        let kinds = [
+            (&b"GBANK_MAIN_PC"[..], BigBankKind::Textures),
+            (b"MBANK_ALLMESHES", BigBankKind::Graphics),
+            (b"LANG_ENGLISH", BigBankKind::Text),
+            (b"LIPSYNC_ENGLISH_MAIN", BigBankKind::Dialogue),
+            (b"FONTS", BigBankKind::Other),
+        ];
+
+        for (name, kind) in kinds {
+            assert_eq!(BigBankKind::from_bank_name(name), kind);
+        }
+
+        assert_eq!(
+            BigFileType::new(BigBankKind::Text, 1),
+            BigFileType::TextGroup
+        );
+        assert_eq!(
+            BigFileType::new(BigBankKind::Dialogue, 0),
+            BigFileType::LipSync
+        );
+        assert_eq!(
+            BigFileType::new(BigBankKind::Other, 0),
+            BigFileType::Unknown(0)
+        );
+
+        for kind in kinds.map(|(_, kind)| kind) {
+            for file_type in 0..12 {
+                assert_eq!(BigFileType::new(kind, file_type).raw(), file_type);
+
+                for file_type_dev in 0..8 {
+                    let dev = BigFileTypeDev::new(kind, file_type, file_type_dev);
+                    assert_eq!(dev.raw(file_type), file_type_dev);
+                }
+            }
+        }
+
+        let mut bytes = entry_bytes(0, &texture_sub_header_bytes());
+        bytes[20..24].copy_from_slice(&3u32.to_le_bytes());
+        let entry = BigFileEntry::parse(&mut &bytes[..], BigBankKind::Textures).unwrap();
+
+        assert_eq!(
+            entry.file_type_dev(BigBankKind::Textures),
+            BigFileTypeDev::Bumpmap
+        );
+
+        // Outside texture banks the value normally repeats `file_type`.
+        let bytes = entry_bytes(5, &mesh_sub_header_bytes());
+        let entry = BigFileEntry::parse(&mut &bytes[..], BigBankKind::Graphics).unwrap();
+
+        assert_eq!(
+            entry.file_type_dev(BigBankKind::Graphics),
+            BigFileTypeDev::FileType
+        );
+    }
+
+    #[test]
+    fn file_index_round_trip() {
+        let mut bytes = u32s(&[3, 1, 2, 6, 10, 5, 11]);
+        bytes.extend(entry_bytes(6, &animation_sub_header_bytes()));
+        bytes.extend(entry_bytes(1, &mesh_sub_header_bytes()));
+
+        let index = BigFileIndex::parse(&mut &bytes[..], BigBankKind::Graphics).unwrap();
+
+        assert_eq!(index.types_map, [[6, 10], [5, 11]]);
+        assert_eq!(
+            index.file_types(BigBankKind::Graphics).collect::<Vec<_>>(),
+            [
+                BigFileType::StaticMesh,
+                BigFileType::Animation,
+                BigFileType::AnimatedMesh
+            ]
+        );
+        assert_eq!(index.entries.len(), 2);
+        assert_eq!(index.byte_size(), bytes.len());
+
+        let mut out = vec![0; index.byte_size()];
+        index.serialize(&mut &mut out[..]).unwrap();
+
+        assert_eq!(out, bytes);
+    }
+}
@@ -1,3 +1,7 @@
+pub(crate) mod byte_str;
 pub(crate) mod bytes;
+pub mod chunk;
 pub(crate) mod kv;
+pub mod lzo;
 pub(crate) mod slice;
+pub mod zlib;
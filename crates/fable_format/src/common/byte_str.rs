@@ -0,0 +1,57 @@
+//! Serde helpers for byte strings such as symbol and bank names.
+//!
+//! Names that are valid UTF-8 are written as strings so they stay readable in JSON. Anything else
+//! is written as an array of bytes, so no name is lost either way.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ByteStr {
+    Str(String),
+    Bytes(Vec<u8>),
+}
+
+pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => serializer.serialize_str(s),
+        Err(_) => bytes.serialize(serializer),
+    }
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    Ok(match ByteStr::deserialize(deserializer)? {
+        ByteStr::Str(s) => s.into_bytes(),
+        ByteStr::Bytes(bytes) => bytes,
+    })
+}
+
+/// The same as the parent module, for lists of byte strings.
+pub mod vec {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    struct ByteStrRef<'a>(&'a [u8]);
+
+    impl Serialize for ByteStrRef<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            super::serialize(self.0, serializer)
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct ByteStrOwned(#[serde(deserialize_with = "super::deserialize")] Vec<u8>);
+
+    pub fn serialize<S: Serializer>(values: &[Vec<u8>], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(values.iter().map(|bytes| ByteStrRef(bytes)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Vec<u8>>, D::Error> {
+        let values = Vec::<ByteStrOwned>::deserialize(deserializer)?;
+        Ok(values
+            .into_iter()
+            .map(|ByteStrOwned(bytes)| bytes)
+            .collect())
+    }
+}
@@ -54,3 +54,9 @@ pub fn put_bytes(out: &mut &mut [u8], inp: &[u8]) -> Result<(), UnexpectedEnd> {
 pub fn put<T: NoUninit>(out: &mut &mut [u8], value: &T) -> Result<(), UnexpectedEnd> {
     put_bytes(out, bytemuck::bytes_of(value))
 }
+
+/// Push a value implementing `bytemuck::NoUninit` onto the end of a byte vector, for output whose
+/// size isn't known up front.
+pub fn push<T: NoUninit>(out: &mut Vec<u8>, value: &T) {
+    out.extend_from_slice(bytemuck::bytes_of(value))
+}
@@ -0,0 +1,195 @@
+//! Tagged chunks, the building blocks of source meshes (`3DMF`) and animations (`3DAF`).
+//!
+//! A chunk is a 4 character tag, a dword holding the size of its data, then the data. Chunks that
+//! hold other chunks hold them one after another, filling their data. Tags are compared in reading
+//! order; files that store them as little endian dwords, so `3DMF` reads as `FMD3`, are read with
+//! [`TagOrder::Reversed`].
+
+use crate::common::bytes::{take, take_bytes, TakeError, UnexpectedEnd};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TagOrder {
+    AsStored,
+    Reversed,
+}
+
+impl TagOrder {
+    /// The order that makes `stored`, the first 4 bytes of a file, read as `magic`.
+    pub fn detect(stored: &[u8], magic: &[u8; 4]) -> Option<TagOrder> {
+        let stored: [u8; 4] = stored.get(..4)?.try_into().ok()?;
+
+        if stored == *magic {
+            Some(TagOrder::AsStored)
+        } else if stored == reversed(*magic) {
+            Some(TagOrder::Reversed)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Chunk<'a> {
+    /// The tag in reading order.
+    pub tag: [u8; 4],
+    pub data: &'a [u8],
+    pub order: TagOrder,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChunkError<E> {
+    Tag(E),
+    Size(E),
+    Data(UnexpectedEnd),
+}
+
+/// A chunk inside the chunk tagged `parent` is cut short.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ChildError<E> {
+    pub parent: [u8; 4],
+    pub error: ChunkError<E>,
+}
+
+impl<'a> Chunk<'a> {
+    pub fn parse(inp: &mut &'a [u8], order: TagOrder) -> Result<Chunk<'a>, ChunkError<TakeError>> {
+        use ChunkError::*;
+
+        let tag = take::<[u8; 4]>(inp).map_err(Tag)?;
+        let size = take::<u32>(inp).map_err(Size)?.to_le() as usize;
+        let data = take_bytes(inp, size).map_err(Data)?;
+
+        let tag = match order {
+            TagOrder::AsStored => tag,
+            TagOrder::Reversed => reversed(tag),
+        };
+
+        Ok(Chunk { tag, data, order })
+    }
+
+    /// The chunks this one holds.
+    pub fn children(&self) -> Chunks<'a> {
+        Chunks::new(self.data, self.order)
+    }
+
+    /// All the chunks this one holds, or an error for the first that is cut short.
+    pub fn collect_children(&self) -> Result<Vec<Chunk<'a>>, ChildError<TakeError>> {
+        self.children()
+            .collect::<Result<_, _>>()
+            .map_err(|error| ChildError {
+                parent: self.tag,
+                error,
+            })
+    }
+
+    /// Whether the data is nothing but whole chunks with printable tags, which is how containers
+    /// are told apart from data where the format description leaves it open.
+    pub fn is_container(&self) -> bool {
+        !self.data.is_empty()
+            && self
+                .children()
+                .all(|x| x.is_ok_and(|x| x.tag.iter().all(u8::is_ascii_graphic)))
+    }
+}
+
+/// Reads chunks one after another until the input runs out, stopping after an error.
+#[derive(Clone, Debug)]
+pub struct Chunks<'a> {
+    inp: &'a [u8],
+    order: TagOrder,
+}
+
+impl<'a> Chunks<'a> {
+    pub fn new(inp: &'a [u8], order: TagOrder) -> Self {
+        Chunks { inp, order }
+    }
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = Result<Chunk<'a>, ChunkError<TakeError>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.inp.is_empty() {
+            return None;
+        }
+
+        let chunk = Chunk::parse(&mut self.inp, self.order);
+
+        if chunk.is_err() {
+            self.inp = &[];
+        }
+
+        Some(chunk)
+    }
+}
+
+fn reversed(mut tag: [u8; 4]) -> [u8; 4] {
+    tag.reverse();
+    tag
+}
+
+/// A chunk's bytes with its tag as stored, for building files in tests.
+#[cfg(test)]
+pub(crate) fn chunk_bytes(tag: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut out = tag.to_vec();
+    out.extend((data.len() as u32).to_le_bytes());
+    out.extend(data);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_chunks() {
+        let mut file = b"FMD3\x0c\x00\x00\x00TRD3\x04\x00\x00\x00\x2a\x00\x00\x00".to_vec();
+        file.extend(b"SLTM\x02\x00\x00\x00");
+
+        let order = TagOrder::detect(&file, b"3DMF").unwrap();
+        assert_eq!(order, TagOrder::Reversed);
+
+        let mut chunks = Chunks::new(&file, order);
+        let root = chunks.next().unwrap().unwrap();
+
+        assert_eq!(&root.tag, b"3DMF");
+        assert!(root.is_container());
+
+        let children = root.children().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(&children[0].tag, b"3DRT");
+        assert_eq!(children[0].data, [0x2a, 0, 0, 0]);
+        assert!(!children[0].is_container());
+
+        assert_eq!(chunks.next(), Some(Err(ChunkError::Data(UnexpectedEnd))));
+        assert_eq!(chunks.next(), None);
+    }
+
+    #[test]
+    fn collect_children() {
+        let data = [chunk_bytes(b"ABCD", &[1]), chunk_bytes(b"EFGH", &[2, 3])].concat();
+        let file = chunk_bytes(b"ROOT", &data);
+
+        let root = Chunk::parse(&mut &file[..], TagOrder::AsStored).unwrap();
+        let children = root.collect_children().unwrap();
+
+        assert_eq!(
+            children
+                .iter()
+                .map(|x| (&x.tag, x.data))
+                .collect::<Vec<_>>(),
+            [(b"ABCD", &[1][..]), (b"EFGH", &[2, 3])]
+        );
+
+        let root = Chunk {
+            data: &data[..data.len() - 1],
+            ..root
+        };
+
+        assert_eq!(
+            root.collect_children(),
+            Err(ChildError {
+                parent: *b"ROOT",
+                error: ChunkError::Data(UnexpectedEnd),
+            })
+        );
+    }
+}
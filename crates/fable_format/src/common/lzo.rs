@@ -0,0 +1,409 @@
+//! LZO1X, the compression Fable uses for height tables, save blocks, compiled meshes and textures.
+//!
+//! [`decompress`] reads any LZO1X stream, like `lzo1x_decompress_safe`. [`compress`] writes
+//! streams that any LZO1X decompressor reads, with greedy matching.
+
+use std::collections::HashMap;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LzoError {
+    /// The input ended in the middle of an instruction or before the end marker.
+    InputOverrun,
+    /// A match referred to data before the start of the output.
+    LookBehindOverrun,
+    /// A length ran on for more zero bytes than any real stream has.
+    LengthOverflow,
+}
+
+/// The most zero bytes a length can be extended with before it would overflow.
+const MAX_255_COUNT: usize = usize::MAX / 255 - 2;
+
+const M2_MAX_LEN: usize = 8;
+const M2_MAX_OFFSET: usize = 0x0800;
+const M3_MAX_OFFSET: usize = 0x4000;
+const M4_MAX_OFFSET: usize = 0xbfff;
+
+const MIN_MATCH: usize = 3;
+
+struct Input<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Input<'_> {
+    fn byte(&mut self) -> Result<usize, LzoError> {
+        let byte = *self
+            .bytes
+            .get(self.position)
+            .ok_or(LzoError::InputOverrun)?;
+        self.position += 1;
+        Ok(byte as usize)
+    }
+
+    fn le16(&mut self) -> Result<usize, LzoError> {
+        Ok(self.byte()? | (self.byte()? << 8))
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&[u8], LzoError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .ok_or(LzoError::InputOverrun)?;
+        let bytes = self
+            .bytes
+            .get(self.position..end)
+            .ok_or(LzoError::InputOverrun)?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    /// Reads the extension of a length whose bits in the instruction were all zero.
+    fn extended_len(&mut self, base: usize) -> Result<usize, LzoError> {
+        let mut zeros = 0;
+
+        while self.byte()? == 0 {
+            zeros += 1;
+
+            if zeros > MAX_255_COUNT {
+                return Err(LzoError::LengthOverflow);
+            }
+        }
+
+        Ok(zeros * 255 + base + self.bytes[self.position - 1] as usize)
+    }
+}
+
+fn copy_match(out: &mut Vec<u8>, distance: usize, len: usize) -> Result<(), LzoError> {
+    let start = out
+        .len()
+        .checked_sub(distance)
+        .ok_or(LzoError::LookBehindOverrun)?;
+
+    // The match may overlap the bytes it produces, so copy one byte at a time.
+    for i in start..start + len {
+        out.push(out[i]);
+    }
+
+    Ok(())
+}
+
+/// Decompresses an LZO1X stream up to its end marker. Bytes after the marker are ignored.
+pub fn decompress(input: &[u8]) -> Result<Vec<u8>, LzoError> {
+    let mut out = Vec::with_capacity(input.len() * 2);
+    decode(input, &mut out)?;
+    Ok(out)
+}
+
+/// Like [`decompress`], appending to `out`. Matches can't reach into what `out` held before, and
+/// `out` is left as it was on errors.
+pub fn decompress_into(input: &[u8], out: &mut Vec<u8>) -> Result<(), LzoError> {
+    let decompressed = decompress(input)?;
+    out.extend_from_slice(&decompressed);
+    Ok(())
+}
+
+/// Decodes a stream into an empty `out`.
+fn decode(input: &[u8], out: &mut Vec<u8>) -> Result<(), LzoError> {
+    let mut ip = Input {
+        bytes: input,
+        position: 0,
+    };
+
+    // Number of literals copied right before the next instruction, which changes the meaning of
+    // instructions below 16: 0 after a match, 1 to 3 after short literals and 4 after a literal
+    // run.
+    let mut state;
+
+    if input.first().is_some_and(|&first| first > 17) {
+        let len = ip.byte()? - 17;
+        out.extend_from_slice(ip.bytes(len)?);
+        state = if len < 4 { len } else { 4 };
+    } else {
+        state = 0;
+    }
+
+    loop {
+        let t = ip.byte()?;
+
+        let (distance, len, next) = if t < 16 {
+            if state == 0 {
+                let len = if t == 0 { ip.extended_len(15)? } else { t } + 3;
+                out.extend_from_slice(ip.bytes(len)?);
+                state = 4;
+                continue;
+            }
+
+            let distance_high = ip.byte()? << 2;
+
+            if state == 4 {
+                (1 + M2_MAX_OFFSET + (t >> 2) + distance_high, 3, t & 3)
+            } else {
+                (1 + (t >> 2) + distance_high, 2, t & 3)
+            }
+        } else if t >= 64 {
+            let distance = 1 + ((t >> 2) & 7) + (ip.byte()? << 3);
+            (distance, (t >> 5) + 1, t & 3)
+        } else if t >= 32 {
+            let len = match t & 31 {
+                0 => ip.extended_len(31)?,
+                len => len,
+            } + 2;
+
+            let bits = ip.le16()?;
+            (1 + (bits >> 2), len, bits & 3)
+        } else {
+            let len = match t & 7 {
+                0 => ip.extended_len(7)?,
+                len => len,
+            } + 2;
+
+            let bits = ip.le16()?;
+            let distance = ((t & 8) << 11) + (bits >> 2);
+
+            if distance == 0 {
+                break;
+            }
+
+            (distance + M3_MAX_OFFSET, len, bits & 3)
+        };
+
+        copy_match(out, distance, len)?;
+
+        out.extend_from_slice(ip.bytes(next)?);
+        state = next;
+    }
+
+    Ok(())
+}
+
+struct Output {
+    bytes: Vec<u8>,
+    /// Position of the byte whose low 2 bits count the literals after the last match.
+    last_match_next: Option<usize>,
+}
+
+impl Output {
+    fn extended_len(&mut self, rest: usize) {
+        let zeros = (rest - 1) / 255;
+        self.bytes.extend(std::iter::repeat_n(0, zeros));
+        self.bytes.push((rest - zeros * 255) as u8);
+    }
+
+    fn literals(&mut self, literals: &[u8]) {
+        let len = literals.len();
+
+        if len == 0 {
+            return;
+        }
+
+        match self.last_match_next {
+            None if self.bytes.is_empty() && len <= 238 => {
+                self.bytes.push((len + 17) as u8);
+            }
+            Some(position) if len <= 3 => {
+                self.bytes[position] |= len as u8;
+            }
+            _ if len <= 18 => {
+                self.bytes.push((len - 3) as u8);
+            }
+            _ => {
+                self.bytes.push(0);
+                self.extended_len(len - 18);
+            }
+        }
+
+        self.bytes.extend_from_slice(literals);
+        self.last_match_next = None;
+    }
+
+    fn matched(&mut self, distance: usize, len: usize) {
+        if len <= M2_MAX_LEN && distance <= M2_MAX_OFFSET {
+            let d = distance - 1;
+            self.bytes.push((((len - 1) << 5) | ((d & 7) << 2)) as u8);
+            self.last_match_next = Some(self.bytes.len() - 1);
+            self.bytes.push((d >> 3) as u8);
+            return;
+        }
+
+        let d = if distance <= M3_MAX_OFFSET {
+            if len <= 33 {
+                self.bytes.push((32 | (len - 2)) as u8);
+            } else {
+                self.bytes.push(32);
+                self.extended_len(len - 33);
+            }
+
+            distance - 1
+        } else {
+            let d = distance - M3_MAX_OFFSET;
+            let high = ((d & 0x4000) >> 11) as u8;
+
+            if len <= 9 {
+                self.bytes.push(16 | high | (len - 2) as u8);
+            } else {
+                self.bytes.push(16 | high);
+                self.extended_len(len - 9);
+            }
+
+            d & 0x3fff
+        };
+
+        self.last_match_next = Some(self.bytes.len());
+        self.bytes
+            .extend_from_slice(&((d << 2) as u16).to_le_bytes());
+    }
+}
+
+/// Compresses `input` into an LZO1X stream, finishing with the end marker.
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Output {
+        bytes: Vec::with_capacity(input.len() / 2 + 16),
+        last_match_next: None,
+    };
+
+    // Last position each 3-byte sequence was seen at.
+    let mut positions: HashMap<[u8; 3], usize> = HashMap::new();
+
+    let mut literal_start = 0;
+    let mut position = 0;
+
+    while position + MIN_MATCH <= input.len() {
+        let key = [input[position], input[position + 1], input[position + 2]];
+        let candidate = positions.insert(key, position);
+
+        let distance = candidate.map_or(usize::MAX, |candidate| position - candidate);
+
+        if distance > M4_MAX_OFFSET {
+            position += 1;
+            continue;
+        }
+
+        let len = input[position..]
+            .iter()
+            .zip(&input[position - distance..])
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        out.literals(&input[literal_start..position]);
+        out.matched(distance, len);
+
+        for skipped in position + 1..(position + len).min(input.len() - MIN_MATCH + 1) {
+            let key = [input[skipped], input[skipped + 1], input[skipped + 2]];
+            positions.insert(key, skipped);
+        }
+
+        position += len;
+        literal_start = position;
+    }
+
+    out.literals(&input[literal_start..]);
+
+    // End marker: a far match with a distance of 0.
+    out.bytes.extend_from_slice(&[17, 0, 0]);
+
+    out.bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_round_trip(input: &[u8]) {
+        let compressed = compress(input);
+        let decompressed = decompress(&compressed).unwrap();
+
+        assert_eq!(decompressed, input);
+    }
+
+    #[test]
+    fn decompress_known_stream() {
+        // 3 literals, a 21 byte match at distance 3 and the end marker.
+        let compressed = [0x14, b'a', b'b', b'c', 0x33, 0x08, 0x00, 0x11, 0x00, 0x00];
+
+        assert_eq!(decompress(&compressed).unwrap(), b"abc".repeat(8));
+
+        // 2 literals, then a 2 byte match that only follows short literals.
+        let compressed = [0x13, b'a', b'b', 0x04, 0x00, 0x11, 0x00, 0x00];
+
+        assert_eq!(decompress(&compressed).unwrap(), b"abab");
+    }
+
+    #[test]
+    fn round_trip_empty_and_short() {
+        assert_round_trip(b"");
+        assert_round_trip(b"a");
+        assert_round_trip(b"ab");
+        assert_round_trip(b"abcd");
+    }
+
+    #[test]
+    fn round_trip_repetitive() {
+        assert_round_trip(&[0; 100_000]);
+        assert_round_trip(&b"FableFableFable the Lost Chapters ".repeat(500));
+    }
+
+    #[test]
+    fn round_trip_mixed() {
+        // A simple generator so the test covers literal runs, short and far matches.
+        let mut state = 0x2545_f491_u32;
+        let mut input = Vec::new();
+
+        while input.len() < 200_000 {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+
+            match state % 4 {
+                0 => input.extend((0..state % 300).map(|i| (state >> (i % 24)) as u8)),
+                1 if input.len() > 60_000 => {
+                    let start = input.len() - 50_000 + (state as usize % 1000);
+                    let copy = input[start..start + (state as usize % 400) + 3].to_vec();
+                    input.extend(copy);
+                }
+                _ => input.extend(b"height table ".repeat(state as usize % 5)),
+            }
+        }
+
+        assert_round_trip(&input);
+
+        let compressed = compress(&input);
+        assert!(compressed.len() < input.len());
+    }
+
+    #[test]
+    fn truncated_input() {
+        let compressed = compress(&b"some data some data some data".repeat(10));
+
+        for len in 0..compressed.len() {
+            assert!(decompress(&compressed[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn look_behind_overrun() {
+        // A literal, then a match reaching back further than the output.
+        let compressed = [0x12, b'a', 0x00, 0xff, 0x40, 0x00, 0x11, 0x00, 0x00];
+
+        assert_eq!(decompress(&compressed), Err(LzoError::LookBehindOverrun));
+    }
+
+    #[test]
+    fn decompress_into_appends() {
+        let mut out = b"kept".to_vec();
+
+        decompress_into(&compress(b"data"), &mut out).unwrap();
+        assert_eq!(out, b"keptdata");
+
+        // Neither a broken stream nor a match reaching into the old bytes touches them.
+        let compressed = compress(&b"more data more data".repeat(4));
+        assert!(decompress_into(&compressed[..compressed.len() - 4], &mut out).is_err());
+
+        let overrun = [0x12, b'a', 0x00, 0xff, 0x40, 0x00, 0x11, 0x00, 0x00];
+        assert_eq!(
+            decompress_into(&overrun, &mut out),
+            Err(LzoError::LookBehindOverrun)
+        );
+
+        assert_eq!(out, b"keptdata");
+    }
+}
@@ -0,0 +1,119 @@
+//! Zlib, the compression of save chunks and compiled def chunks.
+
+use miniz_oxide::{
+    deflate::compress_to_vec_zlib,
+    inflate::{decompress_to_vec_zlib_with_limit, TINFLStatus},
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ZlibError {
+    /// The stream is not valid zlib, or ended early.
+    Corrupt,
+    /// The stream decompressed to more than the expected size.
+    SizeExceeded,
+    /// The stream decompressed to less than the expected size.
+    Size { expected: usize, actual: usize },
+}
+
+/// Compression level, which the stream's second header byte records.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ZlibLevel {
+    /// `78 01`.
+    Fastest,
+    /// `78 5E`.
+    Fast,
+    /// `78 9C`.
+    Default,
+    /// `78 DA`, used by saves.
+    Best,
+}
+
+impl ZlibLevel {
+    /// Reads the level from the header of an existing stream, so it can be compressed the same way
+    /// again.
+    pub fn from_stream(stream: &[u8]) -> Option<ZlibLevel> {
+        match stream.get(1)? >> 6 {
+            0 => Some(ZlibLevel::Fastest),
+            1 => Some(ZlibLevel::Fast),
+            2 => Some(ZlibLevel::Default),
+            _ => Some(ZlibLevel::Best),
+        }
+    }
+
+    fn level(self) -> u8 {
+        match self {
+            ZlibLevel::Fastest => 1,
+            ZlibLevel::Fast => 3,
+            ZlibLevel::Default => 6,
+            ZlibLevel::Best => 9,
+        }
+    }
+}
+
+/// Decompresses a zlib stream of any size.
+pub fn decompress(input: &[u8]) -> Result<Vec<u8>, ZlibError> {
+    decompress_to_vec_zlib_with_limit(input, usize::MAX).map_err(|_| ZlibError::Corrupt)
+}
+
+/// Decompresses a zlib stream whose decompressed size is known, as in save and def headers.
+pub fn decompress_sized(input: &[u8], size: usize) -> Result<Vec<u8>, ZlibError> {
+    let out = decompress_to_vec_zlib_with_limit(input, size).map_err(|e| match e.status {
+        TINFLStatus::HasMoreOutput => ZlibError::SizeExceeded,
+        _ => ZlibError::Corrupt,
+    })?;
+
+    if out.len() != size {
+        return Err(ZlibError::Size {
+            expected: size,
+            actual: out.len(),
+        });
+    }
+
+    Ok(out)
+}
+
+pub fn compress(input: &[u8], level: ZlibLevel) -> Vec<u8> {
+    compress_to_vec_zlib(input, level.level())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_levels() {
+        let input = b"FableSave!\0\0 FinalAlbion ".repeat(200);
+
+        for (level, header) in [
+            (ZlibLevel::Fastest, [0x78, 0x01]),
+            (ZlibLevel::Fast, [0x78, 0x5e]),
+            (ZlibLevel::Default, [0x78, 0x9c]),
+            (ZlibLevel::Best, [0x78, 0xda]),
+        ] {
+            let compressed = compress(&input, level);
+
+            assert_eq!(compressed[..2], header);
+            assert_eq!(ZlibLevel::from_stream(&compressed), Some(level));
+            assert_eq!(decompress(&compressed).unwrap(), input);
+            assert_eq!(decompress_sized(&compressed, input.len()).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn wrong_size() {
+        let compressed = compress(b"hero", ZlibLevel::Best);
+
+        assert_eq!(
+            decompress_sized(&compressed, 3),
+            Err(ZlibError::SizeExceeded)
+        );
+        assert_eq!(
+            decompress_sized(&compressed, 5),
+            Err(ZlibError::Size {
+                expected: 5,
+                actual: 4
+            })
+        );
+        assert_eq!(decompress(&compressed[..4]), Err(ZlibError::Corrupt));
+    }
+}
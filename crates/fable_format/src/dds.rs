@@ -0,0 +1,504 @@
+use std::mem;
+
+use crate::{
+    big::BigSubHeaderTexture,
+    common::bytes::{put, take, TakeError, UnexpectedEnd},
+    texture::{is_top_mip_map_compressed, max_mip_levels, TextureFormat},
+};
+
+/// Header of a DirectDraw Surface file, preceded by the `DDS ` magic.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DdsHeader {
+    pub flags: u32,
+    pub height: u32,
+    pub width: u32,
+    pub pitch_or_linear_size: u32,
+    pub depth: u32,
+    pub mip_map_count: u32,
+    pub reserved_1: [u32; 11],
+    pub pixel_format: DdsPixelFormat,
+    pub caps: u32,
+    pub caps_2: u32,
+    pub caps_3: u32,
+    pub caps_4: u32,
+    pub reserved_2: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DdsPixelFormat {
+    pub flags: u32,
+    pub four_cc: [u8; 4],
+    pub rgb_bit_count: u32,
+    pub r_mask: u32,
+    pub g_mask: u32,
+    pub b_mask: u32,
+    pub a_mask: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum DdsHeaderError<E> {
+    Magic(E),
+    Size(E),
+    Flags(E),
+    Height(E),
+    Width(E),
+    PitchOrLinearSize(E),
+    Depth(E),
+    MipMapCount(E),
+    Reserved1(E),
+    PixelFormatSize(E),
+    PixelFormatFlags(E),
+    FourCc(E),
+    RgbBitCount(E),
+    Masks(E),
+    Caps(E),
+    Reserved2(E),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum DdsError {
+    Header(DdsHeaderError<TakeError>),
+    Magic([u8; 4]),
+    /// The pixel format has no equivalent in `.big` textures.
+    PixelFormat,
+    Size {
+        width: u32,
+        height: u32,
+    },
+    /// More mip levels than it takes to get down to 1x1, or than a sub-header can hold.
+    MipMapCount(u32),
+    DataPastEnd,
+    /// The texture's format could not be determined from its sub-header.
+    TextureFormat {
+        dxt_compression: u16,
+    },
+    /// The top mip map is LZO compressed, which needs decompressing first.
+    CompressedTopMipMap,
+    Serialize(DdsHeaderError<UnexpectedEnd>),
+}
+
+impl DdsHeader {
+    pub const MAGIC: [u8; 4] = *b"DDS ";
+
+    pub const FLAG_CAPS: u32 = 0x1;
+    pub const FLAG_HEIGHT: u32 = 0x2;
+    pub const FLAG_WIDTH: u32 = 0x4;
+    pub const FLAG_PITCH: u32 = 0x8;
+    pub const FLAG_PIXEL_FORMAT: u32 = 0x1000;
+    pub const FLAG_MIP_MAP_COUNT: u32 = 0x20000;
+    pub const FLAG_LINEAR_SIZE: u32 = 0x80000;
+
+    pub const CAPS_COMPLEX: u32 = 0x8;
+    pub const CAPS_TEXTURE: u32 = 0x1000;
+    pub const CAPS_MIP_MAP: u32 = 0x400000;
+
+    pub fn parse(inp: &mut &[u8]) -> Result<Self, DdsHeaderError<TakeError>> {
+        use DdsHeaderError::*;
+
+        let _magic = take::<[u8; 4]>(inp).map_err(Magic)?;
+        let _size = take::<u32>(inp).map_err(Size)?.to_le();
+        let flags = take::<u32>(inp).map_err(Flags)?.to_le();
+        let height = take::<u32>(inp).map_err(Height)?.to_le();
+        let width = take::<u32>(inp).map_err(Width)?.to_le();
+        let pitch_or_linear_size = take::<u32>(inp).map_err(PitchOrLinearSize)?.to_le();
+        let depth = take::<u32>(inp).map_err(Depth)?.to_le();
+        let mip_map_count = take::<u32>(inp).map_err(MipMapCount)?.to_le();
+        let reserved_1 = take::<[u32; 11]>(inp).map_err(Reserved1)?.map(u32::to_le);
+
+        let _pixel_format_size = take::<u32>(inp).map_err(PixelFormatSize)?.to_le();
+        let pixel_format_flags = take::<u32>(inp).map_err(PixelFormatFlags)?.to_le();
+        let four_cc = take::<[u8; 4]>(inp).map_err(FourCc)?;
+        let rgb_bit_count = take::<u32>(inp).map_err(RgbBitCount)?.to_le();
+        let [r_mask, g_mask, b_mask, a_mask] =
+            take::<[u32; 4]>(inp).map_err(Masks)?.map(u32::to_le);
+
+        let [caps, caps_2, caps_3, caps_4] = take::<[u32; 4]>(inp).map_err(Caps)?.map(u32::to_le);
+        let reserved_2 = take::<u32>(inp).map_err(Reserved2)?.to_le();
+
+        Ok(DdsHeader {
+            flags,
+            height,
+            width,
+            pitch_or_linear_size,
+            depth,
+            mip_map_count,
+            reserved_1,
+            pixel_format: DdsPixelFormat {
+                flags: pixel_format_flags,
+                four_cc,
+                rgb_bit_count,
+                r_mask,
+                g_mask,
+                b_mask,
+                a_mask,
+            },
+            caps,
+            caps_2,
+            caps_3,
+            caps_4,
+            reserved_2,
+        })
+    }
+
+    pub fn serialize(&self, out: &mut &mut [u8]) -> Result<(), DdsHeaderError<UnexpectedEnd>> {
+        use DdsHeaderError::*;
+
+        let pixel_format = &self.pixel_format;
+
+        put(out, &Self::MAGIC).map_err(Magic)?;
+        put(out, &124u32.to_le()).map_err(Size)?;
+        put(out, &self.flags.to_le()).map_err(Flags)?;
+        put(out, &self.height.to_le()).map_err(Height)?;
+        put(out, &self.width.to_le()).map_err(Width)?;
+        put(out, &self.pitch_or_linear_size.to_le()).map_err(PitchOrLinearSize)?;
+        put(out, &self.depth.to_le()).map_err(Depth)?;
+        put(out, &self.mip_map_count.to_le()).map_err(MipMapCount)?;
+        put(out, &self.reserved_1.map(u32::to_le)).map_err(Reserved1)?;
+
+        put(out, &32u32.to_le()).map_err(PixelFormatSize)?;
+        put(out, &pixel_format.flags.to_le()).map_err(PixelFormatFlags)?;
+        put(out, &pixel_format.four_cc).map_err(FourCc)?;
+        put(out, &pixel_format.rgb_bit_count.to_le()).map_err(RgbBitCount)?;
+
+        let masks = [
+            pixel_format.r_mask,
+            pixel_format.g_mask,
+            pixel_format.b_mask,
+            pixel_format.a_mask,
+        ];
+
+        put(out, &masks.map(u32::to_le)).map_err(Masks)?;
+
+        let caps = [self.caps, self.caps_2, self.caps_3, self.caps_4];
+
+        put(out, &caps.map(u32::to_le)).map_err(Caps)?;
+        put(out, &self.reserved_2.to_le()).map_err(Reserved2)?;
+
+        Ok(())
+    }
+
+    pub const fn byte_size() -> usize {
+        // Magic
+        mem::size_of::<[u8; 4]>() +
+        // Header
+        124
+    }
+}
+
+impl DdsPixelFormat {
+    pub const FLAG_ALPHA_PIXELS: u32 = 0x1;
+    pub const FLAG_FOUR_CC: u32 = 0x4;
+    pub const FLAG_RGB: u32 = 0x40;
+
+    pub fn from_texture_format(format: TextureFormat) -> Self {
+        let four_cc = |four_cc: &[u8; 4]| DdsPixelFormat {
+            flags: Self::FLAG_FOUR_CC,
+            four_cc: *four_cc,
+            ..Default::default()
+        };
+
+        let rgb = |rgb_bit_count, r_mask, g_mask, b_mask, a_mask| DdsPixelFormat {
+            flags: if a_mask == 0 {
+                Self::FLAG_RGB
+            } else {
+                Self::FLAG_RGB | Self::FLAG_ALPHA_PIXELS
+            },
+            four_cc: [0; 4],
+            rgb_bit_count,
+            r_mask,
+            g_mask,
+            b_mask,
+            a_mask,
+        };
+
+        match format {
+            TextureFormat::Dxt1 => four_cc(b"DXT1"),
+            TextureFormat::Dxt3 => four_cc(b"DXT3"),
+            TextureFormat::Dxt5 => four_cc(b"DXT5"),
+            TextureFormat::Bgra8888 => rgb(32, 0xff0000, 0xff00, 0xff, 0xff000000),
+            TextureFormat::Bgra4444 => rgb(16, 0x0f00, 0x00f0, 0x000f, 0xf000),
+            TextureFormat::Bgr565 => rgb(16, 0xf800, 0x07e0, 0x001f, 0),
+        }
+    }
+
+    pub fn texture_format(&self) -> Option<TextureFormat> {
+        if self.flags & Self::FLAG_FOUR_CC != 0 {
+            return match &self.four_cc {
+                b"DXT1" => Some(TextureFormat::Dxt1),
+                b"DXT3" => Some(TextureFormat::Dxt3),
+                b"DXT5" => Some(TextureFormat::Dxt5),
+                _ => None,
+            };
+        }
+
+        let a_mask = if self.flags & Self::FLAG_ALPHA_PIXELS != 0 {
+            self.a_mask
+        } else {
+            0
+        };
+
+        match (
+            self.rgb_bit_count,
+            self.r_mask,
+            self.g_mask,
+            self.b_mask,
+            a_mask,
+        ) {
+            (32, 0xff0000, 0xff00, 0xff, 0xff000000) => Some(TextureFormat::Bgra8888),
+            (16, 0x0f00, 0x00f0, 0x000f, 0xf000) => Some(TextureFormat::Bgra4444),
+            (16, 0xf800, 0x07e0, 0x001f, 0) => Some(TextureFormat::Bgr565),
+            _ => None,
+        }
+    }
+}
+
+/// Wraps a texture payload in a DDS file without re-encoding it.
+///
+/// The payload's mip levels are copied as is, so the DDS holds exactly the game's data. Levels
+/// missing from the end of the payload are left out of the mip count, as are levels past 1x1.
+pub fn texture_to_dds(header: &BigSubHeaderTexture, payload: &[u8]) -> Result<Vec<u8>, DdsError> {
+    let format = TextureFormat::from_sub_header(header).ok_or(DdsError::TextureFormat {
+        dxt_compression: header.dxt_compression,
+    })?;
+
+    if is_top_mip_map_compressed(header) {
+        return Err(DdsError::CompressedTopMipMap);
+    }
+
+    let width = header.width as u32;
+    let height = header.height as u32;
+
+    let mut data_size = 0;
+    let mut mip_map_count = 0;
+
+    let level_count = (header.mip_maps.max(1) as u32).min(max_mip_levels(width, height));
+
+    for level in 0..level_count {
+        let level_size = format.level_size(
+            (width >> level).max(1) as usize,
+            (height >> level).max(1) as usize,
+        );
+
+        if data_size + level_size > payload.len() {
+            break;
+        }
+
+        data_size += level_size;
+        mip_map_count += 1;
+    }
+
+    if mip_map_count == 0 {
+        return Err(DdsError::DataPastEnd);
+    }
+
+    let pixel_format = DdsPixelFormat::from_texture_format(format);
+
+    let mut flags = DdsHeader::FLAG_CAPS
+        | DdsHeader::FLAG_HEIGHT
+        | DdsHeader::FLAG_WIDTH
+        | DdsHeader::FLAG_PIXEL_FORMAT;
+
+    let pitch_or_linear_size = if format.is_block_compressed() {
+        flags |= DdsHeader::FLAG_LINEAR_SIZE;
+        format.level_size(width as usize, height as usize) as u32
+    } else {
+        flags |= DdsHeader::FLAG_PITCH;
+        width * pixel_format.rgb_bit_count / 8
+    };
+
+    let mut caps = DdsHeader::CAPS_TEXTURE;
+
+    if mip_map_count > 1 {
+        flags |= DdsHeader::FLAG_MIP_MAP_COUNT;
+        caps |= DdsHeader::CAPS_COMPLEX | DdsHeader::CAPS_MIP_MAP;
+    }
+
+    let dds_header = DdsHeader {
+        flags,
+        height,
+        width,
+        pitch_or_linear_size,
+        mip_map_count,
+        pixel_format,
+        caps,
+        ..Default::default()
+    };
+
+    let mut bytes = vec![0; DdsHeader::byte_size() + data_size];
+
+    dds_header
+        .serialize(&mut &mut bytes[..])
+        .map_err(DdsError::Serialize)?;
+
+    bytes[DdsHeader::byte_size()..].copy_from_slice(&payload[..data_size]);
+
+    Ok(bytes)
+}
+
+/// Reads a DDS file into a texture sub-header and payload without re-encoding it, the reverse of
+/// [`texture_to_dds`].
+///
+/// DDS files have no animation frames, so the whole image is one frame, and `transparency` is
+/// set when the format keeps alpha. Fields whose meaning is unknown are left at 0, so copy them
+/// and the frame layout from the original sub-header when replacing a texture.
+pub fn dds_to_texture(bytes: &[u8]) -> Result<(BigSubHeaderTexture, &[u8]), DdsError> {
+    let mut inp = bytes;
+
+    let header = DdsHeader::parse(&mut inp).map_err(DdsError::Header)?;
+
+    let magic: [u8; 4] = bytes[..4].try_into().unwrap();
+
+    if magic != DdsHeader::MAGIC {
+        return Err(DdsError::Magic(magic));
+    }
+
+    let format = header
+        .pixel_format
+        .texture_format()
+        .ok_or(DdsError::PixelFormat)?;
+
+    let size_error = DdsError::Size {
+        width: header.width,
+        height: header.height,
+    };
+
+    if header.width == 0 || header.height == 0 {
+        return Err(size_error);
+    }
+
+    let width = u16::try_from(header.width).map_err(|_| size_error)?;
+    let height = u16::try_from(header.height).map_err(|_| size_error)?;
+
+    let mip_map_count = if header.flags & DdsHeader::FLAG_MIP_MAP_COUNT != 0 {
+        header.mip_map_count.max(1)
+    } else {
+        1
+    };
+
+    if mip_map_count > max_mip_levels(header.width, header.height) {
+        return Err(DdsError::MipMapCount(mip_map_count));
+    }
+
+    let mip_maps = u8::try_from(mip_map_count).map_err(|_| DdsError::MipMapCount(mip_map_count))?;
+
+    let level_sizes = (0..mip_map_count).map(|level| {
+        format.level_size(
+            (header.width >> level).max(1) as usize,
+            (header.height >> level).max(1) as usize,
+        )
+    });
+
+    let top_mip_map_size = format.level_size(width as usize, height as usize);
+    let data_size = level_sizes.sum::<usize>();
+
+    let payload = inp.get(..data_size).ok_or(DdsError::DataPastEnd)?;
+
+    let transparency = matches!(
+        format,
+        TextureFormat::Dxt3
+            | TextureFormat::Dxt5
+            | TextureFormat::Bgra8888
+            | TextureFormat::Bgra4444
+    ) as u8;
+
+    let texture = BigSubHeaderTexture {
+        width,
+        height,
+        depth: 1,
+        frame_width: width,
+        frame_height: height,
+        frame_count: 1,
+        dxt_compression: format.dxt_compression(),
+        unknown1: 0,
+        transparency,
+        mip_maps,
+        unknown2: 0,
+        top_mip_map_size: top_mip_map_size as u32,
+        top_mip_map_compressed_size: 0,
+        unknown3: 0,
+        unknown4: 0,
+    };
+
+    Ok((texture, payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::{decode_mip_levels, encode_texture, TextureImage};
+
+    fn gradient(width: u32, height: u32) -> TextureImage {
+        TextureImage {
+            width,
+            height,
+            rgba: (0..width * height)
+                .flat_map(|i| [(i * 7) as u8, (i * 3) as u8, (i / 5) as u8, (i * 11) as u8])
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn texture_dds_round_trip() {
+        let formats = [
+            TextureFormat::Dxt1,
+            TextureFormat::Dxt3,
+            TextureFormat::Dxt5,
+            TextureFormat::Bgra8888,
+            TextureFormat::Bgra4444,
+            TextureFormat::Bgr565,
+        ];
+
+        for format in formats {
+            for mip_maps in [1, 4] {
+                let (header, payload) = encode_texture(&gradient(16, 8), format, mip_maps).unwrap();
+
+                let dds = texture_to_dds(&header, &payload).unwrap();
+                let (read_header, read_payload) = dds_to_texture(&dds).unwrap();
+
+                assert_eq!(read_payload, payload, "{:?}", format);
+                assert_eq!(
+                    BigSubHeaderTexture {
+                        transparency: header.transparency,
+                        ..read_header
+                    },
+                    header,
+                    "{:?}",
+                    format
+                );
+
+                assert_eq!(texture_to_dds(&read_header, read_payload).unwrap(), dds);
+            }
+        }
+    }
+
+    #[test]
+    fn mip_map_counts() {
+        let (mut header, payload) =
+            encode_texture(&gradient(4, 4), TextureFormat::Dxt1, 3).unwrap();
+        assert_eq!(header.mip_maps, 3);
+
+        let mut dds = texture_to_dds(&header, &payload).unwrap();
+
+        // mip_map_count, far past the 3 levels of a 4x4 image.
+        dds[28..32].copy_from_slice(&40u32.to_le_bytes());
+
+        assert!(matches!(
+            dds_to_texture(&dds),
+            Err(DdsError::MipMapCount(40))
+        ));
+
+        header.mip_maps = 255;
+
+        let dds = texture_to_dds(&header, &payload).unwrap();
+        assert_eq!(dds_to_texture(&dds).unwrap().0.mip_maps, 3);
+        assert_eq!(decode_mip_levels(&header, &payload).unwrap().len(), 3);
+
+        // A compressed top mip map isn't decompressed into the DDS.
+        header.top_mip_map_compressed_size = header.top_mip_map_size / 2;
+
+        assert!(matches!(
+            texture_to_dds(&header, &payload),
+            Err(DdsError::CompressedTopMipMap)
+        ));
+    }
+}
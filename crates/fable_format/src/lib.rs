@@ -1,12 +1,16 @@
 pub(crate) mod common;
 
-pub use common::bytes::{TakeError, UnexpectedEnd};
+pub use common::{
+    bytes::{TakeError, UnexpectedEnd},
+    chunk, lzo, zlib,
+};
 
-// mod bba;
-// mod bbm;
+pub mod bba;
+pub mod bbm;
 pub mod big;
 // mod bncfg;
 // mod bwd;
+pub mod dds;
 // mod def;
 // mod gtg;
 // mod ini;
@@ -17,6 +21,8 @@ pub mod lev;
 // mod qst;
 // mod save;
 // mod stb;
+pub mod tagged_model;
+pub mod texture;
 pub mod tng;
 pub mod wad;
 // mod wld;
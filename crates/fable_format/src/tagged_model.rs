@@ -0,0 +1,334 @@
+//! Source mesh format (`3DMF`), the tagged model format compiled into [`crate::bbm`].
+//!
+//! ## Format Description
+//!
+//! The chunk tree, as described in [`crate::bbm`]:
+//!
+//! ```txt
+//! 3DMF: 3D Mesh File
+//!     3DRT: (File Size of all Chunks)
+//!     MTLS: Material List (File Size of All MTRL Chunks)
+//!         MTRL: Material Description
+//!         MTLE: Extended Material Properties
+//!         MMAP: Mapping Info
+//!     SUBM: Sub-Mesh
+//!         TRFM: Transformation Matrix
+//!         PRIM: Primitive
+//!             TRIS: Triangle List
+//!             SMTH: Smoothing Groups
+//!             VERT: Vertex List
+//!             UNIV: Unique Vertex Information
+//!             VGRP: Vertex Group
+//!         BONE: Bone
+//!         CLTH: Cloth
+//!     HLPR: Helpers
+//!         HDMY: Dummy Object
+//!         HPNT: Helper Point
+//!         HCVL: Convex Volume
+//! ```
+//!
+//! ## Reading
+//!
+//! Chunks are read with [`crate::chunk`]. Where the description is vague this parser reads it as:
+//!
+//! - The file is one `3DMF` chunk holding the others. `3DRT` is either a dword or, when its data
+//!   is nothing but chunks, a container of the chunks listed beside it.
+//! - `MTLE` and `MMAP` belong to the `MTRL` before them, whether they follow it or are inside it.
+//! - `TRFM` is a matrix of floats, whatever their count.
+//!
+//! The contents of the other leaf chunks are undocumented and kept as they are, as are chunks with
+//! tags the description doesn't list, so nothing is lost.
+
+use crate::common::{
+    bytes::TakeError,
+    chunk::{ChildError, Chunk, ChunkError, TagOrder},
+};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TaggedModel {
+    /// The dword `3DRT` holds, unless it's a container.
+    pub size: Option<u32>,
+    pub materials: Vec<TaggedMaterial>,
+    pub sub_meshes: Vec<TaggedSubMesh>,
+    pub helpers: TaggedHelpers,
+    pub other: Vec<TaggedChunk>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TaggedMaterial {
+    /// `MTRL`.
+    pub description: Vec<u8>,
+    /// `MTLE` chunks.
+    pub extended: Vec<Vec<u8>>,
+    /// `MMAP` chunks.
+    pub mappings: Vec<Vec<u8>>,
+    pub other: Vec<TaggedChunk>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TaggedSubMesh {
+    /// `TRFM`.
+    pub transform: Option<Vec<f32>>,
+    pub primitives: Vec<TaggedPrimitive>,
+    /// `BONE` chunks.
+    pub bones: Vec<Vec<u8>>,
+    /// `CLTH` chunks.
+    pub cloths: Vec<Vec<u8>>,
+    pub other: Vec<TaggedChunk>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TaggedPrimitive {
+    /// `TRIS`.
+    pub triangles: Vec<u8>,
+    /// `SMTH`.
+    pub smoothing_groups: Vec<u8>,
+    /// `VERT`.
+    pub vertices: Vec<u8>,
+    /// `UNIV`.
+    pub unique_vertices: Vec<u8>,
+    /// `VGRP` chunks.
+    pub vertex_groups: Vec<Vec<u8>>,
+    pub other: Vec<TaggedChunk>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TaggedHelpers {
+    /// `HDMY` chunks.
+    pub dummies: Vec<Vec<u8>>,
+    /// `HPNT` chunks.
+    pub points: Vec<Vec<u8>>,
+    /// `HCVL` chunks.
+    pub convex_volumes: Vec<Vec<u8>>,
+    pub other: Vec<TaggedChunk>,
+}
+
+/// A chunk kept as it is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaggedChunk {
+    pub tag: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum TaggedModelError<E> {
+    /// The file doesn't start with `3DMF` in either tag order.
+    Magic,
+    Root(ChunkError<E>),
+    Chunk(ChildError<E>),
+}
+
+impl TaggedModel {
+    pub fn parse(inp: &mut &[u8]) -> Result<TaggedModel, TaggedModelError<TakeError>> {
+        let order = TagOrder::detect(inp, b"3DMF").ok_or(TaggedModelError::Magic)?;
+
+        let root = Chunk::parse(inp, order).map_err(TaggedModelError::Root)?;
+
+        let mut model = TaggedModel::default();
+        model.read(&root)?;
+
+        Ok(model)
+    }
+
+    /// Reads the chunks inside `parent` into the model.
+    fn read(&mut self, parent: &Chunk) -> Result<(), TaggedModelError<TakeError>> {
+        for chunk in parent.collect_children().map_err(TaggedModelError::Chunk)? {
+            match &chunk.tag {
+                b"3DRT" if chunk.is_container() => self.read(&chunk)?,
+                b"3DRT" if chunk.data.len() == 4 => {
+                    self.size = Some(u32::from_le_bytes(chunk.data.try_into().unwrap()));
+                }
+                b"MTLS" => self.read_materials(&chunk)?,
+                b"SUBM" => {
+                    let mut sub_mesh = TaggedSubMesh::default();
+                    sub_mesh.read(&chunk)?;
+                    self.sub_meshes.push(sub_mesh);
+                }
+                b"HLPR" => self.helpers.read(&chunk)?,
+                _ => self.other.push(chunk.into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_materials(&mut self, parent: &Chunk) -> Result<(), TaggedModelError<TakeError>> {
+        for chunk in parent.collect_children().map_err(TaggedModelError::Chunk)? {
+            if &chunk.tag == b"MTRL" {
+                self.materials.push(TaggedMaterial::default());
+
+                if chunk.is_container() {
+                    self.read_materials(&chunk)?;
+                } else {
+                    self.materials.last_mut().unwrap().description = chunk.data.to_vec();
+                }
+
+                continue;
+            }
+
+            if self.materials.is_empty() {
+                self.materials.push(TaggedMaterial::default());
+            }
+
+            let material = self.materials.last_mut().unwrap();
+
+            match &chunk.tag {
+                b"MTLE" => material.extended.push(chunk.data.to_vec()),
+                b"MMAP" => material.mappings.push(chunk.data.to_vec()),
+                _ => material.other.push(chunk.into()),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl TaggedSubMesh {
+    fn read(&mut self, parent: &Chunk) -> Result<(), TaggedModelError<TakeError>> {
+        for chunk in parent.collect_children().map_err(TaggedModelError::Chunk)? {
+            match &chunk.tag {
+                b"TRFM" if chunk.data.len().is_multiple_of(4) => {
+                    let floats = chunk.data.chunks_exact(4);
+                    let floats = floats.map(|x| f32::from_le_bytes(x.try_into().unwrap()));
+                    self.transform = Some(floats.collect());
+                }
+                b"PRIM" => {
+                    let mut primitive = TaggedPrimitive::default();
+                    primitive.read(&chunk)?;
+                    self.primitives.push(primitive);
+                }
+                b"BONE" => self.bones.push(chunk.data.to_vec()),
+                b"CLTH" => self.cloths.push(chunk.data.to_vec()),
+                _ => self.other.push(chunk.into()),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl TaggedPrimitive {
+    fn read(&mut self, parent: &Chunk) -> Result<(), TaggedModelError<TakeError>> {
+        for chunk in parent.collect_children().map_err(TaggedModelError::Chunk)? {
+            match &chunk.tag {
+                b"TRIS" => self.triangles = chunk.data.to_vec(),
+                b"SMTH" => self.smoothing_groups = chunk.data.to_vec(),
+                b"VERT" => self.vertices = chunk.data.to_vec(),
+                b"UNIV" => self.unique_vertices = chunk.data.to_vec(),
+                b"VGRP" => self.vertex_groups.push(chunk.data.to_vec()),
+                _ => self.other.push(chunk.into()),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl TaggedHelpers {
+    fn read(&mut self, parent: &Chunk) -> Result<(), TaggedModelError<TakeError>> {
+        for chunk in parent.collect_children().map_err(TaggedModelError::Chunk)? {
+            match &chunk.tag {
+                b"HDMY" => self.dummies.push(chunk.data.to_vec()),
+                b"HPNT" => self.points.push(chunk.data.to_vec()),
+                b"HCVL" => self.convex_volumes.push(chunk.data.to_vec()),
+                _ => self.other.push(chunk.into()),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl From<Chunk<'_>> for TaggedChunk {
+    fn from(chunk: Chunk) -> Self {
+        TaggedChunk {
+            tag: chunk.tag,
+            data: chunk.data.to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::chunk::chunk_bytes;
+
+    #[test]
+    fn parse_tagged_model() {
+        let transform = [
+            1.0f32, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0,
+        ]
+        .iter()
+        .flat_map(|x| x.to_le_bytes())
+        .collect::<Vec<_>>();
+
+        let materials = [
+            chunk_bytes(b"MTRL", b"MAT\0"),
+            chunk_bytes(b"MTLE", &[1]),
+            chunk_bytes(b"MMAP", &[2]),
+        ]
+        .concat();
+
+        let primitive = [
+            chunk_bytes(b"TRIS", &[0, 0, 1, 0, 2, 0]),
+            chunk_bytes(b"VERT", &[3; 12]),
+            chunk_bytes(b"VGRP", &[4]),
+            chunk_bytes(b"VGRP", &[5]),
+        ]
+        .concat();
+
+        let sub_mesh = [
+            chunk_bytes(b"TRFM", &transform),
+            chunk_bytes(b"PRIM", &primitive),
+            chunk_bytes(b"BONE", b"ROOT"),
+            chunk_bytes(b"XTRA", &[6]),
+        ]
+        .concat();
+
+        let helpers = [chunk_bytes(b"HPNT", &[7]), chunk_bytes(b"HDMY", &[8])].concat();
+
+        let root = [
+            chunk_bytes(b"MTLS", &materials),
+            chunk_bytes(b"SUBM", &sub_mesh),
+            chunk_bytes(b"HLPR", &helpers),
+        ]
+        .concat();
+
+        let file = chunk_bytes(b"3DMF", &[chunk_bytes(b"3DRT", &root)].concat());
+
+        let model = TaggedModel::parse(&mut &file[..]).unwrap();
+
+        assert_eq!(model.size, None);
+        assert_eq!(model.materials.len(), 1);
+        assert_eq!(model.materials[0].description, b"MAT\0");
+        assert_eq!(model.materials[0].extended, [vec![1]]);
+        assert_eq!(model.materials[0].mappings, [vec![2]]);
+
+        let sub_mesh = &model.sub_meshes[0];
+        assert_eq!(sub_mesh.transform.as_ref().unwrap()[4], 1.0);
+        assert_eq!(sub_mesh.primitives[0].triangles, [0, 0, 1, 0, 2, 0]);
+        assert_eq!(sub_mesh.primitives[0].vertex_groups, [vec![4], vec![5]]);
+        assert_eq!(sub_mesh.bones, [b"ROOT".to_vec()]);
+        assert_eq!(
+            sub_mesh.other,
+            [TaggedChunk {
+                tag: *b"XTRA",
+                data: vec![6]
+            }]
+        );
+
+        assert_eq!(model.helpers.points, [vec![7]]);
+        assert_eq!(model.helpers.dummies, [vec![8]]);
+
+        let mut truncated = file.clone();
+        truncated[4] -= 1;
+        assert!(matches!(
+            TaggedModel::parse(&mut &truncated[..]),
+            Err(TaggedModelError::Chunk(ChildError { parent, .. })) if &parent == b"3DMF"
+        ));
+        assert!(matches!(
+            TaggedModel::parse(&mut &b"3DMX"[..]),
+            Err(TaggedModelError::Magic)
+        ));
+    }
+}
@@ -0,0 +1,1078 @@
+use std::borrow::Cow;
+
+use crate::{
+    big::BigSubHeaderTexture,
+    common::lzo::{self, LzoError},
+};
+
+/// Pixel layout of a texture payload.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextureFormat {
+    Dxt1,
+    Dxt3,
+    Dxt5,
+    /// 32-bit pixels stored as B, G, R, A bytes.
+    Bgra8888,
+    /// 16-bit pixels with 4 bits per channel, alpha in the top bits.
+    Bgra4444,
+    /// 16-bit pixels without alpha.
+    Bgr565,
+}
+
+impl TextureFormat {
+    /// Determines the format of a texture from its sub-header.
+    ///
+    /// The `dxt_compression` code is used when it agrees with the size of the top mip map.
+    /// Otherwise the format is inferred from that size, since it only matches one bit depth.
+    ///
+    /// Both are guesses. The codes 31, 32 and 33 for DXT1, DXT3 and DXT5 are assumed from the
+    /// DXT1 and DXT5 textures seen so far, and the uncompressed formats have no known code, so
+    /// they are told apart by size alone. `transparency` picking between [`Self::Bgr565`] and
+    /// [`Self::Bgra4444`] is unconfirmed too.
+    pub fn from_sub_header(header: &BigSubHeaderTexture) -> Option<Self> {
+        let width = header.width as usize;
+        let height = header.height as usize;
+        let top_mip_map_size = header.top_mip_map_size as usize;
+
+        let from_code = match header.dxt_compression {
+            31 => Some(Self::Dxt1),
+            32 => Some(Self::Dxt3),
+            33 => Some(Self::Dxt5),
+            _ => None,
+        };
+
+        let fits = |format: &Self| format.level_size(width, height) == top_mip_map_size;
+
+        if let Some(format) = from_code.filter(fits) {
+            return Some(format);
+        }
+
+        let inferred = [
+            Self::Dxt1,
+            Self::Dxt5,
+            Self::Bgra8888,
+            if header.transparency == 0 {
+                Self::Bgr565
+            } else {
+                Self::Bgra4444
+            },
+        ]
+        .into_iter()
+        .find(fits);
+
+        inferred.or(from_code)
+    }
+
+    /// The `dxt_compression` code written for this format. Uncompressed formats are written as 0
+    /// and recognized by their size when read back.
+    ///
+    /// The codes are guesses, see [`Self::from_sub_header`].
+    pub fn dxt_compression(self) -> u16 {
+        match self {
+            Self::Dxt1 => 31,
+            Self::Dxt3 => 32,
+            Self::Dxt5 => 33,
+            Self::Bgra8888 | Self::Bgra4444 | Self::Bgr565 => 0,
+        }
+    }
+
+    pub fn is_block_compressed(self) -> bool {
+        matches!(self, Self::Dxt1 | Self::Dxt3 | Self::Dxt5)
+    }
+
+    /// Size in bytes of one image of the given dimensions.
+    pub fn level_size(self, width: usize, height: usize) -> usize {
+        let blocks = width.div_ceil(4).max(1) * height.div_ceil(4).max(1);
+
+        match self {
+            Self::Dxt1 => blocks * 8,
+            Self::Dxt3 | Self::Dxt5 => blocks * 16,
+            Self::Bgra8888 => width * height * 4,
+            Self::Bgra4444 | Self::Bgr565 => width * height * 2,
+        }
+    }
+}
+
+/// The number of mip levels down to 1x1 for an image of the given dimensions, and so the most a
+/// texture can have.
+pub fn max_mip_levels(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// A decoded image with 8-bit RGBA pixels in rows from the top.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextureImage {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextureError {
+    Format {
+        dxt_compression: u16,
+    },
+    /// The top mip map looks LZO compressed, which needs [`decompress_top_mip_map`] first.
+    TopMipMapCompressed,
+    /// The top mip map's LZO stream is corrupt.
+    CompressedTopMipMap(LzoError),
+    /// The top mip map decompressed to a different size than the sub-header gives.
+    CompressedTopMipMapSize(usize),
+    LevelPastEnd {
+        level: usize,
+    },
+    /// The image is empty or larger than a sub-header can describe.
+    Size {
+        width: u32,
+        height: u32,
+    },
+}
+
+impl TextureImage {
+    /// Decodes one image of `format` from the start of `data`.
+    pub fn decode(
+        data: &[u8],
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Option<TextureImage> {
+        let size = format.level_size(width as usize, height as usize);
+        let data = data.get(..size)?;
+
+        let mut image = TextureImage {
+            width,
+            height,
+            rgba: vec![0; width as usize * height as usize * 4],
+        };
+
+        match format {
+            TextureFormat::Dxt1 => image.decode_blocks(data, 8, |block, out| {
+                decode_color_block(block, true, out);
+            }),
+            TextureFormat::Dxt3 => image.decode_blocks(data, 16, |block, out| {
+                decode_color_block(&block[8..], false, out);
+                decode_explicit_alpha_block(&block[..8], out);
+            }),
+            TextureFormat::Dxt5 => image.decode_blocks(data, 16, |block, out| {
+                decode_color_block(&block[8..], false, out);
+                decode_interpolated_alpha_block(&block[..8], out);
+            }),
+            TextureFormat::Bgra8888 => {
+                for (pixel, out) in data.chunks_exact(4).zip(image.rgba.chunks_exact_mut(4)) {
+                    out.copy_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+                }
+            }
+            TextureFormat::Bgra4444 => {
+                for (pixel, out) in data.chunks_exact(2).zip(image.rgba.chunks_exact_mut(4)) {
+                    let v = u16::from_le_bytes([pixel[0], pixel[1]]);
+                    let channel = |shift: u16| ((v >> shift) & 0xf) as u8 * 17;
+                    out.copy_from_slice(&[channel(8), channel(4), channel(0), channel(12)]);
+                }
+            }
+            TextureFormat::Bgr565 => {
+                for (pixel, out) in data.chunks_exact(2).zip(image.rgba.chunks_exact_mut(4)) {
+                    let [r, g, b] = expand_565(u16::from_le_bytes([pixel[0], pixel[1]]));
+                    out.copy_from_slice(&[r, g, b, 255]);
+                }
+            }
+        }
+
+        Some(image)
+    }
+
+    /// Encodes the image as `format`, producing the same bytes [`TextureImage::decode`] reads.
+    pub fn encode(&self, format: TextureFormat) -> Vec<u8> {
+        let mut out =
+            Vec::with_capacity(format.level_size(self.width as usize, self.height as usize));
+
+        match format {
+            TextureFormat::Dxt1 => self.encode_blocks(&mut out, |pixels, out| {
+                encode_color_block(pixels, true, out);
+            }),
+            TextureFormat::Dxt3 => self.encode_blocks(&mut out, |pixels, out| {
+                encode_explicit_alpha_block(pixels, out);
+                encode_color_block(pixels, false, out);
+            }),
+            TextureFormat::Dxt5 => self.encode_blocks(&mut out, |pixels, out| {
+                encode_interpolated_alpha_block(pixels, out);
+                encode_color_block(pixels, false, out);
+            }),
+            TextureFormat::Bgra8888 => {
+                for pixel in self.rgba.chunks_exact(4) {
+                    out.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+                }
+            }
+            TextureFormat::Bgra4444 => {
+                for pixel in self.rgba.chunks_exact(4) {
+                    let channel = |i: usize, shift: u16| (pixel[i] as u16 >> 4) << shift;
+                    let v = channel(3, 12) | channel(0, 8) | channel(1, 4) | channel(2, 0);
+                    out.extend_from_slice(&v.to_le_bytes());
+                }
+            }
+            TextureFormat::Bgr565 => {
+                for pixel in self.rgba.chunks_exact(4) {
+                    out.extend_from_slice(&pack_565(pixel).to_le_bytes());
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Encodes the image in 4x4 blocks, repeating the edge pixels of blocks that hang over the
+    /// right or bottom edge.
+    fn encode_blocks(
+        &self,
+        out: &mut Vec<u8>,
+        encode_block: impl Fn(&[[u8; 4]; 16], &mut Vec<u8>),
+    ) {
+        let width = self.width as usize;
+        let height = self.height as usize;
+
+        for block_y in (0..height.max(1)).step_by(4) {
+            for block_x in (0..width.max(1)).step_by(4) {
+                let mut pixels = [[0; 4]; 16];
+
+                for (i, pixel) in pixels.iter_mut().enumerate() {
+                    let x = (block_x + i % 4).min(width.saturating_sub(1));
+                    let y = (block_y + i / 4).min(height.saturating_sub(1));
+                    let offset = (y * width + x) * 4;
+
+                    if let Some(source) = self.rgba.get(offset..offset + 4) {
+                        pixel.copy_from_slice(source);
+                    }
+                }
+
+                encode_block(&pixels, out);
+            }
+        }
+    }
+
+    /// Halves the image by averaging each 2x2 square of pixels. Dimensions stop shrinking at 1.
+    pub fn downsample(&self) -> TextureImage {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+
+        let mut rgba = Vec::with_capacity(width as usize * height as usize * 4);
+
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = [0u32; 4];
+                let mut count = 0;
+
+                for (sx, sy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let sx = (x * 2 + sx).min(self.width - 1);
+                    let sy = (y * 2 + sy).min(self.height - 1);
+                    let offset = (sy as usize * self.width as usize + sx as usize) * 4;
+
+                    for (channel, value) in sum.iter_mut().zip(&self.rgba[offset..offset + 4]) {
+                        *channel += *value as u32;
+                    }
+
+                    count += 1;
+                }
+
+                rgba.extend(sum.map(|channel| ((channel + count / 2) / count) as u8));
+            }
+        }
+
+        TextureImage {
+            width,
+            height,
+            rgba,
+        }
+    }
+
+    /// Decodes 4x4 blocks into the image, clipping blocks that hang over the right or bottom edge.
+    fn decode_blocks(
+        &mut self,
+        data: &[u8],
+        block_size: usize,
+        decode_block: impl Fn(&[u8], &mut [[u8; 4]; 16]),
+    ) {
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let blocks_wide = width.div_ceil(4).max(1);
+
+        for (block_index, block) in data.chunks_exact(block_size).enumerate() {
+            let mut pixels = [[0; 4]; 16];
+            decode_block(block, &mut pixels);
+
+            let block_x = (block_index % blocks_wide) * 4;
+            let block_y = (block_index / blocks_wide) * 4;
+
+            for (i, pixel) in pixels.iter().enumerate() {
+                let x = block_x + i % 4;
+                let y = block_y + i / 4;
+
+                if x < width && y < height {
+                    let offset = (y * width + x) * 4;
+                    self.rgba[offset..offset + 4].copy_from_slice(pixel);
+                }
+            }
+        }
+    }
+
+    /// Copies out the region of the image starting at `x`, `y`. The region must lie inside it.
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> TextureImage {
+        let mut rgba = Vec::with_capacity(width as usize * height as usize * 4);
+
+        for row in y..y + height {
+            let start = (row as usize * self.width as usize + x as usize) * 4;
+            rgba.extend_from_slice(&self.rgba[start..start + width as usize * 4]);
+        }
+
+        TextureImage {
+            width,
+            height,
+            rgba,
+        }
+    }
+
+    /// Splits a sheet of animation frames into separate images.
+    ///
+    /// Frames are laid out left to right, then top to bottom. An image that holds a single frame
+    /// is returned as is.
+    pub fn frames(&self, frame_width: u32, frame_height: u32, frame_count: u32) -> Vec<Self> {
+        if frame_count <= 1
+            || frame_width == 0
+            || frame_height == 0
+            || frame_width > self.width
+            || frame_height > self.height
+        {
+            return vec![self.clone()];
+        }
+
+        let columns = self.width / frame_width;
+        let rows = self.height / frame_height;
+
+        (0..frame_count.min(columns * rows))
+            .map(|frame| {
+                let x = (frame % columns) * frame_width;
+                let y = (frame / columns) * frame_height;
+                self.crop(x, y, frame_width, frame_height)
+            })
+            .collect()
+    }
+}
+
+/// Whether the sub-header gives the top mip map a compressed size of its own, which is taken to
+/// mean it is stored LZO compressed.
+pub fn is_top_mip_map_compressed(header: &BigSubHeaderTexture) -> bool {
+    header.top_mip_map_compressed_size != 0
+        && header.top_mip_map_compressed_size != header.top_mip_map_size
+}
+
+/// LZO decompresses the top mip map of a payload, returning a sub-header and payload that
+/// [`decode_mip_levels`] accepts. Payloads whose top mip map isn't compressed are returned as is.
+///
+/// That a compressed size of its own means LZO is unconfirmed, so nothing decompresses unless
+/// asked to through this function.
+pub fn decompress_top_mip_map<'a>(
+    header: &BigSubHeaderTexture,
+    payload: &'a [u8],
+) -> Result<(BigSubHeaderTexture, Cow<'a, [u8]>), TextureError> {
+    if !is_top_mip_map_compressed(header) {
+        return Ok((header.clone(), Cow::Borrowed(payload)));
+    }
+
+    let compressed_size = header.top_mip_map_compressed_size as usize;
+    let size = header.top_mip_map_size as usize;
+
+    let compressed = payload
+        .get(..compressed_size)
+        .ok_or(TextureError::LevelPastEnd { level: 0 })?;
+
+    let rest = &payload[compressed_size..];
+
+    // The sizes come from the file, so they only hint at the capacity.
+    let mut decompressed = Vec::with_capacity(size.saturating_add(rest.len()).min(payload.len()));
+
+    lzo::decompress_into(compressed, &mut decompressed)
+        .map_err(TextureError::CompressedTopMipMap)?;
+
+    if decompressed.len() != size {
+        return Err(TextureError::CompressedTopMipMapSize(decompressed.len()));
+    }
+
+    decompressed.extend_from_slice(rest);
+
+    let header = BigSubHeaderTexture {
+        top_mip_map_compressed_size: 0,
+        ..header.clone()
+    };
+
+    Ok((header, Cow::Owned(decompressed)))
+}
+
+/// Decodes every mip level of a texture payload, largest first.
+///
+/// Levels missing from the end of the payload are left out, as long as the top level is present,
+/// as are levels past 1x1 that a bad `mip_maps` asks for. A compressed top mip map is an error.
+pub fn decode_mip_levels(
+    header: &BigSubHeaderTexture,
+    payload: &[u8],
+) -> Result<Vec<TextureImage>, TextureError> {
+    let format = TextureFormat::from_sub_header(header).ok_or(TextureError::Format {
+        dxt_compression: header.dxt_compression,
+    })?;
+
+    if is_top_mip_map_compressed(header) {
+        return Err(TextureError::TopMipMapCompressed);
+    }
+
+    let mut levels = Vec::new();
+    let mut inp = payload;
+
+    let level_count = (header.mip_maps.max(1) as u32)
+        .min(max_mip_levels(header.width as u32, header.height as u32));
+
+    for level in 0..level_count as usize {
+        let width = (header.width as u32 >> level).max(1);
+        let height = (header.height as u32 >> level).max(1);
+
+        let image = match TextureImage::decode(inp, format, width, height) {
+            Some(image) => image,
+            None if level > 0 => break,
+            None => return Err(TextureError::LevelPastEnd { level }),
+        };
+
+        inp = &inp[format.level_size(width as usize, height as usize)..];
+
+        levels.push(image);
+    }
+
+    Ok(levels)
+}
+
+/// Builds a texture payload and its sub-header from an image, the reverse of
+/// [`decode_mip_levels`].
+///
+/// The mip chain is generated by repeated [`TextureImage::downsample`], stopping after `mip_maps`
+/// levels or at 1x1. The top mip map is stored uncompressed. `transparency` is 1 when any pixel is
+/// not fully opaque and the format keeps alpha. Fields whose meaning is unknown are left at 0, so
+/// copy them from the original sub-header when replacing a texture.
+pub fn encode_texture(
+    image: &TextureImage,
+    format: TextureFormat,
+    mip_maps: u8,
+) -> Result<(BigSubHeaderTexture, Vec<u8>), TextureError> {
+    let size_error = TextureError::Size {
+        width: image.width,
+        height: image.height,
+    };
+
+    if image.width == 0 || image.height == 0 {
+        return Err(size_error);
+    }
+
+    let width = u16::try_from(image.width).map_err(|_| size_error)?;
+    let height = u16::try_from(image.height).map_err(|_| size_error)?;
+
+    let level_count = (mip_maps.max(1) as u32).min(max_mip_levels(image.width, image.height));
+
+    let mut payload = Vec::new();
+    let mut level = image.clone();
+    let mut top_mip_map_size = 0;
+
+    for i in 0..level_count {
+        if i > 0 {
+            level = level.downsample();
+        }
+
+        let encoded = level.encode(format);
+
+        if i == 0 {
+            top_mip_map_size = encoded.len() as u32;
+        }
+
+        payload.extend(encoded);
+    }
+
+    let transparency = (format != TextureFormat::Bgr565
+        && image.rgba.chunks_exact(4).any(|pixel| pixel[3] != 255)) as u8;
+
+    let header = BigSubHeaderTexture {
+        width,
+        height,
+        depth: 1,
+        frame_width: width,
+        frame_height: height,
+        frame_count: 1,
+        dxt_compression: format.dxt_compression(),
+        unknown1: 0,
+        transparency,
+        mip_maps: level_count as u8,
+        unknown2: 0,
+        top_mip_map_size,
+        top_mip_map_compressed_size: 0,
+        unknown3: 0,
+        unknown4: 0,
+    };
+
+    Ok((header, payload))
+}
+
+fn expand_565(v: u16) -> [u8; 3] {
+    let r = ((v >> 11) & 0x1f) as u8;
+    let g = ((v >> 5) & 0x3f) as u8;
+    let b = (v & 0x1f) as u8;
+
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+    ]
+}
+
+/// Decodes the color half of a DXT block. Only DXT1 blocks may use the three color mode with
+/// transparent black.
+fn decode_color_block(block: &[u8], allow_transparent: bool, out: &mut [[u8; 4]; 16]) {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+
+    let [r0, g0, b0] = expand_565(c0).map(u32::from);
+    let [r1, g1, b1] = expand_565(c1).map(u32::from);
+
+    let mix = |a: u32, b: u32, wa: u32, wb: u32| ((a * wa + b * wb) / (wa + wb)) as u8;
+
+    let colors = if c0 > c1 || !allow_transparent {
+        [
+            [r0 as u8, g0 as u8, b0 as u8, 255],
+            [r1 as u8, g1 as u8, b1 as u8, 255],
+            [mix(r0, r1, 2, 1), mix(g0, g1, 2, 1), mix(b0, b1, 2, 1), 255],
+            [mix(r0, r1, 1, 2), mix(g0, g1, 1, 2), mix(b0, b1, 1, 2), 255],
+        ]
+    } else {
+        [
+            [r0 as u8, g0 as u8, b0 as u8, 255],
+            [r1 as u8, g1 as u8, b1 as u8, 255],
+            [mix(r0, r1, 1, 1), mix(g0, g1, 1, 1), mix(b0, b1, 1, 1), 255],
+            [0, 0, 0, 0],
+        ]
+    };
+
+    for (i, pixel) in out.iter_mut().enumerate() {
+        *pixel = colors[((indices >> (i * 2)) & 0b11) as usize];
+    }
+}
+
+/// DXT3 alpha: 4 bits per pixel.
+fn decode_explicit_alpha_block(block: &[u8], out: &mut [[u8; 4]; 16]) {
+    let bits = u64::from_le_bytes(block[..8].try_into().unwrap());
+
+    for (i, pixel) in out.iter_mut().enumerate() {
+        pixel[3] = ((bits >> (i * 4)) & 0xf) as u8 * 17;
+    }
+}
+
+/// DXT5 alpha: two endpoints and 3-bit indices into the values between them.
+fn decode_interpolated_alpha_block(block: &[u8], out: &mut [[u8; 4]; 16]) {
+    let a0 = block[0] as u32;
+    let a1 = block[1] as u32;
+
+    let mut index_bytes = [0; 8];
+    index_bytes[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(index_bytes);
+
+    let mut alphas = [0u8; 8];
+    alphas[0] = a0 as u8;
+    alphas[1] = a1 as u8;
+
+    if a0 > a1 {
+        for i in 1..7 {
+            alphas[i + 1] = (((7 - i as u32) * a0 + i as u32 * a1) / 7) as u8;
+        }
+    } else {
+        for i in 1..5 {
+            alphas[i + 1] = (((5 - i as u32) * a0 + i as u32 * a1) / 5) as u8;
+        }
+        alphas[6] = 0;
+        alphas[7] = 255;
+    }
+
+    for (i, pixel) in out.iter_mut().enumerate() {
+        pixel[3] = alphas[((indices >> (i * 3)) & 0b111) as usize];
+    }
+}
+
+fn pack_565(pixel: &[u8]) -> u16 {
+    let r = (pixel[0] as u16 * 31 + 127) / 255;
+    let g = (pixel[1] as u16 * 63 + 127) / 255;
+    let b = (pixel[2] as u16 * 31 + 127) / 255;
+
+    (r << 11) | (g << 5) | b
+}
+
+fn color_distance(a: [u8; 3], b: [u8; 3]) -> u32 {
+    a.iter()
+        .zip(&b)
+        .map(|(a, b)| (*a as i32 - *b as i32).pow(2) as u32)
+        .sum()
+}
+
+/// Encodes the color half of a DXT block, using the two colors furthest apart as the endpoints.
+///
+/// With `allow_transparent`, blocks with pixels below half alpha use the three color mode and
+/// encode those pixels as transparent black, as DXT1 expects.
+fn encode_color_block(pixels: &[[u8; 4]; 16], allow_transparent: bool, out: &mut Vec<u8>) {
+    let is_transparent = |pixel: &[u8; 4]| allow_transparent && pixel[3] < 128;
+
+    let colors: Vec<[u8; 3]> = pixels
+        .iter()
+        .filter(|pixel| !is_transparent(pixel))
+        .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+        .collect();
+
+    // No colors when every pixel is transparent, which leaves both endpoints black.
+    let (first, second) = colors
+        .iter()
+        .flat_map(|a| colors.iter().map(move |b| (*a, *b)))
+        .max_by_key(|(a, b)| color_distance(*a, *b))
+        .unwrap_or_default();
+
+    let has_transparent = pixels.iter().any(is_transparent);
+
+    let (mut c0, mut c1) = (pack_565(&first), pack_565(&second));
+
+    let three_color = has_transparent;
+
+    // Four color mode needs c0 > c1 and three color mode needs c0 <= c1.
+    if (three_color && c0 > c1) || (!three_color && c0 < c1) {
+        (c0, c1) = (c1, c0);
+    }
+
+    let [r0, g0, b0] = expand_565(c0).map(u32::from);
+    let [r1, g1, b1] = expand_565(c1).map(u32::from);
+
+    let mix = |a: u32, b: u32, wa: u32, wb: u32| ((a * wa + b * wb) / (wa + wb)) as u8;
+
+    let palette: Vec<[u8; 3]> = if three_color {
+        vec![
+            [r0 as u8, g0 as u8, b0 as u8],
+            [r1 as u8, g1 as u8, b1 as u8],
+            [mix(r0, r1, 1, 1), mix(g0, g1, 1, 1), mix(b0, b1, 1, 1)],
+        ]
+    } else if c0 == c1 {
+        vec![[r0 as u8, g0 as u8, b0 as u8]]
+    } else {
+        vec![
+            [r0 as u8, g0 as u8, b0 as u8],
+            [r1 as u8, g1 as u8, b1 as u8],
+            [mix(r0, r1, 2, 1), mix(g0, g1, 2, 1), mix(b0, b1, 2, 1)],
+            [mix(r0, r1, 1, 2), mix(g0, g1, 1, 2), mix(b0, b1, 1, 2)],
+        ]
+    };
+
+    let mut indices = 0u32;
+
+    for (i, pixel) in pixels.iter().enumerate() {
+        let index = if is_transparent(pixel) {
+            3
+        } else {
+            let color = [pixel[0], pixel[1], pixel[2]];
+
+            (0..palette.len())
+                .min_by_key(|&index| color_distance(palette[index], color))
+                .unwrap_or(0) as u32
+        };
+
+        indices |= index << (i * 2);
+    }
+
+    out.extend_from_slice(&c0.to_le_bytes());
+    out.extend_from_slice(&c1.to_le_bytes());
+    out.extend_from_slice(&indices.to_le_bytes());
+}
+
+fn encode_explicit_alpha_block(pixels: &[[u8; 4]; 16], out: &mut Vec<u8>) {
+    let mut bits = 0u64;
+
+    for (i, pixel) in pixels.iter().enumerate() {
+        bits |= ((pixel[3] as u64 + 8) / 17) << (i * 4);
+    }
+
+    out.extend_from_slice(&bits.to_le_bytes());
+}
+
+/// Encodes DXT5 alpha with the block's lowest and highest alpha as endpoints, in the eight value
+/// mode.
+fn encode_interpolated_alpha_block(pixels: &[[u8; 4]; 16], out: &mut Vec<u8>) {
+    let a0 = pixels.iter().map(|pixel| pixel[3]).max().unwrap_or(255);
+    let a1 = pixels.iter().map(|pixel| pixel[3]).min().unwrap_or(255);
+
+    let mut alphas = [a0, a1, 0, 0, 0, 0, 0, 0];
+
+    for i in 1..7 {
+        alphas[i + 1] = (((7 - i as u32) * a0 as u32 + i as u32 * a1 as u32) / 7) as u8;
+    }
+
+    let mut indices = 0u64;
+
+    if a0 > a1 {
+        for (i, pixel) in pixels.iter().enumerate() {
+            let index = (0..8)
+                .min_by_key(|&index| (alphas[index] as i32 - pixel[3] as i32).unsigned_abs())
+                .unwrap_or(0) as u64;
+
+            indices |= index << (i * 3);
+        }
+    }
+
+    out.extend_from_slice(&[a0, a1]);
+    out.extend_from_slice(&indices.to_le_bytes()[..6]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: u16 = 0xf800;
+    const BLUE: u16 = 0x001f;
+
+    fn color_block(c0: u16, c1: u16, indices: u32) -> Vec<u8> {
+        [c0.to_le_bytes(), c1.to_le_bytes()]
+            .concat()
+            .into_iter()
+            .chain(indices.to_le_bytes())
+            .collect()
+    }
+
+    fn decode_pixels(data: &[u8], format: TextureFormat) -> Vec<[u8; 4]> {
+        TextureImage::decode(data, format, 4, 4)
+            .unwrap()
+            .rgba
+            .chunks_exact(4)
+            .map(|pixel| pixel.try_into().unwrap())
+            .collect()
+    }
+
+    /// Indices 0, 1, 2, 3 repeated over the block's pixels.
+    const EACH_INDEX: u32 = 0xe4e4_e4e4;
+
+    #[test]
+    fn decode_dxt1_blocks() {
+        let pixels = decode_pixels(&color_block(RED, BLUE, EACH_INDEX), TextureFormat::Dxt1);
+
+        assert_eq!(
+            pixels[..4],
+            [
+                [255, 0, 0, 255],
+                [0, 0, 255, 255],
+                [170, 0, 85, 255],
+                [85, 0, 170, 255],
+            ]
+        );
+
+        // c0 <= c1 selects three colors and transparent black.
+        let pixels = decode_pixels(&color_block(BLUE, RED, EACH_INDEX), TextureFormat::Dxt1);
+
+        assert_eq!(
+            pixels[..4],
+            [
+                [0, 0, 255, 255],
+                [255, 0, 0, 255],
+                [127, 0, 127, 255],
+                [0, 0, 0, 0],
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_dxt3_and_dxt5_blocks() {
+        // Alpha 0, 1, 2 ... 15 in steps of 17.
+        let alpha = 0xfedc_ba98_7654_3210u64.to_le_bytes();
+        let block = [&alpha[..], &color_block(BLUE, RED, 0)].concat();
+        let pixels = decode_pixels(&block, TextureFormat::Dxt3);
+
+        // DXT3 never uses the three color mode, so c0 <= c1 still gives opaque colors.
+        for (i, pixel) in pixels.iter().enumerate() {
+            assert_eq!(*pixel, [0, 0, 255, i as u8 * 17]);
+        }
+
+        // Eight value mode: indices 0 to 7 in the first eight pixels.
+        let mut alpha = [255, 0, 0, 0, 0, 0, 0, 0];
+        alpha[2..].copy_from_slice(&0o76543210u64.to_le_bytes()[..6]);
+        let block = [&alpha[..], &color_block(RED, BLUE, 0)].concat();
+        let alphas: Vec<u8> = decode_pixels(&block, TextureFormat::Dxt5)
+            .iter()
+            .map(|pixel| pixel[3])
+            .collect();
+
+        assert_eq!(alphas[..8], [255, 0, 218, 182, 145, 109, 72, 36]);
+
+        // Six value mode with fixed 0 and 255.
+        alpha[..2].copy_from_slice(&[0, 255]);
+        let block = [&alpha[..], &color_block(RED, BLUE, 0)].concat();
+        let alphas: Vec<u8> = decode_pixels(&block, TextureFormat::Dxt5)
+            .iter()
+            .map(|pixel| pixel[3])
+            .collect();
+
+        assert_eq!(alphas[..8], [0, 255, 51, 102, 153, 204, 0, 255]);
+    }
+
+    #[test]
+    fn decode_uncompressed_pixels() {
+        let decode = |data: &[u8], format| TextureImage::decode(data, format, 1, 1).unwrap().rgba;
+
+        assert_eq!(decode(&[1, 2, 3, 4], TextureFormat::Bgra8888), [3, 2, 1, 4]);
+        assert_eq!(
+            decode(&0x8f30u16.to_le_bytes(), TextureFormat::Bgra4444),
+            [255, 51, 0, 136]
+        );
+        assert_eq!(
+            decode(&(RED | 0x07e0).to_le_bytes(), TextureFormat::Bgr565),
+            [255, 255, 0, 255]
+        );
+
+        assert_eq!(
+            TextureImage::decode(&[0; 3], TextureFormat::Bgra8888, 1, 1),
+            None
+        );
+    }
+
+    /// An image of `width` by `height` whose pixels are chosen by `pixel(x, y)`.
+    fn image(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [u8; 4]) -> TextureImage {
+        let rgba = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| pixel(x, y))
+            .collect();
+
+        TextureImage {
+            width,
+            height,
+            rgba,
+        }
+    }
+
+    #[test]
+    fn encode_and_decode_exactly() {
+        // Colors every format stores without loss, in two colors per block.
+        let pixel = |x: u32, y: u32| {
+            if (x + y).is_multiple_of(2) {
+                [255, 0, 0, 255]
+            } else {
+                [0, 0, 255, 255]
+            }
+        };
+
+        let formats = [
+            TextureFormat::Dxt1,
+            TextureFormat::Dxt3,
+            TextureFormat::Dxt5,
+            TextureFormat::Bgra8888,
+            TextureFormat::Bgra4444,
+            TextureFormat::Bgr565,
+        ];
+
+        for format in formats {
+            // 6x5 leaves partial blocks on the right and bottom edges.
+            let image = image(6, 5, pixel);
+            let encoded = image.encode(format);
+
+            assert_eq!(encoded.len(), format.level_size(6, 5), "{:?}", format);
+            assert_eq!(
+                TextureImage::decode(&encoded, format, 6, 5),
+                Some(image),
+                "{:?}",
+                format
+            );
+        }
+
+        let translucent = image(4, 4, |x, y| [17 * x as u8, 34, 17 * y as u8, 85]);
+
+        for format in [TextureFormat::Bgra8888, TextureFormat::Bgra4444] {
+            let encoded = translucent.encode(format);
+            assert_eq!(
+                TextureImage::decode(&encoded, format, 4, 4).as_ref(),
+                Some(&translucent)
+            );
+        }
+    }
+
+    #[test]
+    fn encode_and_decode_dxt_closely() {
+        // Colors along a line, as DXT endpoints can only interpolate between two colors.
+        let gradient = image(8, 8, |x, y| {
+            [
+                x as u8 * 32,
+                x as u8 * 16,
+                255 - x as u8 * 32,
+                (x + y) as u8 * 16,
+            ]
+        });
+
+        for format in [
+            TextureFormat::Dxt1,
+            TextureFormat::Dxt3,
+            TextureFormat::Dxt5,
+        ] {
+            let decoded = TextureImage::decode(&gradient.encode(format), format, 8, 8).unwrap();
+
+            for (pixel, expected) in decoded
+                .rgba
+                .chunks_exact(4)
+                .zip(gradient.rgba.chunks_exact(4))
+            {
+                let expected_alpha = match format {
+                    // DXT1 only keeps whether a pixel is at least half opaque, and makes the
+                    // rest transparent black.
+                    TextureFormat::Dxt1 if expected[3] < 128 => {
+                        assert_eq!(pixel, [0; 4]);
+                        continue;
+                    }
+                    TextureFormat::Dxt1 => 255,
+                    _ => expected[3],
+                };
+
+                for channel in 0..3 {
+                    let error = (pixel[channel] as i32 - expected[channel] as i32).abs();
+                    assert!(error <= 24, "{:?} {:?} {:?}", format, pixel, expected);
+                }
+
+                let error = (pixel[3] as i32 - expected_alpha as i32).abs();
+                assert!(error <= 9, "{:?} {:?} {:?}", format, pixel, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn encode_and_decode_texture() {
+        let source = image(16, 8, |x, y| [x as u8 * 16, y as u8 * 32, 0, 255]);
+
+        for format in [
+            TextureFormat::Dxt1,
+            TextureFormat::Dxt5,
+            TextureFormat::Bgra8888,
+        ] {
+            let (header, payload) = encode_texture(&source, format, 10).unwrap();
+
+            assert_eq!(header.mip_maps, 5);
+            assert_eq!(header.transparency, 0);
+            assert_eq!(TextureFormat::from_sub_header(&header), Some(format));
+
+            let levels = decode_mip_levels(&header, &payload).unwrap();
+
+            let sizes: Vec<_> = levels.iter().map(|x| (x.width, x.height)).collect();
+            assert_eq!(sizes, [(16, 8), (8, 4), (4, 2), (2, 1), (1, 1)]);
+
+            if format == TextureFormat::Bgra8888 {
+                assert_eq!(levels[0], source);
+                assert_eq!(levels[1], source.downsample());
+            }
+        }
+
+        assert!(encode_texture(&image(0, 4, |_, _| [0; 4]), TextureFormat::Dxt1, 1).is_err());
+    }
+
+    #[test]
+    fn decompress_top_mip_map_on_request() {
+        let source = image(8, 8, |x, y| [x as u8 * 32, y as u8 * 32, 0, 255]);
+        let (header, payload) = encode_texture(&source, TextureFormat::Bgra8888, 2).unwrap();
+
+        let top_size = header.top_mip_map_size as usize;
+        let mut compressed = lzo::compress(&payload[..top_size]);
+        let compressed_size = compressed.len() as u32;
+        compressed.extend_from_slice(&payload[top_size..]);
+
+        let compressed_header = BigSubHeaderTexture {
+            top_mip_map_compressed_size: compressed_size,
+            ..header.clone()
+        };
+
+        // Nothing decompresses on its own.
+        assert_eq!(
+            decode_mip_levels(&compressed_header, &compressed),
+            Err(TextureError::TopMipMapCompressed)
+        );
+
+        let (decompressed_header, decompressed) =
+            decompress_top_mip_map(&compressed_header, &compressed).unwrap();
+
+        assert_eq!(decompressed_header, header);
+        assert_eq!(decompressed, payload);
+        assert_eq!(
+            decode_mip_levels(&decompressed_header, &decompressed).unwrap()[0],
+            source
+        );
+
+        // Uncompressed payloads are borrowed as is.
+        let (_, same) = decompress_top_mip_map(&header, &payload).unwrap();
+        assert!(matches!(same, Cow::Borrowed(_)));
+
+        let wrong_size = BigSubHeaderTexture {
+            top_mip_map_size: header.top_mip_map_size + 1,
+            ..compressed_header.clone()
+        };
+        assert_eq!(
+            decompress_top_mip_map(&wrong_size, &compressed).unwrap_err(),
+            TextureError::CompressedTopMipMapSize(top_size)
+        );
+    }
+
+    #[test]
+    fn format_from_size() {
+        let mut header = BigSubHeaderTexture {
+            width: 8,
+            height: 8,
+            depth: 1,
+            frame_width: 8,
+            frame_height: 8,
+            frame_count: 1,
+            dxt_compression: 0,
+            unknown1: 0,
+            transparency: 0,
+            mip_maps: 1,
+            unknown2: 0,
+            top_mip_map_size: 128,
+            top_mip_map_compressed_size: 0,
+            unknown3: 0,
+            unknown4: 0,
+        };
+
+        assert_eq!(
+            TextureFormat::from_sub_header(&header),
+            Some(TextureFormat::Bgr565)
+        );
+
+        header.transparency = 1;
+        assert_eq!(
+            TextureFormat::from_sub_header(&header),
+            Some(TextureFormat::Bgra4444)
+        );
+
+        // A code that disagrees with the size loses to it.
+        header.dxt_compression = 31;
+        header.top_mip_map_size = 256;
+        assert_eq!(
+            TextureFormat::from_sub_header(&header),
+            Some(TextureFormat::Bgra8888)
+        );
+
+        header.top_mip_map_size = 100;
+        assert_eq!(
+            TextureFormat::from_sub_header(&header),
+            Some(TextureFormat::Dxt1)
+        );
+
+        header.dxt_compression = 0;
+        assert_eq!(TextureFormat::from_sub_header(&header), None);
+
+        // 16 bytes is both 2x2 DXT3/DXT5 and 2x2 at 32 bits, which the code settles.
+        header.width = 2;
+        header.height = 2;
+        header.top_mip_map_size = 16;
+        assert_eq!(
+            TextureFormat::from_sub_header(&header),
+            Some(TextureFormat::Dxt5)
+        );
+
+        header.dxt_compression = 32;
+        assert_eq!(
+            TextureFormat::from_sub_header(&header),
+            Some(TextureFormat::Dxt3)
+        );
+    }
+}
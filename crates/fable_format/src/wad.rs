@@ -1,7 +1,13 @@
 use crate::common::bytes::{put, put_bytes, take, take_bytes, TakeError, UnexpectedEnd};
 use derive_more::Display;
 use serde::{Deserialize, Serialize};
-use std::{mem, num::TryFromIntError, str::Utf8Error};
+use std::{
+    io::{self, Read, Seek, SeekFrom, Write},
+    mem,
+    num::TryFromIntError,
+    str::Utf8Error,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 #[derive(Debug, Copy, Clone, Default, Serialize, Deserialize)]
 pub struct WadHeader {
@@ -24,6 +30,22 @@ pub enum WadHeaderError<E> {
 }
 
 impl WadHeader {
+    pub const MAGIC: [u8; 4] = *b"BBBB";
+    pub const VERSION: [u32; 3] = [1, 0, 0];
+    pub const BLOCK_SIZE: u32 = 2048;
+
+    /// Where `entry_count_repeated` starts in the header.
+    pub const ENTRY_COUNT_REPEATED_OFFSET: usize =
+        mem::size_of::<[u8; 4]>() + mem::size_of::<[u32; 3]>() + 2 * mem::size_of::<u32>();
+
+    /// Where `first_entry_position` starts in the header.
+    pub const FIRST_ENTRY_POSITION_OFFSET: usize =
+        Self::ENTRY_COUNT_REPEATED_OFFSET + mem::size_of::<u32>();
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, WadHeaderError<TakeError>> {
+        Self::parse(&mut bytes)
+    }
+
     pub fn parse(inp: &mut &[u8]) -> Result<Self, WadHeaderError<TakeError>> {
         use WadHeaderError::*;
 
@@ -84,9 +106,9 @@ pub struct WadEntry<'a> {
     pub unknown_3: u32,
     pub path: &'a str,
     pub unknown_4: [u8; 16],
-    pub created: [u32; 7],
-    pub accessed: [u32; 7],
-    pub modified: [u32; 5],
+    pub created: WadTimestamp,
+    pub accessed: WadTimestamp,
+    pub modified: WadTimestamp,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -99,9 +121,9 @@ pub struct WadEntryOwned {
     pub unknown_3: u32,
     pub path: String,
     pub unknown_4: [u8; 16],
-    pub created: [u32; 7],
-    pub accessed: [u32; 7],
-    pub modified: [u32; 5],
+    pub created: WadTimestamp,
+    pub accessed: WadTimestamp,
+    pub modified: WadTimestamp,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -123,7 +145,42 @@ pub enum WadEntryError<E> {
 }
 
 impl<'a> WadEntry<'a> {
+    /// Where the path starts in an entry, right after its length.
+    pub const PATH_OFFSET: usize = mem::size_of::<[u8; 16]>() + 6 * mem::size_of::<u32>();
+
+    /// The size of the fields after the path.
+    pub const AFTER_PATH_SIZE: usize =
+        mem::size_of::<[u8; 16]>() + 2 * mem::size_of::<[u32; 7]>() + mem::size_of::<[u32; 5]>();
+
+    pub fn from_bytes(mut bytes: &'a [u8]) -> Result<WadEntry<'a>, WadEntryError<TakeError>> {
+        Self::parse(&mut bytes)
+    }
+
     pub fn parse(inp: &mut &'a [u8]) -> Result<WadEntry<'a>, WadEntryError<TakeError>> {
+        Self::parse_with_path(inp, |path| {
+            std::str::from_utf8(path).map_err(WadEntryError::PathString)
+        })
+    }
+
+    /// Parses an entry without decoding its path, e.g. to carry on past an entry whose path isn't
+    /// UTF-8. The path's bytes are returned next to the entry, whose own path is left empty.
+    pub fn parse_without_path(
+        inp: &mut &'a [u8],
+    ) -> Result<(WadEntry<'a>, &'a [u8]), WadEntryError<TakeError>> {
+        let mut path_bytes: &[u8] = &[];
+
+        let entry = Self::parse_with_path(inp, |path| {
+            path_bytes = path;
+            Ok("")
+        })?;
+
+        Ok((entry, path_bytes))
+    }
+
+    fn parse_with_path(
+        inp: &mut &'a [u8],
+        decode_path: impl FnOnce(&'a [u8]) -> Result<&'a str, WadEntryError<TakeError>>,
+    ) -> Result<WadEntry<'a>, WadEntryError<TakeError>> {
         use WadEntryError::*;
 
         let unknown_1 = take::<[u8; 16]>(inp).map_err(Unknown1)?;
@@ -136,13 +193,16 @@ impl<'a> WadEntry<'a> {
         let path_len =
             usize::try_from(take::<u32>(inp).map_err(PathLen)?.to_le()).map_err(PathLenInt)?;
         let path = take_bytes(inp, path_len).map_err(|e| Path(TakeError::UnexpectedEnd(e)))?;
-        let path = std::str::from_utf8(path).map_err(PathString)?;
+        let path = decode_path(path)?;
 
         let unknown_4 = take::<[u8; 16]>(inp).map_err(Unknown4)?;
 
         let created = take::<[u32; 7]>(inp).map_err(Created)?.map(u32::to_le);
+        let created = WadTimestamp::from(created);
         let accessed = take::<[u32; 7]>(inp).map_err(Accessed)?.map(u32::to_le);
+        let accessed = WadTimestamp::from(accessed);
         let modified = take::<[u32; 5]>(inp).map_err(Modified)?.map(u32::to_le);
+        let modified = WadTimestamp::from(modified);
 
         Ok(WadEntry {
             unknown_1,
@@ -176,9 +236,9 @@ impl<'a> WadEntry<'a> {
         put_bytes(out, &self.path.as_bytes()).map_err(Path)?;
 
         put(out, &self.unknown_4).map_err(Unknown4)?;
-        put(out, &self.created.map(u32::to_le)).map_err(Created)?;
-        put(out, &self.accessed.map(u32::to_le)).map_err(Accessed)?;
-        put(out, &self.modified.map(u32::to_le)).map_err(Modified)?;
+        put(out, &<[u32; 7]>::from(self.created).map(u32::to_le)).map_err(Created)?;
+        put(out, &<[u32; 7]>::from(self.accessed).map(u32::to_le)).map_err(Accessed)?;
+        put(out, &<[u32; 5]>::from(self.modified).map(u32::to_le)).map_err(Modified)?;
 
         Ok(())
     }
@@ -226,3 +286,679 @@ impl<'a> WadEntry<'a> {
         }
     }
 }
+
+impl WadEntryOwned {
+    /// The entry's metadata, keeping its data at the same offset.
+    pub fn metadata(&self) -> WadEntryMetadata {
+        WadEntryMetadata {
+            unknown_1: self.unknown_1,
+            id: self.id,
+            unknown_2: self.unknown_2,
+            unknown_3: self.unknown_3,
+            unknown_4: self.unknown_4,
+            created: self.created,
+            accessed: self.accessed,
+            modified: self.modified,
+            offset: Some(self.offset),
+        }
+    }
+
+    pub fn as_entry(&self) -> WadEntry<'_> {
+        WadEntry {
+            unknown_1: self.unknown_1,
+            id: self.id,
+            unknown_2: self.unknown_2,
+            length: self.length,
+            offset: self.offset,
+            unknown_3: self.unknown_3,
+            path: &self.path,
+            unknown_4: self.unknown_4,
+            created: self.created,
+            accessed: self.accessed,
+            modified: self.modified,
+        }
+    }
+}
+
+/// Date and time of an entry, laid out like Windows' `SYSTEMTIME` without the day of the week.
+///
+/// Creation and access times are stored with all seven fields. Modification times are stored
+/// without the second and millisecond, which are dropped when serializing.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct WadTimestamp {
+    pub year: u32,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub millisecond: u32,
+}
+
+impl WadTimestamp {
+    /// Converts a system time to a UTC timestamp. Times before 1970 become the Unix epoch.
+    pub fn from_system_time(time: SystemTime) -> Self {
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let seconds = since_epoch.as_secs();
+        let days = seconds / 86400;
+        let seconds_of_day = seconds % 86400;
+
+        // Howard Hinnant's `civil_from_days`, shifted so the year starts in March.
+        let days = days + 719468;
+        let era = days / 146097;
+        let day_of_era = days % 146097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+        Self {
+            year: year as u32,
+            month: month as u32,
+            day: day as u32,
+            hour: (seconds_of_day / 3600) as u32,
+            minute: (seconds_of_day % 3600 / 60) as u32,
+            second: (seconds_of_day % 60) as u32,
+            millisecond: since_epoch.subsec_millis(),
+        }
+    }
+
+    /// Converts the timestamp, taken as UTC, to a system time. Returns `None` for dates before 1970
+    /// or with fields out of range, like a day past the end of its month.
+    pub fn to_system_time(&self) -> Option<SystemTime> {
+        let leap_year = self.year.is_multiple_of(4)
+            && (!self.year.is_multiple_of(100) || self.year.is_multiple_of(400));
+
+        let month_days = match self.month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if leap_year => 29,
+            2 => 28,
+            _ => return None,
+        };
+
+        if !(1..=month_days).contains(&self.day)
+            || self.hour > 23
+            || self.minute > 59
+            || self.second > 59
+            || self.millisecond > 999
+        {
+            return None;
+        }
+
+        // Howard Hinnant's `days_from_civil`.
+        let year = self.year as i64 - i64::from(self.month <= 2);
+        let month = self.month as i64;
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let shifted_month = if month > 2 { month - 3 } else { month + 9 };
+        let day_of_year = (153 * shifted_month + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146097 + day_of_era - 719468;
+
+        let seconds =
+            days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64;
+
+        let seconds = u64::try_from(seconds).ok()?;
+
+        UNIX_EPOCH.checked_add(
+            Duration::from_secs(seconds) + Duration::from_millis(self.millisecond as u64),
+        )
+    }
+}
+
+impl From<[u32; 7]> for WadTimestamp {
+    fn from([year, month, day, hour, minute, second, millisecond]: [u32; 7]) -> Self {
+        Self {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            millisecond,
+        }
+    }
+}
+
+impl From<[u32; 5]> for WadTimestamp {
+    fn from([year, month, day, hour, minute]: [u32; 5]) -> Self {
+        Self {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second: 0,
+            millisecond: 0,
+        }
+    }
+}
+
+impl From<WadTimestamp> for [u32; 7] {
+    fn from(x: WadTimestamp) -> Self {
+        [
+            x.year,
+            x.month,
+            x.day,
+            x.hour,
+            x.minute,
+            x.second,
+            x.millisecond,
+        ]
+    }
+}
+
+impl From<WadTimestamp> for [u32; 5] {
+    fn from(x: WadTimestamp) -> Self {
+        [x.year, x.month, x.day, x.hour, x.minute]
+    }
+}
+
+/// The fields of an entry that the writer doesn't compute from its path and data, and optionally
+/// where to place that data.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WadEntryMetadata {
+    pub unknown_1: [u8; 16],
+    pub id: u32,
+    pub unknown_2: u32,
+    pub unknown_3: u32,
+    pub unknown_4: [u8; 16],
+    pub created: WadTimestamp,
+    pub accessed: WadTimestamp,
+    pub modified: WadTimestamp,
+    /// Where to place the data. The writer picks the next free block when this is `None`.
+    pub offset: Option<u32>,
+}
+
+/// Writes a `.wad` archive to a sink, laying out the entry data and the entry table.
+///
+/// Entry data is written as soon as an entry is added. The entry table and header are written by
+/// [`WadWriter::finish`].
+pub struct WadWriter<W> {
+    sink: W,
+    header: WadHeader,
+    entries: Vec<WadEntryOwned>,
+    data_end: u64,
+}
+
+#[derive(Debug)]
+pub enum WadWriterError {
+    Io(io::Error),
+    Offset(TryFromIntError),
+    OffsetInHeader(u32),
+    Length(TryFromIntError),
+    EntryCount(TryFromIntError),
+    EntriesPosition(TryFromIntError),
+    Entry(WadEntryError<UnexpectedEnd>),
+    Header(WadHeaderError<UnexpectedEnd>),
+}
+
+impl<W: Write + Seek> WadWriter<W> {
+    /// Creates a writer using the default magic, version and block size.
+    pub fn new(sink: W) -> Self {
+        Self::with_header(
+            sink,
+            WadHeader {
+                magic: WadHeader::MAGIC,
+                version: WadHeader::VERSION,
+                block_size: WadHeader::BLOCK_SIZE,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Creates a writer using the magic, version and block size of `header`. The remaining fields
+    /// are computed.
+    pub fn with_header(sink: W, header: WadHeader) -> Self {
+        Self {
+            sink,
+            header,
+            entries: Vec::new(),
+            data_end: WadHeader::byte_size() as u64,
+        }
+    }
+
+    pub fn entries(&self) -> &[WadEntryOwned] {
+        &self.entries
+    }
+
+    /// Adds an entry with the data read from `data` until its end. An explicit offset has to be
+    /// past the header.
+    pub fn add_entry<R: Read>(
+        &mut self,
+        path: &str,
+        data: &mut R,
+        metadata: &WadEntryMetadata,
+    ) -> Result<&WadEntryOwned, WadWriterError> {
+        use WadWriterError::*;
+
+        let offset = match metadata.offset {
+            Some(offset) if (offset as usize) < WadHeader::byte_size() => {
+                return Err(OffsetInHeader(offset));
+            }
+            Some(offset) => offset as u64,
+            None => self.align_to_block(self.data_end),
+        };
+
+        self.sink.seek(SeekFrom::Start(offset)).map_err(Io)?;
+
+        let length = io::copy(data, &mut self.sink).map_err(Io)?;
+
+        self.data_end = self.data_end.max(offset + length);
+
+        self.entries.push(WadEntryOwned {
+            unknown_1: metadata.unknown_1,
+            id: metadata.id,
+            unknown_2: metadata.unknown_2,
+            length: u32::try_from(length).map_err(Length)?,
+            offset: u32::try_from(offset).map_err(Offset)?,
+            unknown_3: metadata.unknown_3,
+            path: path.to_owned(),
+            unknown_4: metadata.unknown_4,
+            created: metadata.created,
+            accessed: metadata.accessed,
+            modified: metadata.modified,
+        });
+
+        Ok(&self.entries[self.entries.len() - 1])
+    }
+
+    pub fn add_entry_bytes(
+        &mut self,
+        path: &str,
+        data: &[u8],
+        metadata: &WadEntryMetadata,
+    ) -> Result<&WadEntryOwned, WadWriterError> {
+        self.add_entry(path, &mut &data[..], metadata)
+    }
+
+    /// Writes the entry table and the header in front, then returns the sink.
+    ///
+    /// The table is kept at the header's `first_entry_position` when it doesn't overlap the header
+    /// or any entry's data, so an unchanged archive is written back identically. Otherwise it is
+    /// placed at the first block after the data.
+    pub fn finish(mut self) -> Result<W, WadWriterError> {
+        use WadWriterError::*;
+
+        let entries_size: usize = self
+            .entries
+            .iter()
+            .map(|entry| entry.as_entry().byte_size())
+            .sum();
+
+        let entries_position =
+            if self.is_free(self.header.first_entry_position as u64, entries_size as u64) {
+                self.header.first_entry_position as u64
+            } else {
+                self.align_to_block(self.data_end)
+            };
+
+        let entry_count = u32::try_from(self.entries.len()).map_err(EntryCount)?;
+
+        self.header.entry_count = entry_count;
+        self.header.entry_count_repeated = entry_count;
+        self.header.first_entry_position =
+            u32::try_from(entries_position).map_err(EntriesPosition)?;
+
+        let mut entries_bytes = vec![0; entries_size];
+        let mut out = &mut entries_bytes[..];
+
+        for entry in &self.entries {
+            entry.as_entry().serialize(&mut out).map_err(Entry)?;
+        }
+
+        self.sink
+            .seek(SeekFrom::Start(entries_position))
+            .map_err(Io)?;
+
+        self.sink.write_all(&entries_bytes).map_err(Io)?;
+
+        let mut header_bytes = [0; WadHeader::byte_size()];
+
+        self.header
+            .serialize(&mut &mut header_bytes[..])
+            .map_err(Header)?;
+
+        self.sink.seek(SeekFrom::Start(0)).map_err(Io)?;
+        self.sink.write_all(&header_bytes).map_err(Io)?;
+        self.sink.flush().map_err(Io)?;
+
+        Ok(self.sink)
+    }
+
+    /// Whether `size` bytes at `position` are clear of the header and every entry's data.
+    fn is_free(&self, position: u64, size: u64) -> bool {
+        let end = position + size;
+
+        position >= WadHeader::byte_size() as u64
+            && self.entries.iter().all(|entry| {
+                let entry_start = entry.offset as u64;
+                let entry_end = entry_start + entry.length as u64;
+                entry_start == entry_end || end <= entry_start || entry_end <= position
+            })
+    }
+
+    fn align_to_block(&self, position: u64) -> u64 {
+        position.next_multiple_of(self.header.block_size.max(1) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn metadata(id: u32) -> WadEntryMetadata {
+        WadEntryMetadata {
+            id,
+            created: WadTimestamp::from([2005, 9, 14, 12, 30, 15, 250]),
+            accessed: WadTimestamp::from([2005, 9, 15, 8, 0, 1, 2]),
+            modified: WadTimestamp::from([2005, 9, 14, 12, 31]),
+            ..Default::default()
+        }
+    }
+
+    fn write(header: Option<WadHeader>, entries: &[(&str, &[u8], WadEntryMetadata)]) -> Vec<u8> {
+        let sink = Cursor::new(Vec::new());
+
+        let mut writer = match header {
+            Some(header) => WadWriter::with_header(sink, header),
+            None => WadWriter::new(sink),
+        };
+
+        for (path, data, metadata) in entries {
+            writer.add_entry_bytes(path, data, metadata).unwrap();
+        }
+
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn read(bytes: &[u8]) -> (WadHeader, Vec<WadEntryOwned>) {
+        let header = WadHeader::from_bytes(bytes).unwrap();
+        let mut inp = &bytes[header.first_entry_position as usize..];
+
+        let entries = (0..header.entry_count)
+            .map(|_| WadEntry::parse(&mut inp).unwrap().to_owned())
+            .collect();
+
+        (header, entries)
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = write(
+            None,
+            &[
+                (r"Data\Levels\A.tng", b"first", metadata(0)),
+                (r"Data\Levels\B.lev", &[7; 3000], metadata(1)),
+            ],
+        );
+
+        let (header, entries) = read(&bytes);
+
+        assert_eq!(header.magic, WadHeader::MAGIC);
+        assert_eq!(header.entry_count, 2);
+        assert_eq!(header.entry_count_repeated, 2);
+        assert_eq!(header.first_entry_position, 8192);
+
+        assert_eq!(entries[0].path, r"Data\Levels\A.tng");
+        assert_eq!(entries[0].offset, 2048);
+        assert_eq!(entries[1].offset, 4096);
+        assert_eq!(&bytes[4096..4096 + 3000], &[7; 3000][..]);
+
+        for (id, entry) in entries.iter().enumerate() {
+            let expected = WadEntryMetadata {
+                offset: Some(entry.offset),
+                ..metadata(id as u32)
+            };
+            assert_eq!(entry.metadata(), expected);
+        }
+
+        // Writing the parsed archive back keeps every offset and the table position.
+        let rewritten = write(
+            Some(header),
+            &[
+                (r"Data\Levels\A.tng", b"first", entries[0].metadata()),
+                (r"Data\Levels\B.lev", &[7; 3000], entries[1].metadata()),
+            ],
+        );
+
+        assert_eq!(rewritten, bytes);
+    }
+
+    #[test]
+    fn parse_without_path() {
+        let bytes = write(None, &[("a", b"data", metadata(7))]);
+        let (header, _) = read(&bytes);
+
+        let mut table = bytes[header.first_entry_position as usize..].to_vec();
+        table[WadEntry::PATH_OFFSET] = 0xff;
+
+        assert!(matches!(
+            WadEntry::parse(&mut &table[..]),
+            Err(WadEntryError::PathString(_))
+        ));
+
+        let mut inp = &table[..];
+        let (entry, path) = WadEntry::parse_without_path(&mut inp).unwrap();
+
+        assert_eq!((entry.id, entry.path, path), (7, "", &[0xff][..]));
+        assert!(inp.is_empty());
+    }
+
+    #[test]
+    fn timestamp_system_time() {
+        let timestamp = |seconds, millis: u32| {
+            WadTimestamp::from_system_time(UNIX_EPOCH + Duration::new(seconds, millis * 1_000_000))
+        };
+
+        assert_eq!(
+            timestamp(0, 0),
+            WadTimestamp::from([1970, 1, 1, 0, 0, 0, 0])
+        );
+        assert_eq!(
+            timestamp(951827696, 789),
+            WadTimestamp::from([2000, 2, 29, 12, 34, 56, 789])
+        );
+        assert_eq!(
+            timestamp(4107542400, 0),
+            WadTimestamp::from([2100, 3, 1, 0, 0])
+        );
+
+        // Every day of a few centuries converts back to the same time.
+        for days in (0..150_000).step_by(7) {
+            let time = UNIX_EPOCH + Duration::new(days * 86400 + 3723, 4_000_000);
+            let converted = WadTimestamp::from_system_time(time);
+
+            assert_eq!(converted.to_system_time(), Some(time), "{:?}", converted);
+            assert_eq!((converted.hour, converted.minute), (1, 2));
+            assert_eq!((converted.second, converted.millisecond), (3, 4));
+        }
+
+        let before_epoch = UNIX_EPOCH - Duration::from_secs(1);
+        assert_eq!(
+            WadTimestamp::from_system_time(before_epoch),
+            timestamp(0, 0)
+        );
+
+        assert_eq!(
+            WadTimestamp::from([1969, 12, 31, 23, 59]).to_system_time(),
+            None
+        );
+        assert_eq!(
+            WadTimestamp::from([2005, 13, 1, 0, 0]).to_system_time(),
+            None
+        );
+        assert_eq!(
+            WadTimestamp::from([2005, 1, 0, 0, 0]).to_system_time(),
+            None
+        );
+        assert_eq!(
+            WadTimestamp::from([2005, 1, 1, 24, 0]).to_system_time(),
+            None
+        );
+
+        // Days are checked against the length of their month.
+        for [year, month, day] in [[2005, 4, 31], [2005, 2, 29], [1900, 2, 29], [2100, 2, 29]] {
+            let timestamp = WadTimestamp::from([year, month, day, 0, 0]);
+            assert_eq!(timestamp.to_system_time(), None, "{:?}", timestamp);
+        }
+
+        for [year, month, day] in [[2005, 1, 31], [2005, 4, 30], [2004, 2, 29], [2000, 2, 29]] {
+            let timestamp = WadTimestamp::from([year, month, day, 0, 0]);
+            assert!(timestamp.to_system_time().is_some(), "{:?}", timestamp);
+        }
+        assert_eq!(
+            WadTimestamp::from([2005, 1, 1, 0, 0, 0, 1000]).to_system_time(),
+            None
+        );
+    }
+
+    #[test]
+    fn timestamp_fields() {
+        let timestamp = WadTimestamp::from([2005, 9, 14, 12, 30, 15, 250]);
+
+        assert_eq!(<[u32; 7]>::from(timestamp), [2005, 9, 14, 12, 30, 15, 250]);
+        assert_eq!(<[u32; 5]>::from(timestamp), [2005, 9, 14, 12, 30]);
+        assert_eq!(
+            WadTimestamp::from(<[u32; 5]>::from(timestamp)),
+            WadTimestamp::from([2005, 9, 14, 12, 30, 0, 0])
+        );
+
+        // Modification times lose their second and millisecond when written.
+        let entry = WadEntryOwned {
+            unknown_1: [0; 16],
+            id: 0,
+            unknown_2: 0,
+            length: 0,
+            offset: 0,
+            unknown_3: 0,
+            path: "a".to_owned(),
+            unknown_4: [0; 16],
+            created: timestamp,
+            accessed: timestamp,
+            modified: timestamp,
+        };
+
+        let mut bytes = vec![0; entry.as_entry().byte_size()];
+        entry.as_entry().serialize(&mut &mut bytes[..]).unwrap();
+
+        let parsed = WadEntry::parse(&mut &bytes[..]).unwrap();
+
+        assert_eq!(parsed.created, timestamp);
+        assert_eq!(parsed.accessed, timestamp);
+        assert_eq!(parsed.modified, WadTimestamp::from([2005, 9, 14, 12, 30]));
+    }
+
+    #[test]
+    fn writer_layout() {
+        let header = WadHeader {
+            block_size: 512,
+            ..WadHeader::default()
+        };
+
+        let mut writer = WadWriter::with_header(Cursor::new(Vec::new()), header);
+
+        // Data is streamed from a reader into the next free block.
+        let entry = writer
+            .add_entry("a", &mut io::repeat(5).take(600), &metadata(0))
+            .unwrap();
+        assert_eq!((entry.offset, entry.length), (512, 600));
+
+        // Entries can be placed anywhere, and the next ones follow the furthest data.
+        let placed = WadEntryMetadata {
+            offset: Some(4000),
+            ..metadata(1)
+        };
+        let entry = writer.add_entry_bytes("b", b"placed", &placed).unwrap();
+        assert_eq!((entry.offset, entry.length), (4000, 6));
+
+        let entry = writer.add_entry_bytes("c", b"", &metadata(2)).unwrap();
+        assert_eq!((entry.offset, entry.length), (4096, 0));
+
+        // Placing an entry in a gap doesn't move the end of the data.
+        let early = WadEntryMetadata {
+            offset: Some(1536),
+            ..metadata(3)
+        };
+        writer.add_entry_bytes("d", b"early", &early).unwrap();
+
+        let header = WadEntryMetadata {
+            offset: Some(16),
+            ..metadata(4)
+        };
+        assert!(matches!(
+            writer.add_entry_bytes("e", b"header", &header),
+            Err(WadWriterError::OffsetInHeader(16))
+        ));
+
+        let paths = writer
+            .entries()
+            .iter()
+            .map(|x| x.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["a", "b", "c", "d"]);
+
+        let bytes = writer.finish().unwrap().into_inner();
+        let (header, entries) = read(&bytes);
+
+        assert_eq!(header.block_size, 512);
+        assert_eq!(header.entry_count, 4);
+        assert_eq!(header.entry_count_repeated, 4);
+        // The empty entry takes no room, so the table can start where it is.
+        assert_eq!(header.first_entry_position, 4096);
+
+        let layout = entries
+            .iter()
+            .map(|x| (x.id, x.offset, x.length))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            layout,
+            [(0, 512, 600), (1, 4000, 6), (2, 4096, 0), (3, 1536, 5)]
+        );
+
+        assert!(bytes[512..1112].iter().all(|x| *x == 5));
+        assert!(bytes[1112..1536].iter().all(|x| *x == 0));
+        assert_eq!(&bytes[1536..1541], b"early");
+        assert_eq!(&bytes[4000..4006], b"placed");
+    }
+
+    #[test]
+    fn entry_table_position() {
+        let header = WadHeader {
+            block_size: 2048,
+            first_entry_position: 2048,
+            ..WadHeader::default()
+        };
+
+        // The table stays in front of the data when the data leaves room for it.
+        let at_start = WadEntryMetadata {
+            offset: Some(4096),
+            ..metadata(0)
+        };
+        let bytes = write(Some(header), &[("a", b"data", at_start)]);
+        assert_eq!(read(&bytes).0.first_entry_position, 2048);
+        assert_eq!(read(&bytes).1[0].offset, 4096);
+
+        // It moves after the data when an entry now covers that position.
+        let bytes = write(Some(header), &[("a", &[1; 3000], metadata(0))]);
+        assert_eq!(read(&bytes).0.first_entry_position, 6144);
+
+        // A position inside the header is never used.
+        let header = WadHeader {
+            block_size: 2048,
+            first_entry_position: 8,
+            ..WadHeader::default()
+        };
+        let bytes = write(Some(header), &[("a", b"data", metadata(0))]);
+        assert_eq!(read(&bytes).0.first_entry_position, 4096);
+    }
+}
@@ -1,11 +1,51 @@
-use std::{fs::File, io, path::Path};
+use crate::common::{OffsetError, SubReader};
+use fable_format::{
+    big::{
+        BigBankIndex, BigBankIndexError, BigBankIndexOwned, BigFileEntryOwned, BigFileIndex,
+        BigFileIndexError, BigFileIndexOwned, BigHeader, BigHeaderError,
+    },
+    TakeError,
+};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+    mem,
+    path::Path,
+};
 
+/// A `.big` archive on disk with its header, bank list and every bank's file index loaded.
 pub struct BigFile {
-    file: File,
+    source: File,
+    header: BigHeader,
+    banks: Vec<BigFileBank>,
 }
 
+/// A bank of a `.big` archive together with its file index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BigFileBank {
+    pub bank: BigBankIndexOwned,
+    pub index: BigFileIndexOwned,
+}
+
+#[derive(Debug)]
 pub enum BigFileError {
     Open(io::Error),
+    ReadHeader(io::Error),
+    Header(BigHeaderError<TakeError>),
+    ReadBanks(io::Error),
+    Banks(OffsetError<BigBankIndexError<TakeError>>),
+    IndexPastEnd {
+        bank: usize,
+        end: u64,
+    },
+    ReadIndex {
+        bank: usize,
+        error: io::Error,
+    },
+    Index {
+        bank: usize,
+        error: OffsetError<BigFileIndexError<TakeError>>,
+    },
 }
 
 impl BigFile {
@@ -14,6 +54,328 @@ impl BigFile {
 
         let file = File::open(path).map_err(Open)?;
 
-        Ok(BigFile { file })
+        Self::from_file(file)
+    }
+
+    pub fn from_file(mut source: File) -> Result<BigFile, BigFileError> {
+        use BigFileError::*;
+
+        let file_len = source.metadata().map_err(Open)?.len();
+
+        let mut header_bytes = [0; BigHeader::byte_size()];
+
+        source.read_exact(&mut header_bytes).map_err(ReadHeader)?;
+
+        let header = BigHeader::parse(&mut &header_bytes[..]).map_err(Header)?;
+
+        source
+            .seek(SeekFrom::Start(header.bank_address as u64))
+            .map_err(ReadBanks)?;
+
+        let banks_bytes = read_bank_list(&source).map_err(ReadBanks)?;
+
+        let mut inp = &banks_bytes[..];
+
+        let bank_indexes = BigBankIndex::parse_all(&mut inp).map_err(|error| {
+            let position = header.bank_address as usize + (banks_bytes.len() - inp.len());
+            Banks(OffsetError { position, error })
+        })?;
+
+        let mut banks = Vec::with_capacity(bank_indexes.len());
+
+        for (bank_number, bank) in bank_indexes.iter().enumerate() {
+            let end = bank.index_start as u64 + bank.index_size as u64;
+
+            if end > file_len {
+                return Err(IndexPastEnd {
+                    bank: bank_number,
+                    end,
+                });
+            }
+
+            let mut index_bytes = vec![0; bank.index_size as usize];
+
+            source
+                .seek(SeekFrom::Start(bank.index_start as u64))
+                .and_then(|_| source.read_exact(&mut index_bytes))
+                .map_err(|error| ReadIndex {
+                    bank: bank_number,
+                    error,
+                })?;
+
+            let mut inp = &index_bytes[..];
+
+            let index = BigFileIndex::parse(&mut inp, bank.kind()).map_err(|error| {
+                let position = bank.index_start as usize + (index_bytes.len() - inp.len());
+                let error = OffsetError { position, error };
+                Index {
+                    bank: bank_number,
+                    error,
+                }
+            })?;
+
+            banks.push(BigFileBank {
+                bank: bank.to_owned(),
+                index: index.to_owned(),
+            });
+        }
+
+        Ok(BigFile {
+            source,
+            header,
+            banks,
+        })
+    }
+
+    pub fn header(&self) -> &BigHeader {
+        &self.header
+    }
+
+    pub fn banks(&self) -> &[BigFileBank] {
+        &self.banks
+    }
+
+    pub fn bank_by_name(&self, name: &[u8]) -> Option<&BigFileBank> {
+        self.banks.iter().find(|bank| bank.bank.name == name)
+    }
+
+    /// Iterates the entries of every bank, in bank order.
+    pub fn entries(&self) -> impl Iterator<Item = (&BigFileBank, &BigFileEntryOwned)> {
+        self.banks
+            .iter()
+            .flat_map(|bank| bank.index.entries.iter().map(move |entry| (bank, entry)))
+    }
+
+    /// Finds an entry by its symbol name, e.g. `MESH_OBJECT_BARREL`, in any bank.
+    pub fn entry_by_symbol_name(&self, symbol_name: &[u8]) -> Option<&BigFileEntryOwned> {
+        self.entries()
+            .map(|(_, entry)| entry)
+            .find(|entry| entry.symbol_name == symbol_name)
+    }
+
+    pub fn entry_by_id(&self, id: u32) -> Option<&BigFileEntryOwned> {
+        self.entries()
+            .map(|(_, entry)| entry)
+            .find(|entry| entry.id == id)
+    }
+
+    /// Opens a reader over only the entry's payload.
+    pub fn open_entry(&self, entry: &BigFileEntryOwned) -> SubReader<&File> {
+        SubReader::new(&self.source, entry.start as u64, entry.size as u64)
+    }
+
+    /// Reads all of the entry's payload.
+    pub fn read_entry(&self, entry: &BigFileEntryOwned) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(entry.size as usize);
+        self.open_entry(entry).read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+/// Reads the bank list at the position of `source`, one bank at a time so that nothing past the
+/// list is read. The bytes stop early at the end of the file.
+pub(crate) fn read_bank_list<R: Read>(source: R) -> io::Result<Vec<u8>> {
+    let mut source = BufReader::new(source);
+    let mut bytes = Vec::new();
+
+    source
+        .by_ref()
+        .take(mem::size_of::<u32>() as u64)
+        .read_to_end(&mut bytes)?;
+
+    let Ok(banks_count) = <[u8; 4]>::try_from(&bytes[..]) else {
+        return Ok(bytes);
+    };
+
+    for _ in 0..u32::from_le_bytes(banks_count) {
+        // The name and its terminator.
+        if source.read_until(0, &mut bytes)? == 0 || bytes.last() != Some(&0) {
+            break;
+        }
+
+        let size = BigBankIndex::AFTER_NAME_SIZE as u64;
+        let read = source.by_ref().take(size).read_to_end(&mut bytes)?;
+
+        if read as u64 != size {
+            break;
+        }
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{entry, temp_path};
+    use fable_format::big::BigSubHeader;
+    use std::{fmt::Debug, fs};
+
+    /// Runs `serialize` on a large enough buffer and keeps the part it wrote.
+    fn serialized<E: Debug>(serialize: impl FnOnce(&mut &mut [u8]) -> Result<(), E>) -> Vec<u8> {
+        let mut bytes = vec![0; 4096];
+        let mut out = &mut bytes[..];
+
+        serialize(&mut out).unwrap();
+
+        let size = 4096 - out.len();
+        bytes.truncate(size);
+        bytes
+    }
+
+    /// A bank's name with the symbol names and data of its entries.
+    type Bank<'a> = (&'a str, &'a [(&'a str, &'a [u8])]);
+
+    /// Lays out an archive by hand with a bank per item of `banks`, each with entries of the given
+    /// symbol names and data. The data comes first, then the file indexes and the bank list.
+    fn big_bytes(banks: &[Bank]) -> Vec<u8> {
+        let mut bytes = vec![0; 16];
+        let mut indexes = Vec::new();
+        let mut id = 0;
+
+        for (_, entries) in banks {
+            let mut index = BigFileIndexOwned {
+                file_type: 99,
+                types_map: vec![[99, entries.len() as u32]],
+                entries: Vec::new(),
+            };
+
+            for (symbol_name, data) in *entries {
+                id += 1;
+                index.entries.push(BigFileEntryOwned {
+                    size: data.len() as u32,
+                    start: bytes.len() as u32,
+                    ..entry(id, symbol_name.as_bytes())
+                });
+                bytes.extend(*data);
+            }
+
+            indexes.push(index);
+        }
+
+        let mut bank_list = Vec::new();
+
+        for (bank_id, ((name, _), index)) in banks.iter().zip(&indexes).enumerate() {
+            let index_start = bytes.len();
+            bytes.extend(serialized(|out| index.as_file_index().serialize(out)));
+
+            bank_list.push(BigBankIndexOwned {
+                name: name.as_bytes().to_vec(),
+                bank_id: bank_id as u32,
+                bank_entries_count: index.entries.len() as u32,
+                index_start: index_start as u32,
+                index_size: (bytes.len() - index_start) as u32,
+                block_size: 2048,
+            });
+        }
+
+        let bank_address = bytes.len() as u32;
+        let bank_list = bank_list
+            .iter()
+            .map(BigBankIndexOwned::as_bank_index)
+            .collect::<Vec<_>>();
+        bytes.extend(serialized(|out| {
+            BigBankIndex::serialize_all(&bank_list, out)
+        }));
+
+        let header = BigHeader {
+            magic: BigHeader::MAGIC,
+            version: 100,
+            bank_address,
+            unknown_1: 0,
+        };
+        header.serialize(&mut &mut bytes[..16]).unwrap();
+
+        bytes
+    }
+
+    #[test]
+    fn open_and_read() {
+        let path = temp_path("open_and_read.big");
+        fs::write(
+            &path,
+            big_bytes(&[
+                ("BANK_A", &[("FIRST", b"first"), ("SECOND", b"second")]),
+                ("BANK_B", &[("THIRD", b"third")]),
+            ]),
+        )
+        .unwrap();
+
+        let big = BigFile::open(&path).unwrap();
+
+        assert_eq!(big.header().magic, BigHeader::MAGIC);
+        assert_eq!(big.banks().len(), 2);
+
+        let bank = big.bank_by_name(b"BANK_B").unwrap();
+        assert_eq!(bank.index.entries.len(), 1);
+        assert_eq!(bank.index.entries[0].symbol_name, b"THIRD");
+        assert!(big.bank_by_name(b"BANK_C").is_none());
+
+        let entries = big
+            .entries()
+            .map(|(bank, entry)| (&bank.bank.name[..], &entry.symbol_name[..], entry.id))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [
+                (&b"BANK_A"[..], &b"FIRST"[..], 1),
+                (b"BANK_A", b"SECOND", 2),
+                (b"BANK_B", b"THIRD", 3),
+            ]
+        );
+
+        let entry = big.entry_by_symbol_name(b"SECOND").unwrap();
+        assert_eq!(big.read_entry(entry).unwrap(), b"second");
+        assert_eq!(
+            entry.sub_header,
+            BigSubHeader::Unknown(vec![1, 2, 3].into())
+        );
+        assert_eq!(big.entry_by_id(3).unwrap().symbol_name, b"THIRD");
+        assert!(big.entry_by_symbol_name(b"FOURTH").is_none());
+
+        // Entry readers stay within the entry.
+        let mut reader = big.open_entry(entry);
+        reader.seek(SeekFrom::Start(3)).unwrap();
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"ond");
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn open_errors() {
+        let path = temp_path("open_errors.big");
+        let bytes = big_bytes(&[("BANK", &[("FIRST", b"first")])]);
+        let header = BigHeader::parse(&mut &bytes[..]).unwrap();
+        let bank_address = header.bank_address as usize;
+
+        fs::write(&path, &bytes[..8]).unwrap();
+        assert!(matches!(
+            BigFile::open(&path),
+            Err(BigFileError::ReadHeader(_))
+        ));
+
+        fs::write(&path, &bytes[..bank_address + 4]).unwrap();
+        assert!(matches!(
+            BigFile::open(&path),
+            Err(BigFileError::Banks(OffsetError { position, .. })) if position >= bank_address
+        ));
+
+        // Make the bank's index run past the end of the file.
+        let mut banks = BigBankIndex::parse_all(&mut &bytes[bank_address..]).unwrap();
+        banks[0].index_size = bytes.len() as u32;
+
+        let mut corrupt = bytes.clone();
+        BigBankIndex::serialize_all(&banks, &mut &mut corrupt[bank_address..]).unwrap();
+        fs::write(&path, &corrupt).unwrap();
+
+        assert!(matches!(
+            BigFile::open(&path),
+            Err(BigFileError::IndexPastEnd { bank: 0, .. })
+        ));
+
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(BigFile::open(&path), Err(BigFileError::Open(_))));
     }
 }
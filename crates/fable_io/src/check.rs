@@ -0,0 +1,861 @@
+use crate::{big_file::read_bank_list, common::OffsetError, wad_file::read_entry_table};
+use derive_more::Display;
+use fable_format::{
+    big::{
+        BigBankIndex, BigBankIndexError, BigFileEntry, BigFileIndex, BigFileIndexError, BigHeader,
+        BigHeaderError,
+    },
+    wad::{WadEntry, WadEntryError, WadHeader, WadHeaderError},
+    TakeError,
+};
+use std::{
+    collections::HashMap,
+    io::{self, Read, Seek, SeekFrom},
+};
+
+/// A problem found in a `.wad` archive.
+#[derive(Debug, Display)]
+pub enum WadIssue {
+    #[display("invalid header: {_0}")]
+    Header(WadHeaderError<TakeError>),
+
+    #[display("unexpected magic {_0:?}")]
+    Magic([u8; 4]),
+
+    #[display(
+        "entry count {entry_count} differs from repeated entry count {entry_count_repeated}"
+    )]
+    EntryCountMismatch {
+        entry_count: u32,
+        entry_count_repeated: u32,
+    },
+
+    #[display("entry table starts past the end of the file")]
+    EntriesPastEnd,
+
+    #[display("entry {index} is invalid: {error:?}")]
+    Entry {
+        index: usize,
+        error: WadEntryError<TakeError>,
+    },
+
+    #[display("entry {index} has a path that is not UTF-8")]
+    NonUtf8Path { index: usize },
+
+    #[display("entry {index} data ends at {end}, past the end of the file")]
+    DataPastEnd { index: usize, end: u64 },
+
+    #[display("entry {index} data is not aligned to the block size {block_size}")]
+    Misaligned { index: usize, block_size: u32 },
+
+    #[display("entry {index} data overlaps entry {other}")]
+    Overlap { index: usize, other: usize },
+
+    #[display("entry {index} data overlaps the header or entry table")]
+    OverlapsIndex { index: usize },
+
+    #[display("entry {index} has the same path as entry {other}")]
+    DuplicatePath { index: usize, other: usize },
+
+    #[display("entry {index} has the same id as entry {other}")]
+    DuplicateId { index: usize, other: usize },
+}
+
+/// Checks the header, entry table and entry data ranges of a `.wad` archive.
+///
+/// Every issue is positioned at the bytes it concerns: the header field, the entry's record in the
+/// entry table, or the entry's data. Only I/O failures are returned as errors.
+pub fn check_wad<S: Read + Seek>(source: &mut S) -> io::Result<Vec<OffsetError<WadIssue>>> {
+    let mut issues = Vec::new();
+
+    let file_len = source.seek(SeekFrom::End(0))?;
+
+    let mut header_bytes = Vec::with_capacity(WadHeader::byte_size());
+    source.seek(SeekFrom::Start(0))?;
+    source
+        .by_ref()
+        .take(WadHeader::byte_size() as u64)
+        .read_to_end(&mut header_bytes)?;
+
+    let header = match WadHeader::from_bytes(&header_bytes) {
+        Ok(header) => header,
+        Err(error) => {
+            issue(&mut issues, 0, WadIssue::Header(error));
+            return Ok(issues);
+        }
+    };
+
+    if header.magic != WadHeader::MAGIC {
+        issue(&mut issues, 0, WadIssue::Magic(header.magic));
+    }
+
+    if header.entry_count != header.entry_count_repeated {
+        issue(
+            &mut issues,
+            WadHeader::ENTRY_COUNT_REPEATED_OFFSET as u64,
+            WadIssue::EntryCountMismatch {
+                entry_count: header.entry_count,
+                entry_count_repeated: header.entry_count_repeated,
+            },
+        );
+    }
+
+    let entries_position = header.first_entry_position as u64;
+
+    if entries_position > file_len {
+        let position = WadHeader::FIRST_ENTRY_POSITION_OFFSET as u64;
+        issue(&mut issues, position, WadIssue::EntriesPastEnd);
+        return Ok(issues);
+    }
+
+    source.seek(SeekFrom::Start(entries_position))?;
+    let entries_bytes = read_entry_table(&mut *source, header.entry_count)?;
+
+    let mut inp = &entries_bytes[..];
+    let mut entries = Vec::new();
+
+    for index in 0..header.entry_count as usize {
+        let position = entries_position + (entries_bytes.len() - inp.len()) as u64;
+
+        let mut entry_bytes = inp;
+
+        let entry = match WadEntry::parse(&mut entry_bytes) {
+            Ok(entry) => {
+                let path = entry.path.as_bytes();
+                Ok((entry, path))
+            }
+            Err(WadEntryError::PathString(_)) => {
+                issue(&mut issues, position, WadIssue::NonUtf8Path { index });
+
+                entry_bytes = inp;
+                WadEntry::parse_without_path(&mut entry_bytes)
+            }
+            Err(error) => Err(error),
+        };
+
+        inp = entry_bytes;
+
+        match entry {
+            Ok((entry, path)) => entries.push((position, entry, path)),
+            Err(error) => {
+                // The entry's size is unknown, so the ones after it can't be found.
+                issue(&mut issues, position, WadIssue::Entry { index, error });
+                break;
+            }
+        }
+    }
+
+    let entries_end = entries_position + (entries_bytes.len() - inp.len()) as u64;
+
+    let mut paths = HashMap::new();
+    let mut ids = HashMap::new();
+    let mut ranges = Vec::with_capacity(entries.len());
+
+    for (index, (position, entry, path)) in entries.iter().enumerate() {
+        let start = entry.offset as u64;
+        let end = start + entry.length as u64;
+
+        let path = path.to_ascii_lowercase();
+
+        if let Some(&other) = paths.get(&path) {
+            issue(
+                &mut issues,
+                *position,
+                WadIssue::DuplicatePath { index, other },
+            );
+        } else {
+            paths.insert(path, index);
+        }
+
+        if let Some(&other) = ids.get(&entry.id) {
+            issue(
+                &mut issues,
+                *position,
+                WadIssue::DuplicateId { index, other },
+            );
+        } else {
+            ids.insert(entry.id, index);
+        }
+
+        if end > file_len {
+            issue(&mut issues, start, WadIssue::DataPastEnd { index, end });
+        }
+
+        if header.block_size != 0 && !start.is_multiple_of(header.block_size as u64) {
+            let block_size = header.block_size;
+            issue(
+                &mut issues,
+                start,
+                WadIssue::Misaligned { index, block_size },
+            );
+        }
+
+        if entry.length != 0 {
+            let overlaps_header = start < WadHeader::byte_size() as u64;
+            let overlaps_entries = start < entries_end && entries_position < end;
+
+            if overlaps_header || overlaps_entries {
+                issue(&mut issues, start, WadIssue::OverlapsIndex { index });
+            }
+
+            ranges.push(DataRange {
+                start,
+                end,
+                id: index,
+            });
+        }
+    }
+
+    for (index, other) in find_overlaps(&mut ranges) {
+        let position = entries[index].1.offset as u64;
+        issue(&mut issues, position, WadIssue::Overlap { index, other });
+    }
+
+    issues.sort_by_key(|x| x.position);
+
+    Ok(issues)
+}
+
+/// A problem found in a `.big` archive.
+#[derive(Debug, Display)]
+pub enum BigIssue {
+    #[display("invalid header: {_0:?}")]
+    Header(BigHeaderError<TakeError>),
+
+    #[display("unexpected magic {_0:?}")]
+    Magic([u8; 4]),
+
+    #[display("bank list starts past the end of the file")]
+    BanksPastEnd,
+
+    #[display("invalid bank list: {_0:?}")]
+    Banks(BigBankIndexError<TakeError>),
+
+    #[display("bank {bank} has a name that is not UTF-8")]
+    NonUtf8BankName { bank: usize },
+
+    #[display("bank {bank} index ends at {end}, past the end of the file")]
+    IndexPastEnd { bank: usize, end: u64 },
+
+    #[display("bank {bank} index overlaps the bank list")]
+    IndexOverlapsBanks { bank: usize },
+
+    #[display("bank {bank} index overlaps bank {other} index")]
+    IndexOverlap { bank: usize, other: usize },
+
+    #[display("bank {bank} index is invalid: {error:?}")]
+    Index {
+        bank: usize,
+        error: BigFileIndexError<TakeError>,
+    },
+
+    #[display("bank {bank} lists {expected} entries but its index has {found}")]
+    EntryCountMismatch {
+        bank: usize,
+        expected: u32,
+        found: u32,
+    },
+
+    #[display("bank {bank} entry {index} has a symbol name that is not UTF-8")]
+    NonUtf8SymbolName { bank: usize, index: usize },
+
+    #[display("bank {bank} entry {index} has a file name that is not UTF-8")]
+    NonUtf8FileName { bank: usize, index: usize },
+
+    #[display("bank {bank} entry {index} data ends at {end}, past the end of the file")]
+    DataPastEnd { bank: usize, index: usize, end: u64 },
+
+    #[display("bank {bank} entry {index} data is not aligned to the block size {block_size}")]
+    Misaligned {
+        bank: usize,
+        index: usize,
+        block_size: u32,
+    },
+
+    #[display("bank {bank} entry {index} data overlaps bank {other_bank} entry {other}")]
+    Overlap {
+        bank: usize,
+        index: usize,
+        other_bank: usize,
+        other: usize,
+    },
+
+    #[display("bank {bank} entry {index} data overlaps the header, bank list or a file index")]
+    OverlapsIndex { bank: usize, index: usize },
+
+    #[display("bank {bank} entry {index} has the same id as entry {other}")]
+    DuplicateId {
+        bank: usize,
+        index: usize,
+        other: usize,
+    },
+
+    #[display("bank {bank} entry {index} has the same symbol name as entry {other}")]
+    DuplicateSymbolName {
+        bank: usize,
+        index: usize,
+        other: usize,
+    },
+}
+
+/// Checks the header, bank list, file indexes and entry data ranges of a `.big` archive.
+///
+/// Issues are positioned like those of [`check_wad`].
+pub fn check_big<S: Read + Seek>(source: &mut S) -> io::Result<Vec<OffsetError<BigIssue>>> {
+    let mut issues = Vec::new();
+
+    let file_len = source.seek(SeekFrom::End(0))?;
+
+    let mut header_bytes = Vec::with_capacity(BigHeader::byte_size());
+    source.seek(SeekFrom::Start(0))?;
+    source
+        .by_ref()
+        .take(BigHeader::byte_size() as u64)
+        .read_to_end(&mut header_bytes)?;
+
+    let header = match BigHeader::parse(&mut &header_bytes[..]) {
+        Ok(header) => header,
+        Err(error) => {
+            issue(&mut issues, 0, BigIssue::Header(error));
+            return Ok(issues);
+        }
+    };
+
+    if header.magic != BigHeader::MAGIC {
+        issue(&mut issues, 0, BigIssue::Magic(header.magic));
+    }
+
+    let banks_position = header.bank_address as u64;
+
+    if banks_position > file_len {
+        let position = BigHeader::BANK_ADDRESS_OFFSET as u64;
+        issue(&mut issues, position, BigIssue::BanksPastEnd);
+        return Ok(issues);
+    }
+
+    source.seek(SeekFrom::Start(banks_position))?;
+    let banks_bytes = read_bank_list(&mut *source)?;
+
+    let banks = match BigBankIndex::parse_all(&mut &banks_bytes[..]) {
+        Ok(banks) => banks,
+        Err(error) => {
+            issue(&mut issues, banks_position, BigIssue::Banks(error));
+            return Ok(issues);
+        }
+    };
+
+    let mut ranges = Vec::new();
+    let mut regions = Vec::new();
+
+    let banks_end = banks_position + BigBankIndex::byte_size_all(&banks) as u64;
+
+    ranges.push(DataRange {
+        start: banks_position,
+        end: banks_end,
+        id: regions.len(),
+    });
+    regions.push((BigRegion::BankList, banks_position));
+
+    for (bank_index, bank) in banks.iter().enumerate() {
+        if std::str::from_utf8(bank.name).is_err() {
+            let bank = bank_index;
+            issue(
+                &mut issues,
+                banks_position,
+                BigIssue::NonUtf8BankName { bank },
+            );
+        }
+
+        let index_start = bank.index_start as u64;
+        let index_end = index_start + bank.index_size as u64;
+
+        if index_end > file_len {
+            let (bank, end) = (bank_index, index_end);
+            issue(
+                &mut issues,
+                index_start,
+                BigIssue::IndexPastEnd { bank, end },
+            );
+            continue;
+        }
+
+        if bank.index_size != 0 {
+            ranges.push(DataRange {
+                start: index_start,
+                end: index_end,
+                id: regions.len(),
+            });
+            regions.push((BigRegion::Index { bank: bank_index }, index_start));
+        }
+
+        let mut index_bytes = vec![0; bank.index_size as usize];
+        source.seek(SeekFrom::Start(index_start))?;
+        source.read_exact(&mut index_bytes)?;
+
+        let entries = check_big_index(&mut issues, bank_index, bank, &index_bytes);
+
+        let block_size = bank.block_size;
+        let mut ids = HashMap::new();
+        let mut names = HashMap::new();
+
+        for (index, (position, entry)) in entries.iter().enumerate() {
+            let bank = bank_index;
+            let start = entry.start as u64;
+            let end = start + entry.size as u64;
+
+            if std::str::from_utf8(entry.symbol_name).is_err() {
+                issue(
+                    &mut issues,
+                    *position,
+                    BigIssue::NonUtf8SymbolName { bank, index },
+                );
+            }
+
+            if entry.files.iter().any(|x| std::str::from_utf8(x).is_err()) {
+                issue(
+                    &mut issues,
+                    *position,
+                    BigIssue::NonUtf8FileName { bank, index },
+                );
+            }
+
+            if let Some(&other) = ids.get(&entry.id) {
+                let kind = BigIssue::DuplicateId { bank, index, other };
+                issue(&mut issues, *position, kind);
+            } else {
+                ids.insert(entry.id, index);
+            }
+
+            if let Some(&other) = names.get(entry.symbol_name) {
+                let kind = BigIssue::DuplicateSymbolName { bank, index, other };
+                issue(&mut issues, *position, kind);
+            } else {
+                names.insert(entry.symbol_name, index);
+            }
+
+            if end > file_len {
+                issue(
+                    &mut issues,
+                    start,
+                    BigIssue::DataPastEnd { bank, index, end },
+                );
+            }
+
+            if block_size != 0 && !start.is_multiple_of(block_size as u64) {
+                let kind = BigIssue::Misaligned {
+                    bank,
+                    index,
+                    block_size,
+                };
+                issue(&mut issues, start, kind);
+            }
+
+            if entry.size != 0 {
+                if start < BigHeader::byte_size() as u64 {
+                    issue(&mut issues, start, BigIssue::OverlapsIndex { bank, index });
+                }
+
+                ranges.push(DataRange {
+                    start,
+                    end,
+                    id: regions.len(),
+                });
+                regions.push((BigRegion::Data { bank, index }, start));
+            }
+        }
+    }
+
+    for (id, other_id) in find_overlaps(&mut ranges) {
+        use BigRegion::*;
+
+        let ((region, start), (other_region, other_start)) = (regions[id], regions[other_id]);
+
+        let (position, kind) = match (region, other_region) {
+            (
+                Data { bank, index },
+                Data {
+                    bank: other_bank,
+                    index: other,
+                },
+            ) => {
+                let kind = BigIssue::Overlap {
+                    bank,
+                    index,
+                    other_bank,
+                    other,
+                };
+                (start, kind)
+            }
+            (Data { bank, index }, _) => (start, BigIssue::OverlapsIndex { bank, index }),
+            (_, Data { bank, index }) => (other_start, BigIssue::OverlapsIndex { bank, index }),
+            (Index { bank }, Index { bank: other }) => {
+                (start, BigIssue::IndexOverlap { bank, other })
+            }
+            (Index { bank }, BankList) => (start, BigIssue::IndexOverlapsBanks { bank }),
+            (BankList, Index { bank }) => (other_start, BigIssue::IndexOverlapsBanks { bank }),
+            // There's only one bank list.
+            (BankList, BankList) => continue,
+        };
+
+        issue(&mut issues, position, kind);
+    }
+
+    issues.sort_by_key(|x| x.position);
+
+    Ok(issues)
+}
+
+/// Parses a bank's file index, returning its entries with the positions of their records so that
+/// issues can be pointed at them.
+fn check_big_index<'a>(
+    issues: &mut Vec<OffsetError<BigIssue>>,
+    bank_index: usize,
+    bank: &BigBankIndex,
+    index_bytes: &'a [u8],
+) -> Vec<(u64, BigFileEntry<'a>)> {
+    let index_start = bank.index_start as u64;
+    let expected = bank.bank_entries_count;
+    let kind = bank.kind();
+    let bank = bank_index;
+
+    let mut inp = index_bytes;
+
+    let index = match BigFileIndex::parse(&mut inp, kind) {
+        Ok(index) => index,
+        Err(error) => {
+            let position = index_start + (index_bytes.len() - inp.len()) as u64;
+            issue(issues, position, BigIssue::Index { bank, error });
+            return Vec::new();
+        }
+    };
+
+    let found = index.entries.len() as u32;
+
+    if expected != found {
+        let kind = BigIssue::EntryCountMismatch {
+            bank,
+            expected,
+            found,
+        };
+        issue(issues, index_start, kind);
+    }
+
+    let entries_size = index
+        .entries
+        .iter()
+        .map(BigFileEntry::byte_size)
+        .sum::<usize>();
+
+    let mut position = index_start + (index.byte_size() - entries_size) as u64;
+
+    index
+        .entries
+        .into_iter()
+        .map(|entry| {
+            let entry_position = position;
+            position += entry.byte_size() as u64;
+            (entry_position, entry)
+        })
+        .collect()
+}
+
+struct DataRange {
+    start: u64,
+    end: u64,
+    id: usize,
+}
+
+/// A part of a `.big` archive that no other part may overlap.
+#[derive(Clone, Copy)]
+enum BigRegion {
+    BankList,
+    Index { bank: usize },
+    Data { bank: usize, index: usize },
+}
+
+/// Finds every pair of overlapping ranges, as `(later, earlier)` ids.
+fn find_overlaps(ranges: &mut [DataRange]) -> Vec<(usize, usize)> {
+    ranges.sort_by_key(|x| (x.start, x.end));
+
+    let mut overlaps = Vec::new();
+    let mut furthest: Option<&DataRange> = None;
+
+    for range in ranges.iter() {
+        if let Some(previous) = furthest {
+            if range.start < previous.end {
+                overlaps.push((range.id, previous.id));
+            }
+
+            if range.end > previous.end {
+                furthest = Some(range);
+            }
+        } else {
+            furthest = Some(range);
+        }
+    }
+
+    overlaps
+}
+
+fn issue<E>(issues: &mut Vec<OffsetError<E>>, position: u64, error: E) {
+    issues.push(OffsetError {
+        position: position as usize,
+        error,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{entry, wad_bytes};
+    use fable_format::big::{BigBankMetadata, BigWriter};
+    use std::io::Cursor;
+
+    fn wad_issues(bytes: &[u8]) -> Vec<(usize, String)> {
+        check_wad(&mut Cursor::new(bytes))
+            .unwrap()
+            .into_iter()
+            .map(|issue| (issue.position, issue.error.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn clean_wad() {
+        let bytes = wad_bytes(0, &[("A", 0, b"first", None), ("B", 1, &[2; 3000], None)]);
+
+        assert!(wad_issues(&bytes).is_empty());
+    }
+
+    #[test]
+    fn wad_header_issues() {
+        let mut bytes = wad_bytes(0, &[("A", 0, b"first", None)]);
+
+        let position = WadHeader::ENTRY_COUNT_REPEATED_OFFSET;
+        bytes[position..position + 4].copy_from_slice(&2u32.to_le_bytes());
+
+        assert_eq!(
+            wad_issues(&bytes),
+            [(
+                position,
+                "entry count 1 differs from repeated entry count 2".to_owned()
+            )]
+        );
+
+        let position = WadHeader::FIRST_ENTRY_POSITION_OFFSET;
+        bytes[position..position + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert_eq!(
+            wad_issues(&bytes)[1],
+            (
+                position,
+                "entry table starts past the end of the file".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn wad_entry_issues() {
+        let mut bytes = wad_bytes(
+            0,
+            &[
+                ("A", 0, b"first", None),
+                ("a", 0, b"second", Some(2052)),
+                ("C", 2, b"third", None),
+            ],
+        );
+
+        let header = WadHeader::from_bytes(&bytes).unwrap();
+        let table = header.first_entry_position as usize;
+        let second = table + WadEntry::from_bytes(&bytes[table..]).unwrap().byte_size();
+
+        // Make the first path invalid UTF-8. It and the entries after it are still checked.
+        let path = table + bytes[table..].iter().position(|x| *x == b'A').unwrap();
+        bytes[path] = 0xff;
+
+        let issues = wad_issues(&bytes);
+
+        assert_eq!(
+            issues,
+            [
+                (
+                    2052,
+                    "entry 1 data is not aligned to the block size 2048".to_owned()
+                ),
+                (2052, "entry 1 data overlaps entry 0".to_owned()),
+                (table, "entry 0 has a path that is not UTF-8".to_owned()),
+                (second, "entry 1 has the same id as entry 0".to_owned()),
+            ]
+        );
+
+        // The same archive with a valid path reports the duplicates too.
+        bytes[path] = b'A';
+
+        let issues = wad_issues(&bytes);
+
+        assert!(issues.contains(&(2052, "entry 1 data overlaps entry 0".to_owned())));
+        assert!(issues
+            .iter()
+            .any(|(_, issue)| issue == "entry 1 has the same path as entry 0"));
+        assert!(issues
+            .iter()
+            .any(|(_, issue)| issue == "entry 1 has the same id as entry 0"));
+
+        // A truncated table keeps the issues of the entries before it.
+        bytes.truncate(bytes.len() - 4);
+
+        let issues = wad_issues(&bytes);
+
+        assert!(issues.contains(&(2052, "entry 1 data overlaps entry 0".to_owned())));
+        assert!(issues
+            .last()
+            .is_some_and(|(_, issue)| issue.starts_with("entry 2 is invalid")));
+    }
+
+    fn bank(name: &str) -> BigBankMetadata {
+        BigBankMetadata {
+            name: name.as_bytes().to_vec(),
+            bank_id: 1,
+            block_size: 2048,
+            file_type: 0,
+            types_map: Vec::new(),
+            index_start: None,
+        }
+    }
+
+    fn big_issues(bytes: &[u8]) -> Vec<(usize, String)> {
+        check_big(&mut Cursor::new(bytes))
+            .unwrap()
+            .into_iter()
+            .map(|issue| (issue.position, issue.error.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn big_issues_in_every_bank() {
+        let mut writer = BigWriter::new(Cursor::new(Vec::new()));
+
+        writer.add_bank(bank("BANK_A"));
+        writer.add_entry_bytes(b"a", &entry(1, b"A"), None).unwrap();
+        writer.add_entry_bytes(b"b", &entry(2, b"B"), None).unwrap();
+
+        writer.add_bank(bank("BANK_B"));
+        writer.add_entry_bytes(b"c", &entry(1, b"C"), None).unwrap();
+        writer
+            .add_entry_bytes(b"dd", &entry(1, b"\xffD"), Some(6144))
+            .unwrap();
+
+        let mut bytes = writer.finish().unwrap().into_inner();
+
+        let expected = [
+            "bank 1 entry 1 data overlaps bank 1 entry 0",
+            "bank 1 entry 1 has a symbol name that is not UTF-8",
+            "bank 1 entry 1 has the same id as entry 0",
+        ];
+
+        let issues = big_issues(&bytes);
+
+        assert_eq!(
+            issues.iter().map(|(_, issue)| issue).collect::<Vec<_>>(),
+            expected
+        );
+        assert_eq!(issues[0].0, 6144);
+
+        // Cut the last entry off bank A's index and give bank B a wrong entry count.
+        let header = BigHeader::parse(&mut &bytes[..]).unwrap();
+        let banks_position = header.bank_address as usize;
+        let mut banks = BigBankIndex::parse_all(&mut &bytes[banks_position..]).unwrap();
+
+        banks[0].index_size -= 4;
+        banks[1].bank_entries_count = 3;
+
+        let (bank_a, bank_b) = (banks[0].index_start as usize, banks[1].index_start as usize);
+
+        let banks_bytes = {
+            let mut out = vec![0; BigBankIndex::byte_size_all(&banks)];
+            BigBankIndex::serialize_all(&banks, &mut &mut out[..]).unwrap();
+            out
+        };
+
+        bytes[banks_position..banks_position + banks_bytes.len()].copy_from_slice(&banks_bytes);
+
+        let issues = big_issues(&bytes);
+
+        assert_eq!(issues.len(), 5, "{:?}", issues);
+        assert!(issues
+            .iter()
+            .any(|(position, issue)| *position > bank_a
+                && issue.starts_with("bank 0 index is invalid")));
+
+        for expected in expected {
+            assert!(
+                issues.iter().any(|(_, issue)| issue == expected),
+                "{}",
+                expected
+            );
+        }
+
+        let mismatch = "bank 1 lists 3 entries but its index has 2".to_owned();
+        assert!(issues.contains(&(bank_b, mismatch)));
+    }
+
+    #[test]
+    fn big_table_overlaps() {
+        let mut writer = BigWriter::new(Cursor::new(Vec::new()));
+
+        writer.add_bank(bank("BANK_A"));
+        writer.add_entry_bytes(b"a", &entry(1, b"A"), None).unwrap();
+        writer
+            .add_entry_bytes(b"b", &entry(2, b"B"), Some(4))
+            .unwrap();
+
+        let mut bytes = writer.finish().unwrap().into_inner();
+
+        // Add a bank sharing bank A's index and one whose index is the bank list itself.
+        let header = BigHeader::parse(&mut &bytes[..]).unwrap();
+        let banks_position = header.bank_address as usize;
+        let mut banks = BigBankIndex::parse_all(&mut &bytes[banks_position..]).unwrap();
+
+        let shared = BigBankIndex {
+            name: b"BANK_B",
+            ..banks[0].clone()
+        };
+        let inside_banks = BigBankIndex {
+            name: b"BANK_C",
+            index_start: banks_position as u32,
+            index_size: 4,
+            ..banks[0].clone()
+        };
+        let index_start = banks[0].index_start as usize;
+        banks.extend([shared, inside_banks]);
+
+        let banks_bytes = {
+            let mut out = vec![0; BigBankIndex::byte_size_all(&banks)];
+            BigBankIndex::serialize_all(&banks, &mut &mut out[..]).unwrap();
+            out
+        };
+
+        bytes.truncate(banks_position);
+        bytes.extend(banks_bytes);
+
+        let issues = big_issues(&bytes);
+
+        for expected in [
+            (
+                4,
+                "bank 0 entry 1 data overlaps the header, bank list or a file index",
+            ),
+            (
+                4,
+                "bank 1 entry 1 data overlaps the header, bank list or a file index",
+            ),
+            (2048, "bank 1 entry 0 data overlaps bank 0 entry 0"),
+            (banks_position, "bank 2 index overlaps the bank list"),
+        ] {
+            let expected = (expected.0, expected.1.to_owned());
+            assert!(issues.contains(&expected), "{:?} {:?}", expected, issues);
+        }
+
+        let index_overlap = (index_start, "bank 1 index overlaps bank 0 index".to_owned());
+        assert!(issues.contains(&index_overlap), "{:?}", issues);
+    }
+}
@@ -1,5 +1,6 @@
 use derive_more::{Display, From};
 use fable_format::UnexpectedEnd;
+use std::io::{self, Read, Seek, SeekFrom};
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, From, Display)]
 #[display("{error} at position {position}")]
@@ -18,3 +19,79 @@ impl<E> OffsetError<E> {
         Ok(Self { position, error })
     }
 }
+
+/// A `Read + Seek` view over `length` bytes of `inner` starting at `start`.
+///
+/// The inner source is seeked before every read, so several views can share one source, e.g. a
+/// `&File`.
+#[derive(Debug)]
+pub struct SubReader<R> {
+    inner: R,
+    start: u64,
+    length: u64,
+    position: u64,
+}
+
+impl<R> SubReader<R> {
+    pub fn new(inner: R, start: u64, length: u64) -> Self {
+        Self {
+            inner,
+            start,
+            length,
+            position: 0,
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read + Seek> Read for SubReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.length.saturating_sub(self.position);
+        let size = buf
+            .len()
+            .min(usize::try_from(remaining).unwrap_or(usize::MAX));
+
+        if size == 0 {
+            return Ok(0);
+        }
+
+        self.inner
+            .seek(SeekFrom::Start(self.start + self.position))?;
+
+        let read = self.inner.read(&mut buf[..size])?;
+
+        self.position += read as u64;
+
+        Ok(read)
+    }
+}
+
+impl<R> Seek for SubReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.length.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )
+        })?;
+
+        Ok(self.position)
+    }
+}
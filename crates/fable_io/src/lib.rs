@@ -1,4 +1,11 @@
-pub(crate) mod common;
+pub mod common;
+
+pub use common::{OffsetError, SubReader};
 
 pub mod big_file;
+pub mod check;
+pub mod mmap;
 pub mod wad_file;
+
+#[cfg(test)]
+mod test_support;
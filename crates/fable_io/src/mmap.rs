@@ -0,0 +1,374 @@
+use crate::common::OffsetError;
+use fable_format::{
+    big::{
+        BigBankIndex, BigBankIndexError, BigFileEntry, BigFileIndex, BigFileIndexError, BigHeader,
+        BigHeaderError,
+    },
+    wad::{WadEntry, WadEntryError, WadHeader, WadHeaderError},
+    TakeError,
+};
+use memmap2::Mmap;
+use std::{fs::File, io, ops::Deref, path::Path};
+
+/// A read-only memory map of an archive.
+///
+/// Parsing borrows from the map, so [`WadArchive`] and [`BigArchive`] hand out entries and payloads
+/// without copying.
+pub struct MmapFile {
+    mmap: Mmap,
+}
+
+impl MmapFile {
+    /// Maps the file at `path`.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while it is mapped, e.g. by the game or another
+    /// tool. Doing so is undefined behavior.
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> io::Result<MmapFile> {
+        let file = File::open(path)?;
+
+        // Safety: upheld by the caller.
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        Ok(MmapFile { mmap })
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.mmap
+    }
+
+    pub fn wad(&self) -> Result<WadArchive<'_>, WadArchiveError> {
+        WadArchive::parse(&self.mmap)
+    }
+
+    pub fn big(&self) -> Result<BigArchive<'_>, BigArchiveError> {
+        BigArchive::parse(&self.mmap)
+    }
+}
+
+impl Deref for MmapFile {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.mmap
+    }
+}
+
+/// A `.wad` archive parsed from bytes in memory, with entries borrowing their paths from it.
+pub struct WadArchive<'a> {
+    bytes: &'a [u8],
+    pub header: WadHeader,
+    pub entries: Vec<WadEntry<'a>>,
+}
+
+#[derive(Debug)]
+pub enum WadArchiveError {
+    Header(WadHeaderError<TakeError>),
+    EntriesPosition(usize),
+    Entry(OffsetError<WadEntryError<TakeError>>),
+}
+
+impl<'a> WadArchive<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, WadArchiveError> {
+        use WadArchiveError::*;
+
+        let header = WadHeader::from_bytes(bytes).map_err(Header)?;
+
+        let entries_position = header.first_entry_position as usize;
+
+        let entries_bytes = bytes
+            .get(entries_position..)
+            .ok_or(EntriesPosition(entries_position))?;
+
+        let entries = parse_table(entries_bytes, entries_position, |inp| {
+            let mut entries = Vec::with_capacity((header.entry_count as usize).min(inp.len()));
+
+            for _ in 0..header.entry_count {
+                // Errors are positioned at the start of the entry's record.
+                let mut entry_inp = *inp;
+                entries.push(WadEntry::parse(&mut entry_inp)?);
+                *inp = entry_inp;
+            }
+
+            Ok(entries)
+        })
+        .map_err(Entry)?;
+
+        Ok(WadArchive {
+            bytes,
+            header,
+            entries,
+        })
+    }
+
+    /// Finds an entry by its path. Paths are compared ignoring ASCII case, like Windows does.
+    pub fn entry_by_path(&self, path: &str) -> Option<&WadEntry<'a>> {
+        self.entries
+            .iter()
+            .find(|entry| entry.path.eq_ignore_ascii_case(path))
+    }
+
+    pub fn entry_by_id(&self, id: u32) -> Option<&WadEntry<'a>> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    /// The entry's data, or `None` if it lies past the end of the archive.
+    pub fn entry_data(&self, entry: &WadEntry) -> Option<&'a [u8]> {
+        let start = entry.offset as usize;
+        self.bytes
+            .get(start..start.checked_add(entry.length as usize)?)
+    }
+}
+
+/// A `.big` archive parsed from bytes in memory, with entries borrowing their names from it.
+pub struct BigArchive<'a> {
+    bytes: &'a [u8],
+    pub header: BigHeader,
+    pub banks: Vec<BigArchiveBank<'a>>,
+}
+
+pub struct BigArchiveBank<'a> {
+    pub bank: BigBankIndex<'a>,
+    pub index: BigFileIndex<'a>,
+}
+
+#[derive(Debug)]
+pub enum BigArchiveError {
+    Header(BigHeaderError<TakeError>),
+    BankAddress(usize),
+    Banks(OffsetError<BigBankIndexError<TakeError>>),
+    IndexRange {
+        bank: usize,
+    },
+    Index {
+        bank: usize,
+        error: OffsetError<BigFileIndexError<TakeError>>,
+    },
+}
+
+impl<'a> BigArchive<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, BigArchiveError> {
+        use BigArchiveError::*;
+
+        let header = BigHeader::parse(&mut &bytes[..]).map_err(Header)?;
+
+        let bank_address = header.bank_address as usize;
+
+        let banks_bytes = bytes.get(bank_address..).ok_or(BankAddress(bank_address))?;
+
+        let bank_indexes =
+            parse_table(banks_bytes, bank_address, BigBankIndex::parse_all).map_err(Banks)?;
+
+        let mut banks = Vec::with_capacity(bank_indexes.len());
+
+        for (bank_number, bank) in bank_indexes.into_iter().enumerate() {
+            let index_start = bank.index_start as usize;
+
+            let index_bytes = index_start
+                .checked_add(bank.index_size as usize)
+                .and_then(|index_end| bytes.get(index_start..index_end))
+                .ok_or(IndexRange { bank: bank_number })?;
+
+            let index = parse_table(index_bytes, index_start, |inp| {
+                BigFileIndex::parse(inp, bank.kind())
+            })
+            .map_err(|error| Index {
+                bank: bank_number,
+                error,
+            })?;
+
+            banks.push(BigArchiveBank { bank, index });
+        }
+
+        Ok(BigArchive {
+            bytes,
+            header,
+            banks,
+        })
+    }
+
+    /// Iterates the entries of every bank.
+    pub fn entries(&self) -> impl Iterator<Item = (&BigArchiveBank<'a>, &BigFileEntry<'a>)> {
+        self.banks
+            .iter()
+            .flat_map(|bank| bank.index.entries.iter().map(move |entry| (bank, entry)))
+    }
+
+    /// The entry's data, or `None` if it lies past the end of the archive.
+    pub fn entry_data(&self, entry: &BigFileEntry) -> Option<&'a [u8]> {
+        let start = entry.start as usize;
+        self.bytes
+            .get(start..start.checked_add(entry.size as usize)?)
+    }
+}
+
+/// Runs `parse` on a table found at `position` in the archive, positioning an error at the bytes
+/// `parse` stopped at.
+fn parse_table<'a, T, E>(
+    bytes: &'a [u8],
+    position: usize,
+    parse: impl FnOnce(&mut &'a [u8]) -> Result<T, E>,
+) -> Result<T, OffsetError<E>> {
+    let mut inp = bytes;
+
+    parse(&mut inp).map_err(|error| OffsetError {
+        position: position + (bytes.len() - inp.len()),
+        error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, entry, temp_path};
+    use fable_format::big::{BigBankMetadata, BigSubHeader, BigWriter};
+    use std::{borrow::Cow, fs, io::Cursor};
+
+    fn wad_bytes() -> Vec<u8> {
+        test_support::wad_bytes(
+            0,
+            &[
+                ("data\\a.txt", 0, b"first", None),
+                ("data\\b.txt", 1, b"second", None),
+            ],
+        )
+    }
+
+    fn big_bytes() -> Vec<u8> {
+        let mut writer = BigWriter::new(Cursor::new(Vec::new()));
+
+        writer.add_bank(BigBankMetadata {
+            name: b"BANK".to_vec(),
+            bank_id: 1,
+            block_size: 2048,
+            file_type: 99,
+            types_map: vec![[99, 2]],
+            index_start: None,
+        });
+
+        for (id, (name, data)) in [(&b"FIRST"[..], &b"first"[..]), (b"SECOND", b"second")]
+            .into_iter()
+            .enumerate()
+        {
+            writer
+                .add_entry_bytes(data, &entry(id as u32, name), None)
+                .unwrap();
+        }
+
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn wad_archive() {
+        let bytes = wad_bytes();
+        let wad = WadArchive::parse(&bytes).unwrap();
+
+        assert_eq!(wad.entries.len(), 2);
+
+        let entry = wad.entry_by_path("DATA\\B.TXT").unwrap();
+        assert_eq!(entry.path, "data\\b.txt");
+        assert_eq!(wad.entry_data(entry), Some(&b"second"[..]));
+        assert_eq!(wad.entry_by_id(0).unwrap().path, "data\\a.txt");
+
+        // Payloads are slices of the archive itself.
+        let data = wad.entry_data(entry).unwrap();
+        assert!(bytes.as_ptr_range().contains(&data.as_ptr()));
+
+        let mut entry = entry.clone();
+        entry.offset = bytes.len() as u32;
+        assert_eq!(wad.entry_data(&entry), None);
+    }
+
+    #[test]
+    fn wad_archive_errors() {
+        let bytes = wad_bytes();
+
+        assert!(matches!(
+            WadArchive::parse(&bytes[..10]),
+            Err(WadArchiveError::Header(_))
+        ));
+
+        let header = WadHeader::from_bytes(&bytes).unwrap();
+        let first_entry_position = header.first_entry_position as usize;
+
+        assert!(matches!(
+            WadArchive::parse(&bytes[..first_entry_position + 10]),
+            Err(WadArchiveError::Entry(OffsetError { position, .. }))
+                if position == first_entry_position
+        ));
+    }
+
+    #[test]
+    fn big_archive() {
+        let bytes = big_bytes();
+        let big = BigArchive::parse(&bytes).unwrap();
+
+        assert_eq!(big.banks.len(), 1);
+        assert_eq!(big.banks[0].bank.name, b"BANK");
+
+        let entries = big
+            .entries()
+            .map(|(_, entry)| (entry.symbol_name, big.entry_data(entry).unwrap()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            entries,
+            [(&b"FIRST"[..], &b"first"[..]), (b"SECOND", b"second")]
+        );
+
+        // Unknown sub-headers borrow from the archive too.
+        for (_, entry) in big.entries() {
+            let BigSubHeader::Unknown(Cow::Borrowed(sub_header)) = &entry.sub_header else {
+                panic!("{:?}", entry.sub_header);
+            };
+            assert_eq!(sub_header, &[1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn big_archive_errors() {
+        let mut bytes = big_bytes();
+
+        assert!(matches!(
+            BigArchive::parse(&bytes[..10]),
+            Err(BigArchiveError::Header(_))
+        ));
+
+        let mut header = BigHeader::parse(&mut &bytes[..]).unwrap();
+        let bank_address = header.bank_address as usize;
+
+        assert!(matches!(
+            BigArchive::parse(&bytes[..bank_address]),
+            Err(BigArchiveError::Banks(OffsetError { position, .. })) if position == bank_address
+        ));
+
+        // Point the banks past the end of the file.
+        header.bank_address = bytes.len() as u32 + 1;
+        header
+            .serialize(&mut &mut bytes[..BigHeader::byte_size()])
+            .unwrap();
+
+        assert!(matches!(
+            BigArchive::parse(&bytes),
+            Err(BigArchiveError::BankAddress(_))
+        ));
+    }
+
+    #[test]
+    fn map_file() {
+        let path = temp_path("mmap.big");
+        let bytes = big_bytes();
+        fs::write(&path, &bytes).unwrap();
+
+        // Safety: the file is only removed once it's unmapped.
+        let file = unsafe { MmapFile::open(&path) }.unwrap();
+        assert_eq!(file.bytes(), &bytes[..]);
+
+        let big = file.big().unwrap();
+        assert_eq!(big.entries().count(), 2);
+
+        drop(file);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,58 @@
+//! Helpers shared by the tests of the crate.
+
+use fable_format::{
+    big::{BigFileEntryOwned, BigSubHeader},
+    wad::{WadEntryMetadata, WadHeader, WadWriter},
+};
+use std::{io::Cursor, path::PathBuf};
+
+/// A path of its own under the system's temporary directory, for the file named `name`.
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("fable_io_{}_{}", std::process::id(), name))
+}
+
+/// An entry of type 99 with an unknown sub-header, ready to be written with a size and start of 0.
+pub fn entry(id: u32, symbol_name: &[u8]) -> BigFileEntryOwned {
+    BigFileEntryOwned {
+        magic: 42,
+        id,
+        file_type: 99,
+        size: 0,
+        start: 0,
+        file_type_dev: 99,
+        symbol_name: symbol_name.to_vec(),
+        crc: 0,
+        files: Vec::new(),
+        sub_header: BigSubHeader::Unknown(vec![1, 2, 3].into()),
+    }
+}
+
+/// Writes an archive with a 2048 byte block size, with the paths, ids and data of `entries`,
+/// placing them at the given offsets when they have one. A `first_entry_position` of 0 puts the
+/// entry table right after the data.
+pub fn wad_bytes(
+    first_entry_position: u32,
+    entries: &[(&str, u32, &[u8], Option<u32>)],
+) -> Vec<u8> {
+    let header = WadHeader {
+        magic: WadHeader::MAGIC,
+        version: WadHeader::VERSION,
+        block_size: WadHeader::BLOCK_SIZE,
+        first_entry_position,
+        ..Default::default()
+    };
+
+    let mut writer = WadWriter::with_header(Cursor::new(Vec::new()), header);
+
+    for (path, id, data, offset) in entries {
+        let metadata = WadEntryMetadata {
+            id: *id,
+            offset: *offset,
+            ..Default::default()
+        };
+
+        writer.add_entry_bytes(path, data, &metadata).unwrap();
+    }
+
+    writer.finish().unwrap().into_inner()
+}
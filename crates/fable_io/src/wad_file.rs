@@ -1,15 +1,39 @@
-use std::{fs::File, io, path::Path};
-
-use fable_format::wad::{WadEntryOwned, WadHeader};
+use crate::common::{OffsetError, SubReader};
+use fable_format::{
+    wad::{WadEntry, WadEntryError, WadEntryOwned, WadHeader, WadHeaderError},
+    TakeError, UnexpectedEnd,
+};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    mem,
+    num::TryFromIntError,
+    path::Path,
+};
 
+/// A `.wad` archive on disk with its header and entry table loaded.
 pub struct WadFile {
     source: File,
     header: WadHeader,
     entries: Vec<WadEntryOwned>,
 }
 
+#[derive(Debug)]
 pub enum WadFileError {
     Open(io::Error),
+    ReadHeader(io::Error),
+    Header(WadHeaderError<TakeError>),
+    ReadEntries(io::Error),
+    Entry(OffsetError<WadEntryError<TakeError>>),
+}
+
+#[derive(Debug)]
+pub enum WadReplaceError {
+    NotFound,
+    Io(io::Error),
+    Length(TryFromIntError),
+    Offset(TryFromIntError),
+    Entry(WadEntryError<UnexpectedEnd>),
 }
 
 impl WadFile {
@@ -18,8 +42,402 @@ impl WadFile {
 
         let file = File::open(path).map_err(Open)?;
 
-        let header_bytes = vec![0; WadHeader::byte_size()];
+        Self::from_file(file)
+    }
+
+    /// Opens the archive for reading and writing, which [`WadFile::replace_entry`] needs.
+    pub fn open_writable<P: AsRef<Path>>(path: P) -> Result<WadFile, WadFileError> {
+        use WadFileError::*;
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(Open)?;
+
+        Self::from_file(file)
+    }
+
+    pub fn from_file(mut source: File) -> Result<WadFile, WadFileError> {
+        use WadFileError::*;
+
+        let mut header_bytes = vec![0; WadHeader::byte_size()];
+
+        source.read_exact(&mut header_bytes).map_err(ReadHeader)?;
+
+        let header = WadHeader::from_bytes(&header_bytes).map_err(Header)?;
+
+        source
+            .seek(SeekFrom::Start(header.first_entry_position as u64))
+            .map_err(ReadEntries)?;
+
+        let entries_bytes = read_entry_table(&source, header.entry_count).map_err(ReadEntries)?;
+
+        let mut entries =
+            Vec::with_capacity((header.entry_count as usize).min(entries_bytes.len()));
+        let mut inp = &entries_bytes[..];
+
+        for _ in 0..header.entry_count {
+            let position = header.first_entry_position as usize + (entries_bytes.len() - inp.len());
+
+            let entry = WadEntry::parse(&mut inp)
+                .map_err(|error| Entry(OffsetError { position, error }))?;
+
+            entries.push(entry.to_owned());
+        }
+
+        Ok(WadFile {
+            source,
+            header,
+            entries,
+        })
+    }
+
+    pub fn header(&self) -> &WadHeader {
+        &self.header
+    }
+
+    pub fn entries(&self) -> &[WadEntryOwned] {
+        &self.entries
+    }
+
+    /// Finds an entry by its path. Paths are compared ignoring ASCII case, like Windows does.
+    pub fn entry_by_path(&self, path: &str) -> Option<&WadEntryOwned> {
+        self.entries
+            .iter()
+            .find(|entry| entry.path.eq_ignore_ascii_case(path))
+    }
+
+    pub fn entry_by_id(&self, id: u32) -> Option<&WadEntryOwned> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    /// Opens a reader over only the entry's data.
+    pub fn open_entry(&self, entry: &WadEntryOwned) -> SubReader<&File> {
+        SubReader::new(&self.source, entry.offset as u64, entry.length as u64)
+    }
+
+    /// Reads all of the entry's data.
+    pub fn read_entry(&self, entry: &WadEntryOwned) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(entry.length as usize);
+        self.open_entry(entry).read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl WadFile {
+    /// Replaces the data of the entry at `path` without rewriting the rest of the archive.
+    ///
+    /// The data is overwritten in place when it fits in the entry's slot: the blocks it already
+    /// occupies, up to the next entry's data or the entry table. Otherwise, or when another entry
+    /// shares the old data, it is appended to the end of the archive. Either way only the entry's
+    /// record in the entry table is patched.
+    pub fn replace_entry(
+        &mut self,
+        path: &str,
+        data: &[u8],
+    ) -> Result<&WadEntryOwned, WadReplaceError> {
+        use WadReplaceError::*;
+
+        let index = self
+            .entries
+            .iter()
+            .position(|entry| entry.path.eq_ignore_ascii_case(path))
+            .ok_or(NotFound)?;
+
+        let block_size = self.header.block_size.max(1) as u64;
+        let entry = &self.entries[index];
+        let old_offset = entry.offset as u64;
+        let old_length = entry.length as u64;
+
+        let entries_size = self
+            .entries
+            .iter()
+            .map(|entry| entry.as_entry().byte_size() as u64)
+            .sum::<u64>();
+
+        let others = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(other_index, _)| *other_index != index)
+            .map(|(_, other)| (other.offset as u64, other.length as u64))
+            .chain([(self.header.first_entry_position as u64, entries_size)])
+            .filter(|(_, length)| *length > 0);
+
+        let mut slot_end = (old_offset + old_length).next_multiple_of(block_size);
+        let mut shared = false;
+
+        for (start, length) in others {
+            if start <= old_offset {
+                shared |= old_offset < start + length;
+            } else if start < old_offset + old_length {
+                shared = true;
+            } else {
+                slot_end = slot_end.min(start);
+            }
+        }
+
+        let length = u32::try_from(data.len()).map_err(Length)?;
+
+        let offset = if !shared && old_offset + length as u64 <= slot_end {
+            old_offset
+        } else {
+            let end = self.source.seek(SeekFrom::End(0)).map_err(Io)?;
+            end.next_multiple_of(block_size)
+        };
+
+        self.source.seek(SeekFrom::Start(offset)).map_err(Io)?;
+        self.source.write_all(data).map_err(Io)?;
+
+        if offset == old_offset && old_length > length as u64 {
+            let stale = vec![0; (old_length - length as u64) as usize];
+            self.source.write_all(&stale).map_err(Io)?;
+        }
+
+        let entry_position = self.header.first_entry_position as u64
+            + self.entries[..index]
+                .iter()
+                .map(|entry| entry.as_entry().byte_size() as u64)
+                .sum::<u64>();
+
+        let entry = &mut self.entries[index];
+
+        entry.offset = u32::try_from(offset).map_err(Offset)?;
+        entry.length = length;
+
+        let entry = entry.as_entry();
+        let mut entry_bytes = vec![0; entry.byte_size()];
+
+        entry.serialize(&mut &mut entry_bytes[..]).map_err(Entry)?;
+
+        self.source
+            .seek(SeekFrom::Start(entry_position))
+            .map_err(Io)?;
+
+        self.source.write_all(&entry_bytes).map_err(Io)?;
+        self.source.flush().map_err(Io)?;
+
+        Ok(&self.entries[index])
+    }
+}
+
+/// Reads the table of `entry_count` entries at the position of `source`, one entry at a time so
+/// that nothing past the table is read. The bytes stop early at the end of the file.
+pub(crate) fn read_entry_table<R: Read>(source: R, entry_count: u32) -> io::Result<Vec<u8>> {
+    let mut source = BufReader::new(source);
+    let mut bytes = Vec::new();
+
+    for _ in 0..entry_count {
+        let start = bytes.len();
+
+        source
+            .by_ref()
+            .take(WadEntry::PATH_OFFSET as u64)
+            .read_to_end(&mut bytes)?;
+
+        let Some(path_len) = bytes
+            .get(start + WadEntry::PATH_OFFSET - mem::size_of::<u32>()..)
+            .and_then(|x| <[u8; 4]>::try_from(x).ok())
+            .map(u32::from_le_bytes)
+        else {
+            break;
+        };
+
+        let size = path_len as u64 + WadEntry::AFTER_PATH_SIZE as u64;
+        let read = source.by_ref().take(size).read_to_end(&mut bytes)?;
+
+        if read as u64 != size {
+            break;
+        }
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{temp_path, wad_bytes};
+    use std::fs;
+
+    fn read_all(path: &Path) -> Vec<(String, u32, Vec<u8>)> {
+        let wad = WadFile::open(path).unwrap();
+
+        wad.entries()
+            .iter()
+            .map(|entry| {
+                (
+                    entry.path.clone(),
+                    entry.offset,
+                    wad.read_entry(entry).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn open_and_read() {
+        let path = temp_path("open_and_read.wad");
+        fs::write(
+            &path,
+            wad_bytes(
+                0,
+                &[
+                    ("Data\\A.tng", 0, b"first", None),
+                    ("Data\\B.lev", 1, b"second", None),
+                ],
+            ),
+        )
+        .unwrap();
+
+        let wad = WadFile::open(&path).unwrap();
+
+        assert_eq!(wad.header().entry_count, 2);
+        assert_eq!(wad.header().first_entry_position, 3 * 2048);
+
+        let entry = wad.entry_by_path("data\\b.LEV").unwrap();
+        assert_eq!(entry.id, 1);
+        assert_eq!(wad.read_entry(entry).unwrap(), b"second");
+        assert_eq!(wad.entry_by_id(0).unwrap().path, "Data\\A.tng");
+        assert!(wad.entry_by_path("Data\\C.tng").is_none());
+
+        let mut reader = wad.open_entry(entry);
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"second");
+
+        // Entry readers seek within the entry.
+        reader.seek(SeekFrom::Start(3)).unwrap();
+        data.clear();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"ond");
+        assert_eq!(reader.seek(SeekFrom::End(-1)).unwrap(), 5);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn open_truncated() {
+        let path = temp_path("open_truncated.wad");
+        fs::write(&path, wad_bytes(0, &[("A", 0, b"first", None)])).unwrap();
+
+        let length = fs::metadata(&path).unwrap().len();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(length - 4)
+            .unwrap();
+
+        assert!(matches!(WadFile::open(&path), Err(WadFileError::Entry(_))));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replace_in_place() {
+        let path = temp_path("replace_in_place.wad");
+        fs::write(
+            &path,
+            wad_bytes(0, &[("A", 0, &[1; 100], None), ("B", 1, &[2; 100], None)]),
+        )
+        .unwrap();
+
+        let mut wad = WadFile::open_writable(&path).unwrap();
+
+        // Growing within the entry's block keeps its offset.
+        assert_eq!(wad.replace_entry("a", &[3; 2000]).unwrap().offset, 2048);
+        // Shrinking clears the rest of the old data.
+        assert_eq!(wad.replace_entry("A", &[4; 10]).unwrap().offset, 2048);
+
+        let mut bytes = vec![0; 2000];
+        wad.source.seek(SeekFrom::Start(2048)).unwrap();
+        wad.source.read_exact(&mut bytes).unwrap();
+        assert_eq!(bytes[..10], [4; 10]);
+        assert!(bytes[10..].iter().all(|x| *x == 0));
+
+        assert!(matches!(
+            wad.replace_entry("C", b""),
+            Err(WadReplaceError::NotFound)
+        ));
+
+        drop(wad);
+
+        assert_eq!(
+            read_all(&path),
+            [
+                ("A".to_owned(), 2048, vec![4; 10]),
+                ("B".to_owned(), 4096, vec![2; 100])
+            ]
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replace_bounded_by_neighbours() {
+        let path = temp_path("replace_bounded.wad");
+
+        // B starts right after A in the same block, and the entry table right after B.
+        fs::write(
+            &path,
+            wad_bytes(
+                2300,
+                &[
+                    ("A", 0, &[1; 100], Some(2048)),
+                    ("B", 1, &[2; 100], Some(2148)),
+                ],
+            ),
+        )
+        .unwrap();
+
+        let mut wad = WadFile::open_writable(&path).unwrap();
+        assert_eq!(wad.header().first_entry_position, 2300);
+
+        let end = wad.source.seek(SeekFrom::End(0)).unwrap();
+
+        let offset = wad.replace_entry("A", &[3; 101]).unwrap().offset as u64;
+        assert_eq!(offset, end.next_multiple_of(2048));
+
+        // B may grow up to the entry table, but no further.
+        assert_eq!(wad.replace_entry("B", &[4; 152]).unwrap().offset, 2148);
+        assert_ne!(wad.replace_entry("B", &[5; 153]).unwrap().offset, 2148);
+
+        drop(wad);
+
+        let entries = read_all(&path);
+        assert_eq!(entries[0].2, [3; 101]);
+        assert_eq!(entries[1].2, [5; 153]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replace_shared_data() {
+        let path = temp_path("replace_shared.wad");
+        fs::write(
+            &path,
+            wad_bytes(
+                0,
+                &[
+                    ("A", 0, &[1; 100], Some(2048)),
+                    ("B", 1, &[1; 100], Some(2048)),
+                ],
+            ),
+        )
+        .unwrap();
+
+        let mut wad = WadFile::open_writable(&path).unwrap();
+
+        assert_ne!(wad.replace_entry("A", &[2; 10]).unwrap().offset, 2048);
+
+        drop(wad);
+
+        let entries = read_all(&path);
+        assert_eq!(entries[0].2, [2; 10]);
+        assert_eq!(entries[1], ("B".to_owned(), 2048, vec![1; 100]));
 
-        Ok(WadFile { file })
+        fs::remove_file(&path).unwrap();
     }
 }
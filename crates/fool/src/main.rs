@@ -1,7 +1,11 @@
+mod mesh;
 mod subcommand;
 
+#[cfg(test)]
+mod test_support;
+
 use clap::{Parser, Subcommand};
-use subcommand::{LevArgs, TngArgs, WadArgs};
+use subcommand::{BbaArgs, BigArgs, LevArgs, TngArgs, WadArgs};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -20,6 +24,12 @@ enum Commands {
     #[command(arg_required_else_help = true)]
     Wad(WadArgs),
 
+    #[command(arg_required_else_help = true)]
+    Big(BigArgs),
+
+    #[command(arg_required_else_help = true)]
+    Bba(BbaArgs),
+
     #[command(arg_required_else_help = true)]
     Lev(LevArgs),
 
@@ -35,7 +45,9 @@ fn main() {
             let message = err.to_string();
             let snippet = annotate_snippets::Level::Error.title(&message);
             anstream::eprintln!("{}", renderer.render(snippet));
-        })
+        });
+
+        std::process::exit(1);
     }
 }
 
@@ -45,6 +57,8 @@ fn try_main() -> anyhow::Result<()> {
     match cli.command {
         None => Ok(()),
         Some(Commands::Wad(args)) => subcommand::wad::handle(args),
+        Some(Commands::Big(args)) => subcommand::big::handle(args),
+        Some(Commands::Bba(args)) => subcommand::bba::handle(args),
         Some(Commands::Lev(args)) => subcommand::lev::handle(args),
         Some(Commands::Tng(args)) => subcommand::tng::handle(args),
     }
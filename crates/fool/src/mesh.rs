@@ -0,0 +1,23 @@
+pub mod gltf;
+pub mod obj;
+
+use fable_format::bbm::{Bbm, BbmPrimitive};
+
+/// The position in the model's material list of a primitive's material.
+fn primitive_material(bbm: &Bbm, primitive: &BbmPrimitive) -> Option<usize> {
+    bbm.materials
+        .iter()
+        .position(|x| x.index == primitive.material_index)
+}
+
+/// Scales a normal to unit length. Packed normals are only roughly unit length, and zero ones
+/// point up.
+fn unit_normal([x, y, z]: [f32; 3]) -> [f32; 3] {
+    let length = (x * x + y * y + z * z).sqrt();
+
+    if length > 0.0 {
+        [x / length, y / length, z / length]
+    } else {
+        [0.0, 0.0, 1.0]
+    }
+}
@@ -0,0 +1,1561 @@
+//! Conversion of compiled `.bbm` meshes to binary glTF 2.0, with `.bba` animations of their bones
+//! kept undecoded.
+//!
+//! Matrices in `.bbm` files are Direct3D style: the x, y and z axes then the translation, which
+//! is also the order of glTF's column-major matrices. Coordinates are written as they are.
+//!
+//! Anything glTF has no place for is kept in `extras`, with `fable_type` telling bones, helper
+//! points and helper dummies apart. [`gltf_to_bbm`] reads those `extras` back, so a model survives
+//! a round trip. Cloths are kept decoded, or as hex when their data can't be decoded.
+
+use super::{primitive_material, unit_normal};
+use ::gltf::{
+    binary::{Glb, Header},
+    json::{
+        self,
+        accessor::{ComponentType, GenericComponentType, Type},
+        buffer::Target,
+        material::{AlphaMode, PbrMetallicRoughness, StrengthFactor},
+        mesh::{Mode, Primitive, Semantic},
+        validation::{Checked::Valid, USize64},
+        Index,
+    },
+    material::AlphaMode as ReadAlphaMode,
+    mesh::Mode as ReadMode,
+};
+use anyhow::anyhow;
+use fable_format::bba::Bba;
+use fable_format::bbm::{
+    Bbm, BbmBone, BbmBonePrimitive, BbmCloth, BbmClothData, BbmHelperDummy, BbmHelperPoint,
+    BbmMaterial, BbmPrimitive, BbmSubMesh, BbmVertex,
+};
+use serde::de::DeserializeOwned;
+use serde_json::{json, value::RawValue};
+use std::{borrow::Cow, collections::BTreeMap, collections::HashMap, mem, path::Path};
+
+/// Converts a model to a `.glb` file.
+///
+/// `texture_uris` maps texture ids, as used by materials, to the URIs of exported images.
+/// Materials whose textures are missing from it keep only their ids, in `extras`.
+///
+/// The tracks of each of `animations` are kept in the model's `extras`, by name. See
+/// [`animation_extras`].
+pub fn bbm_to_glb(
+    bbm: &Bbm,
+    texture_uris: &HashMap<u32, String>,
+    animations: &[(String, Bba)],
+) -> anyhow::Result<Vec<u8>> {
+    let mut builder = Builder::default();
+
+    builder.root.asset.generator = Some("fool".to_owned());
+
+    let materials = builder.materials(bbm, texture_uris);
+    let bones = builder.bones(bbm);
+    let skin = builder.skin(bbm, &bones);
+
+    let mut children = bones
+        .iter()
+        .zip(&bbm.bones)
+        .filter(|(_, bone)| parent(bone.parent, bbm.bones.len()).is_none())
+        .map(|(&node, _)| node)
+        .collect::<Vec<_>>();
+
+    for (index, sub_mesh) in bbm.sub_meshes.iter().enumerate() {
+        if let Some(node) = builder.sub_mesh(bbm, index, sub_mesh, &materials, skin) {
+            children.push(node);
+        }
+    }
+
+    for (index, helper) in bbm.helper_points.iter().enumerate() {
+        let node = builder.root.push(json::Node {
+            name: Some(name(
+                bbm.helper_point_names.get(index),
+                "helper_point",
+                index,
+            )),
+            translation: Some([helper.matrix[0], helper.matrix[1], helper.matrix[2]]),
+            extras: extras(json!({
+                "fable_type": "helper_point",
+                "unknown": helper.matrix[3],
+            })),
+            ..Default::default()
+        });
+
+        builder.attach(node, helper.hierarchy, &bones, &mut children);
+    }
+
+    for (index, helper) in bbm.helper_dummies.iter().enumerate() {
+        let matrix = matrix_4x3(helper.matrix[..12].try_into().unwrap());
+
+        let node = builder.root.push(json::Node {
+            name: Some(name(
+                bbm.helper_dummy_names.get(index),
+                "helper_dummy",
+                index,
+            )),
+            matrix: (matrix != IDENTITY).then_some(matrix),
+            extras: extras(json!({
+                "fable_type": "helper_dummy",
+                "unknown": helper.matrix[12],
+            })),
+            ..Default::default()
+        });
+
+        builder.attach(node, helper.hierarchy, &bones, &mut children);
+    }
+
+    let mut model = json!({
+        "fable_type": "model",
+        "has_skeleton": bbm.has_skeleton,
+        "origin": bbm.origin,
+        "transform": bbm.transform,
+        "bone_index_reference": bbm.bone_index_reference,
+        "unknown": [bbm.unknown1, bbm.unknown2, bbm.unknown3],
+    });
+
+    if !animations.is_empty() {
+        model["animations"] = animations
+            .iter()
+            .map(|(name, bba)| animation_extras(name, bba, bbm))
+            .collect::<anyhow::Result<_>>()?;
+    }
+
+    let root = builder.root.push(json::Node {
+        name: Some(String::from_utf8_lossy(&bbm.name).into_owned()),
+        children: Some(children),
+        extras: extras(model),
+        ..Default::default()
+    });
+
+    let scene = builder.root.push(json::Scene {
+        extensions: None,
+        extras: None,
+        name: None,
+        nodes: vec![root],
+    });
+
+    builder.root.scene = Some(scene);
+
+    builder.finish()
+}
+
+const IDENTITY: [f32; 16] = [
+    1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+];
+
+#[derive(Default)]
+struct Builder {
+    root: json::Root,
+    bin: Vec<u8>,
+}
+
+impl Builder {
+    fn materials(
+        &mut self,
+        bbm: &Bbm,
+        texture_uris: &HashMap<u32, String>,
+    ) -> Vec<Index<json::Material>> {
+        let mut textures = HashMap::new();
+
+        bbm.materials
+            .iter()
+            .map(|material| {
+                let base_color_texture = texture_uris
+                    .get(&material.base_texture_id)
+                    .filter(|_| material.base_texture_id != 0)
+                    .map(|uri| {
+                        let index =
+                            *textures.entry(material.base_texture_id).or_insert_with(|| {
+                                let source = self.root.push(json::Image {
+                                    buffer_view: None,
+                                    mime_type: None,
+                                    name: None,
+                                    uri: Some(uri.clone()),
+                                    extensions: None,
+                                    extras: None,
+                                });
+
+                                self.root.push(json::Texture {
+                                    name: None,
+                                    sampler: None,
+                                    source,
+                                    extensions: None,
+                                    extras: None,
+                                })
+                            });
+
+                        json::texture::Info {
+                            index,
+                            tex_coord: 0,
+                            extensions: None,
+                            extras: None,
+                        }
+                    });
+
+                let alpha_mode = if material.alpha_enabled != 0 {
+                    AlphaMode::Blend
+                } else {
+                    AlphaMode::Opaque
+                };
+
+                self.root.push(json::Material {
+                    name: Some(String::from_utf8_lossy(&material.name).into_owned()),
+                    alpha_mode: Valid(alpha_mode),
+                    pbr_metallic_roughness: PbrMetallicRoughness {
+                        base_color_texture,
+                        metallic_factor: StrengthFactor(0.0),
+                        ..Default::default()
+                    },
+                    extras: extras(json!({
+                        "index": material.index,
+                        "base_texture_id": material.base_texture_id,
+                        "bump_map_texture_id": material.bump_map_texture_id,
+                        "reflection_texture_id": material.reflection_texture_id,
+                        "max_texture_layers": material.max_texture_layers,
+                        "glow_strength": material.glow_strength,
+                        "alpha_enabled": material.alpha_enabled,
+                        "degenerate_triangles": material.degenerate_triangles,
+                        "unknown": [
+                            material.padding,
+                            material.unknown1,
+                            material.unknown2,
+                            material.unknown3,
+                        ],
+                    })),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    /// Adds a node per bone, with its children, and returns them in bone order.
+    fn bones(&mut self, bbm: &Bbm) -> Vec<Index<json::Node>> {
+        let first = self.root.nodes.len() as u32;
+        let count = bbm.bones.len();
+
+        for (index, bone) in bbm.bones.iter().enumerate() {
+            let children = (0..count)
+                .filter(|&child| parent(bbm.bones[child].parent, count) == Some(index))
+                .filter(|&child| !has_cycle(bbm, child))
+                .map(|child| Index::new(first + child as u32))
+                .collect::<Vec<_>>();
+
+            let matrix = matrix_4x3(&bone.transform);
+
+            self.root.push(json::Node {
+                name: Some(name(Some(&bone.name), "bone", index)),
+                children: (!children.is_empty()).then_some(children),
+                matrix: (matrix != IDENTITY).then_some(matrix),
+                extras: extras(json!({
+                    "fable_type": "bone",
+                    "index": bone.index,
+                    "child_count": bone.child_count,
+                    "world_transform": bone.world_transform,
+                    "inverse_bind": bone.inverse_bind,
+                })),
+                ..Default::default()
+            });
+        }
+
+        (0..count)
+            .map(|index| Index::new(first + index as u32))
+            .collect()
+    }
+
+    /// Adds the skin of all bones, with the inverse bind matrices of the file, so the mesh is
+    /// posed the way the game poses it. Bones whose inverse bind isn't an invertible affine matrix
+    /// get one worked out from the bone transforms instead, which leaves the mesh at rest.
+    fn skin(&mut self, bbm: &Bbm, bones: &[Index<json::Node>]) -> Option<Index<json::Skin>> {
+        if bones.is_empty() {
+            return None;
+        }
+
+        let inverse_binds = (0..bbm.bones.len())
+            .map(|index| match bbm.bones[index].inverse_bind {
+                inverse_bind if is_invertible_affine(&inverse_bind) => inverse_bind,
+                _ => invert_affine(&world_transform(bbm, index)),
+            })
+            .collect::<Vec<_>>();
+
+        let view = self.view(bytemuck::cast_slice(&inverse_binds), None);
+        let accessor = self.accessor(
+            view,
+            inverse_binds.len(),
+            ComponentType::F32,
+            Type::Mat4,
+            None,
+        );
+
+        Some(self.root.push(json::Skin {
+            extensions: None,
+            extras: None,
+            inverse_bind_matrices: Some(accessor),
+            joints: bones.to_vec(),
+            name: None,
+            skeleton: None,
+        }))
+    }
+
+    fn sub_mesh(
+        &mut self,
+        bbm: &Bbm,
+        index: usize,
+        sub_mesh: &BbmSubMesh,
+        materials: &[Index<json::Material>],
+        skin: Option<Index<json::Skin>>,
+    ) -> Option<Index<json::Node>> {
+        let vertices = &sub_mesh.vertices;
+
+        if vertices.is_empty() || sub_mesh.indices.is_empty() {
+            return None;
+        }
+
+        let positions = vertices.iter().map(|x| x.position).collect::<Vec<_>>();
+
+        let (min, max) = positions.iter().fold(
+            ([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]),
+            |(min, max), position| {
+                (
+                    [0, 1, 2].map(|i| min[i].min(position[i])),
+                    [0, 1, 2].map(|i| max[i].max(position[i])),
+                )
+            },
+        );
+
+        let normals = vertices
+            .iter()
+            .map(|x| unit_normal(x.normal))
+            .collect::<Vec<_>>();
+
+        let uvs = vertices.iter().map(|x| x.uv).collect::<Vec<_>>();
+
+        let mut attributes = BTreeMap::new();
+
+        let view = self.view(bytemuck::cast_slice(&positions), Some(Target::ArrayBuffer));
+        let accessor = self.accessor(
+            view,
+            positions.len(),
+            ComponentType::F32,
+            Type::Vec3,
+            Some((json!(min), json!(max))),
+        );
+        attributes.insert(Valid(Semantic::Positions), accessor);
+
+        let view = self.view(bytemuck::cast_slice(&normals), Some(Target::ArrayBuffer));
+        let accessor = self.accessor(view, normals.len(), ComponentType::F32, Type::Vec3, None);
+        attributes.insert(Valid(Semantic::Normals), accessor);
+
+        let view = self.view(bytemuck::cast_slice(&uvs), Some(Target::ArrayBuffer));
+        let accessor = self.accessor(view, uvs.len(), ComponentType::F32, Type::Vec2, None);
+        attributes.insert(Valid(Semantic::TexCoords(0)), accessor);
+
+        let skinned = skin.is_some()
+            && sub_mesh
+                .layout(!bbm.bones.is_empty())
+                .is_some_and(|x| x.has_bones());
+
+        if skinned {
+            let bone_count = bbm.bones.len();
+
+            let (joints, weights): (Vec<[u8; 4]>, Vec<[f32; 4]>) = vertices
+                .iter()
+                .map(|vertex| {
+                    let mut joints = vertex.bone_indices;
+                    let mut weights = vertex.bone_weights.map(|x| x as f32);
+
+                    for (joint, weight) in joints.iter_mut().zip(&mut weights) {
+                        if *joint as usize >= bone_count {
+                            *joint = 0;
+                            *weight = 0.0;
+                        }
+                    }
+
+                    let sum = weights.iter().sum::<f32>();
+
+                    if sum > 0.0 {
+                        (joints, weights.map(|x| x / sum))
+                    } else {
+                        (joints, [1.0, 0.0, 0.0, 0.0])
+                    }
+                })
+                .unzip();
+
+            let view = self.view(bytemuck::cast_slice(&joints), Some(Target::ArrayBuffer));
+            let accessor = self.accessor(view, joints.len(), ComponentType::U8, Type::Vec4, None);
+            attributes.insert(Valid(Semantic::Joints(0)), accessor);
+
+            let view = self.view(bytemuck::cast_slice(&weights), Some(Target::ArrayBuffer));
+            let accessor = self.accessor(view, weights.len(), ComponentType::F32, Type::Vec4, None);
+            attributes.insert(Valid(Semantic::Weights(0)), accessor);
+        }
+
+        let indices_view = self.view(
+            bytemuck::cast_slice(&sub_mesh.indices),
+            Some(Target::ElementArrayBuffer),
+        );
+
+        let ranges = if sub_mesh.primitives.is_empty() {
+            vec![(None, 0, sub_mesh.indices.len())]
+        } else {
+            sub_mesh
+                .primitives
+                .iter()
+                .map(|primitive| {
+                    let material = primitive_material(bbm, primitive)
+                        .and_then(|x| materials.get(x))
+                        .copied();
+
+                    let start = primitive.start_index as usize;
+                    let count = primitive.index_count as usize;
+
+                    (material, start, count)
+                })
+                .filter(|&(_, start, count)| {
+                    count > 0 && start.saturating_add(count) <= sub_mesh.indices.len()
+                })
+                .collect()
+        };
+
+        let primitives = ranges
+            .into_iter()
+            .map(|(material, start, count)| {
+                let indices = self.root.push(json::Accessor {
+                    buffer_view: Some(indices_view),
+                    byte_offset: Some(USize64::from(start * mem::size_of::<u16>())),
+                    count: USize64::from(count),
+                    component_type: Valid(GenericComponentType(ComponentType::U16)),
+                    extensions: None,
+                    extras: None,
+                    type_: Valid(Type::Scalar),
+                    min: None,
+                    max: None,
+                    name: None,
+                    normalized: false,
+                    sparse: None,
+                });
+
+                Primitive {
+                    attributes: attributes.clone(),
+                    extensions: None,
+                    extras: None,
+                    indices: Some(indices),
+                    material,
+                    mode: Valid(Mode::Triangles),
+                    targets: None,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if primitives.is_empty() {
+            return None;
+        }
+
+        let name = format!("sub_mesh_{}", index);
+
+        let mesh = self.root.push(json::Mesh {
+            extensions: None,
+            extras: None,
+            name: Some(name.clone()),
+            primitives,
+            weights: None,
+        });
+
+        Some(self.root.push(json::Node {
+            name: Some(name),
+            mesh: Some(mesh),
+            skin: skinned.then_some(skin).flatten(),
+            extras: extras(json!({
+                "fable_type": "sub_mesh",
+                "hierarchy": sub_mesh.hierarchy,
+                "destroyable_mesh_levels": sub_mesh.destroyable_mesh_levels,
+                "vertex_layout": sub_mesh.vertex_layout,
+                "bounds": sub_mesh.bounds,
+                "bone_primitives": sub_mesh.bone_primitives.iter().map(|x| {
+                    [x.start_index, x.index_count, x.bone, x.unknown]
+                }).collect::<Vec<_>>(),
+                "unknown": sub_mesh.unknown1,
+                "cloths": sub_mesh.cloths.iter().map(cloth_extras).collect::<Vec<_>>(),
+            })),
+            ..Default::default()
+        }))
+    }
+
+    /// Makes `node` a child of the bone `hierarchy`, or of the model when there's no such bone.
+    fn attach(
+        &mut self,
+        node: Index<json::Node>,
+        hierarchy: i32,
+        bones: &[Index<json::Node>],
+        children: &mut Vec<Index<json::Node>>,
+    ) {
+        match parent(hierarchy, bones.len()) {
+            Some(bone) => self.root.nodes[bones[bone].value()]
+                .children
+                .get_or_insert_with(Vec::new)
+                .push(node),
+            None => children.push(node),
+        }
+    }
+
+    fn view(&mut self, bytes: &[u8], target: Option<Target>) -> Index<json::buffer::View> {
+        while !self.bin.len().is_multiple_of(4) {
+            self.bin.push(0);
+        }
+
+        let byte_offset = self.bin.len();
+        self.bin.extend_from_slice(bytes);
+
+        self.root.push(json::buffer::View {
+            buffer: Index::new(0),
+            byte_length: USize64::from(bytes.len()),
+            byte_offset: Some(USize64::from(byte_offset)),
+            byte_stride: None,
+            name: None,
+            target: target.map(Valid),
+            extensions: None,
+            extras: None,
+        })
+    }
+
+    fn accessor(
+        &mut self,
+        view: Index<json::buffer::View>,
+        count: usize,
+        component_type: ComponentType,
+        type_: Type,
+        bounds: Option<(serde_json::Value, serde_json::Value)>,
+    ) -> Index<json::Accessor> {
+        let (min, max) = bounds.unzip();
+
+        self.root.push(json::Accessor {
+            buffer_view: Some(view),
+            byte_offset: None,
+            count: USize64::from(count),
+            component_type: Valid(GenericComponentType(component_type)),
+            extensions: None,
+            extras: None,
+            type_: Valid(type_),
+            min,
+            max,
+            name: None,
+            normalized: false,
+            sparse: None,
+        })
+    }
+
+    fn finish(mut self) -> anyhow::Result<Vec<u8>> {
+        while !self.bin.len().is_multiple_of(4) {
+            self.bin.push(0);
+        }
+
+        if !self.bin.is_empty() {
+            self.root.push(json::Buffer {
+                byte_length: USize64::from(self.bin.len()),
+                name: None,
+                uri: None,
+                extensions: None,
+                extras: None,
+            });
+        }
+
+        let json = json::serialize::to_vec(&self.root)?;
+
+        let glb = Glb {
+            header: Header {
+                magic: *b"glTF",
+                version: 2,
+                length: 0,
+            },
+            json: Cow::Owned(json),
+            bin: (!self.bin.is_empty()).then_some(Cow::Owned(self.bin)),
+        };
+
+        glb.to_vec()
+            .map_err(|e| anyhow!("failed to write glb. {:?}", e))
+    }
+}
+
+/// Reads a `.gltf` or `.glb` file as a model, leaving the sub-meshes' vertex layouts unset.
+///
+/// Every node with a mesh becomes a sub-mesh, and every triangle list primitive a range of its
+/// indices drawn with one material. The joints of the first skin become the bones, keeping the
+/// skin's inverse bind matrices, or working them out from the pose the bones are in when it has
+/// none. Nodes exported as helpers become helpers again.
+pub fn gltf_to_bbm(path: &Path) -> anyhow::Result<Bbm> {
+    let ::gltf::Gltf { document, blob } =
+        ::gltf::Gltf::open(path).map_err(|e| anyhow!("failed to read glTF file. {}", e))?;
+
+    let buffers = ::gltf::import_buffers(&document, path.parent(), blob)
+        .map_err(|e| anyhow!("failed to read glTF buffers. {}", e))?;
+
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or_else(|| anyhow!("glTF file has no scene."))?;
+
+    // The scene's nodes depth first, with their parents and transforms relative to the scene.
+    let mut nodes = Vec::new();
+    let mut parents = HashMap::new();
+    let mut worlds = HashMap::new();
+    let mut stack = scene
+        .nodes()
+        .map(|node| (node, IDENTITY))
+        .collect::<Vec<_>>();
+
+    stack.reverse();
+
+    while let Some((node, parent_world)) = stack.pop() {
+        if worlds.contains_key(&node.index()) {
+            continue;
+        }
+
+        let world = multiply(&parent_world, &bytemuck::cast(node.transform().matrix()));
+        worlds.insert(node.index(), world);
+
+        let mut children = node.children().collect::<Vec<_>>();
+        children.reverse();
+
+        for child in children {
+            parents.insert(child.index(), node.index());
+            stack.push((child, world));
+        }
+
+        nodes.push(node);
+    }
+
+    let model = nodes
+        .iter()
+        .find(|node| fable_type(node.extras()).as_deref() == Some("model"));
+
+    let mut bbm = Bbm {
+        name: model
+            .and_then(|node| node.name())
+            .or_else(|| path.file_stem().and_then(|x| x.to_str()))
+            .unwrap_or_default()
+            .as_bytes()
+            .to_vec(),
+        transform: [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+        ..Default::default()
+    };
+
+    if let Some(model) = model {
+        let extras = model.extras();
+
+        bbm.origin = extra(extras, "origin").unwrap_or(bbm.origin);
+        bbm.transform = extra(extras, "transform").unwrap_or(bbm.transform);
+        bbm.bone_index_reference = extra(extras, "bone_index_reference").unwrap_or_default();
+
+        if let Some([unknown1, unknown2, unknown3]) = extra::<[u16; 3]>(extras, "unknown") {
+            bbm.unknown1 = unknown1 as u8;
+            bbm.unknown2 = unknown2;
+            bbm.unknown3 = unknown3;
+        }
+    }
+
+    // Bones, by node index.
+    let skin = nodes
+        .iter()
+        .find_map(|node| node.skin())
+        .or_else(|| document.skins().next());
+
+    let joints = skin
+        .as_ref()
+        .map(|skin| skin.joints().map(|x| x.index()).collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    let bone_of = joints
+        .iter()
+        .enumerate()
+        .map(|(bone, &node)| (node, bone))
+        .collect::<HashMap<_, _>>();
+
+    // The nearest ancestor of a node that is a bone.
+    let parent_bone = |node: usize| {
+        let mut current = node;
+
+        for _ in 0..nodes.len() {
+            current = *parents.get(&current)?;
+
+            if let Some(&bone) = bone_of.get(&current) {
+                return Some(bone);
+            }
+        }
+
+        None
+    };
+
+    // A node's transform relative to its bone, or to the model without one.
+    let relative = |node: usize, bone: Option<usize>| {
+        let world = worlds.get(&node).copied().unwrap_or(IDENTITY);
+
+        match bone.and_then(|bone| worlds.get(&joints[bone])) {
+            Some(bone_world) => multiply(&invert_affine(bone_world), &world),
+            None => world,
+        }
+    };
+
+    let inverse_binds = skin
+        .as_ref()
+        .and_then(|skin| {
+            let reader = skin.reader(|buffer| buffers.get(buffer.index()).map(|x| &x[..]));
+            let matrices = reader.read_inverse_bind_matrices()?;
+
+            Some(
+                matrices
+                    .map(bytemuck::cast::<_, [f32; 16]>)
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .unwrap_or_default();
+
+    for (index, &node_index) in joints.iter().enumerate() {
+        let node = document
+            .nodes()
+            .nth(node_index)
+            .ok_or_else(|| anyhow!("skin joint {} is not a node.", node_index))?;
+
+        let parent = parent_bone(node_index);
+        let world = worlds.get(&node_index).copied().unwrap_or(IDENTITY);
+
+        // The file's own matrices win, then the skin's, then those of the pose.
+        let inverse_bind = extra(node.extras(), "inverse_bind")
+            .or_else(|| inverse_binds.get(index).copied())
+            .unwrap_or_else(|| invert_affine(&world));
+
+        bbm.bones.push(BbmBone {
+            name: node
+                .name()
+                .map_or_else(|| format!("bone_{}", index), str::to_owned)
+                .into_bytes(),
+            index: extra(node.extras(), "index").unwrap_or(index as i32),
+            parent: parent.map_or(-1, |x| x as i32),
+            child_count: joints
+                .iter()
+                .filter(|&&x| parent_bone(x) == Some(index))
+                .count() as i32,
+            transform: matrix_3x4(&relative(node_index, parent)),
+            world_transform: extra(node.extras(), "world_transform")
+                .unwrap_or_else(|| matrix_3x4(&world)),
+            inverse_bind,
+        });
+    }
+
+    if bbm.bone_index_reference.len() != bbm.bones.len().saturating_sub(1) {
+        bbm.bone_index_reference = vec![0; bbm.bones.len().saturating_sub(1)];
+    }
+
+    bbm.has_skeleton = (!bbm.bones.is_empty()) as u8;
+
+    for (position, material) in document.materials().enumerate() {
+        let extras = material.extras();
+
+        let [padding, unknown1, unknown2, unknown3] =
+            extra::<[u32; 4]>(extras, "unknown").unwrap_or_default();
+
+        bbm.materials.push(BbmMaterial {
+            index: extra(extras, "index").unwrap_or(position as u32),
+            name: material
+                .name()
+                .map_or_else(|| format!("material_{}", position), str::to_owned)
+                .into_bytes(),
+            padding,
+            base_texture_id: extra(extras, "base_texture_id").unwrap_or_default(),
+            bump_map_texture_id: extra(extras, "bump_map_texture_id").unwrap_or_default(),
+            reflection_texture_id: extra(extras, "reflection_texture_id").unwrap_or_default(),
+            unknown1,
+            max_texture_layers: extra(extras, "max_texture_layers").unwrap_or(1),
+            glow_strength: extra(extras, "glow_strength").unwrap_or_default(),
+            unknown2: unknown2 as u8,
+            alpha_enabled: (material.alpha_mode() != ReadAlphaMode::Opaque) as u8,
+            unknown3: unknown3 as u8,
+            degenerate_triangles: extra(extras, "degenerate_triangles").unwrap_or_default(),
+        });
+    }
+
+    let material_indices = bbm.materials.iter().map(|x| x.index).collect::<Vec<_>>();
+    let mut default_material = None;
+
+    for node in nodes.iter().filter(|node| node.mesh().is_some()) {
+        let Some(mesh) = node.mesh() else {
+            continue;
+        };
+
+        let extras = node.extras();
+        let index = bbm.sub_meshes.len();
+
+        // Skinned vertices are in model space; others are moved there.
+        let skin_bones = node.skin().filter(|_| !bbm.bones.is_empty()).map(|skin| {
+            skin.joints()
+                .map(|joint| bone_of.get(&joint.index()).copied())
+                .collect::<Vec<_>>()
+        });
+
+        let world = worlds.get(&node.index()).copied().unwrap_or(IDENTITY);
+
+        let mut sub_mesh = BbmSubMesh {
+            hierarchy: extra(extras, "hierarchy").unwrap_or_default(),
+            destroyable_mesh_levels: extra(extras, "destroyable_mesh_levels").unwrap_or_default(),
+            unknown1: extra(extras, "unknown").unwrap_or_default(),
+            bone_primitives: extra::<Vec<[u32; 4]>>(extras, "bone_primitives")
+                .unwrap_or_default()
+                .into_iter()
+                .map(
+                    |[start_index, index_count, bone, unknown]| BbmBonePrimitive {
+                        start_index,
+                        index_count,
+                        bone,
+                        unknown,
+                    },
+                )
+                .collect(),
+            cloths: extra::<Vec<serde_json::Value>>(extras, "cloths")
+                .unwrap_or_default()
+                .iter()
+                .map(cloth_from_extras)
+                .collect::<anyhow::Result<_>>()?,
+            ..Default::default()
+        };
+
+        for primitive in mesh.primitives() {
+            if primitive.mode() != ReadMode::Triangles {
+                return Err(anyhow!(
+                    "mesh {} has primitives that are not triangle lists.",
+                    mesh.name().unwrap_or_default()
+                ));
+            }
+
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|x| &x[..]));
+
+            let Some(positions) = reader.read_positions() else {
+                continue;
+            };
+
+            let first = sub_mesh.vertices.len();
+
+            sub_mesh
+                .vertices
+                .extend(positions.map(|position| BbmVertex {
+                    position,
+                    normal: [0.0, 0.0, 1.0],
+                    ..Default::default()
+                }));
+
+            let vertices = &mut sub_mesh.vertices[first..];
+
+            if let Some(normals) = reader.read_normals() {
+                for (vertex, normal) in vertices.iter_mut().zip(normals) {
+                    vertex.normal = normal;
+                }
+            }
+
+            if let Some(uvs) = reader.read_tex_coords(0) {
+                for (vertex, uv) in vertices.iter_mut().zip(uvs.into_f32()) {
+                    vertex.uv = uv;
+                }
+            }
+
+            match (&skin_bones, reader.read_joints(0), reader.read_weights(0)) {
+                (Some(skin_bones), Some(joints), Some(weights)) => {
+                    for ((vertex, joints), weights) in vertices
+                        .iter_mut()
+                        .zip(joints.into_u16())
+                        .zip(weights.into_f32())
+                    {
+                        vertex.bone_indices = vertex_bones(index, skin_bones, joints)?;
+                        vertex.bone_weights = quantize_weights(weights);
+                    }
+                }
+                _ => {
+                    for vertex in vertices.iter_mut() {
+                        vertex.position = transform_point(&world, vertex.position);
+                        vertex.normal = unit_normal(transform_vector(&world, vertex.normal));
+                        vertex.bone_weights = [255, 0, 0, 0];
+                    }
+                }
+            }
+
+            let vertex_count = vertices.len();
+
+            let indices = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+                None => (0..vertex_count as u32).collect(),
+            };
+
+            if sub_mesh.vertices.len() > u16::MAX as usize + 1 {
+                return Err(anyhow!(
+                    "sub-mesh {} has more than {} vertices.",
+                    index,
+                    u16::MAX as usize + 1
+                ));
+            }
+
+            let indices = indices
+                .into_iter()
+                .map(|x| {
+                    if x as usize >= vertex_count {
+                        return Err(anyhow!(
+                            "sub-mesh {} has index {}, but its primitive only has {} vertices.",
+                            index,
+                            x,
+                            vertex_count
+                        ));
+                    }
+
+                    u16::try_from(first + x as usize).map_err(|_| {
+                        anyhow!(
+                            "sub-mesh {} has index {}, which doesn't fit in 16 bits.",
+                            index,
+                            first + x as usize
+                        )
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            let material_index = match primitive.material().index() {
+                Some(x) => material_indices[x],
+                None => *default_material.get_or_insert_with(|| {
+                    let index = material_indices.iter().max().map_or(0, |x| x + 1);
+
+                    bbm.materials.push(BbmMaterial {
+                        index,
+                        name: b"default".to_vec(),
+                        max_texture_layers: 1,
+                        ..Default::default()
+                    });
+
+                    index
+                }),
+            };
+
+            sub_mesh.primitives.push(BbmPrimitive {
+                material_index,
+                repeating_mesh_reps: 0,
+                start_index: sub_mesh.indices.len() as u32,
+                index_count: indices.len() as u32,
+            });
+
+            sub_mesh.indices.extend(indices);
+        }
+
+        sub_mesh.source_vertex_count = sub_mesh.vertices.len() as u32;
+        bbm.sub_meshes.push(sub_mesh);
+    }
+
+    for node in &nodes {
+        let kind = fable_type(node.extras());
+        let bone = parent_bone(node.index());
+        let hierarchy = bone.map_or(-1, |x| x as i32);
+        let matrix = relative(node.index(), bone);
+        let unknown = extra(node.extras(), "unknown").unwrap_or_default();
+        let name = node.name().unwrap_or_default().as_bytes().to_vec();
+
+        match kind.as_deref() {
+            Some("helper_point") => {
+                bbm.helper_points.push(BbmHelperPoint {
+                    matrix: [matrix[12], matrix[13], matrix[14], unknown],
+                    hierarchy,
+                });
+                bbm.helper_point_names.push(name);
+            }
+            Some("helper_dummy") => {
+                let mut dummy = [0.0; 13];
+                dummy[..12].copy_from_slice(&matrix_3x4(&matrix));
+                dummy[12] = unknown;
+
+                bbm.helper_dummies.push(BbmHelperDummy {
+                    matrix: dummy,
+                    hierarchy,
+                });
+                bbm.helper_dummy_names.push(name);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(bbm)
+}
+
+/// The bones a vertex of sub-mesh `sub_mesh` is weighted to, given its joints and the bone of
+/// each joint of the skin, if it is one.
+fn vertex_bones(
+    sub_mesh: usize,
+    skin_bones: &[Option<usize>],
+    joints: [u16; 4],
+) -> anyhow::Result<[u8; 4]> {
+    let mut bones = [0; 4];
+
+    for (bone, joint) in bones.iter_mut().zip(joints) {
+        let index = skin_bones
+            .get(joint as usize)
+            .ok_or_else(|| {
+                anyhow!(
+                    "sub-mesh {} is weighted to joint {}, which its skin doesn't have.",
+                    sub_mesh,
+                    joint
+                )
+            })?
+            .ok_or_else(|| {
+                anyhow!(
+                    "sub-mesh {} is weighted to joint {}, which is not a bone of the model.",
+                    sub_mesh,
+                    joint
+                )
+            })?;
+
+        *bone = u8::try_from(index).map_err(|_| {
+            anyhow!(
+                "sub-mesh {} is weighted to bone {}, but vertices can only refer to the first 256 bones.",
+                sub_mesh,
+                index
+            )
+        })?;
+    }
+
+    Ok(bones)
+}
+
+/// The index of a bone's parent, if it refers to a bone.
+fn parent(parent: i32, bone_count: usize) -> Option<usize> {
+    usize::try_from(parent).ok().filter(|&x| x < bone_count)
+}
+
+/// Whether following the parents from `bone` never reaches a root.
+fn has_cycle(bbm: &Bbm, bone: usize) -> bool {
+    let count = bbm.bones.len();
+    let mut current = bone;
+
+    for _ in 0..=count {
+        match parent(bbm.bones[current].parent, count) {
+            Some(parent) => current = parent,
+            None => return false,
+        }
+    }
+
+    true
+}
+
+fn world_transform(bbm: &Bbm, bone: usize) -> [f32; 16] {
+    let count = bbm.bones.len();
+    let mut world = matrix_4x3(&bbm.bones[bone].transform);
+    let mut current = bone;
+
+    for _ in 0..count {
+        match parent(bbm.bones[current].parent, count) {
+            Some(parent) => {
+                world = multiply(&matrix_4x3(&bbm.bones[parent].transform), &world);
+                current = parent;
+            }
+            None => break,
+        }
+    }
+
+    world
+}
+
+/// Expands a Direct3D style 4x3 matrix to a column-major 4x4 one.
+fn matrix_4x3(m: &[f32; 12]) -> [f32; 16] {
+    [
+        m[0], m[1], m[2], 0.0, m[3], m[4], m[5], 0.0, m[6], m[7], m[8], 0.0, m[9], m[10], m[11],
+        1.0,
+    ]
+}
+
+/// Shrinks a column-major 4x4 matrix with no projection to a Direct3D style 4x3 one.
+fn matrix_3x4(m: &[f32; 16]) -> [f32; 12] {
+    [
+        m[0], m[1], m[2], m[4], m[5], m[6], m[8], m[9], m[10], m[12], m[13], m[14],
+    ]
+}
+
+fn transform_point(m: &[f32; 16], [x, y, z]: [f32; 3]) -> [f32; 3] {
+    [0, 1, 2].map(|row| m[row] * x + m[4 + row] * y + m[8 + row] * z + m[12 + row])
+}
+
+fn transform_vector(m: &[f32; 16], [x, y, z]: [f32; 3]) -> [f32; 3] {
+    [0, 1, 2].map(|row| m[row] * x + m[4 + row] * y + m[8 + row] * z)
+}
+
+/// Multiplies two column-major 4x4 matrices.
+fn multiply(a: &[f32; 16], b: &[f32; 16]) -> [f32; 16] {
+    let mut out = [0.0; 16];
+
+    for column in 0..4 {
+        for row in 0..4 {
+            out[column * 4 + row] = (0..4).map(|k| a[k * 4 + row] * b[column * 4 + k]).sum();
+        }
+    }
+
+    out
+}
+
+/// The determinant of the rotation and scale part of an affine matrix.
+fn determinant(m: &[f32; 16]) -> f32 {
+    let [a, b, c] = [m[0], m[4], m[8]];
+    let [d, e, f] = [m[1], m[5], m[9]];
+    let [g, h, i] = [m[2], m[6], m[10]];
+
+    a * (e * i - f * h) - b * (d * i - f * g) + c * (d * h - e * g)
+}
+
+/// Whether a column-major matrix has no projection and a non-zero determinant.
+fn is_invertible_affine(m: &[f32; 16]) -> bool {
+    [m[3], m[7], m[11], m[15]] == [0.0, 0.0, 0.0, 1.0] && determinant(m).abs() >= f32::EPSILON
+}
+
+/// Inverts a column-major matrix with no projection. Singular matrices give the identity.
+fn invert_affine(m: &[f32; 16]) -> [f32; 16] {
+    let [a, b, c] = [m[0], m[4], m[8]];
+    let [d, e, f] = [m[1], m[5], m[9]];
+    let [g, h, i] = [m[2], m[6], m[10]];
+
+    let determinant = determinant(m);
+
+    if determinant.abs() < f32::EPSILON {
+        return IDENTITY;
+    }
+
+    let r = [
+        (e * i - f * h) / determinant,
+        (c * h - b * i) / determinant,
+        (b * f - c * e) / determinant,
+        (f * g - d * i) / determinant,
+        (a * i - c * g) / determinant,
+        (c * d - a * f) / determinant,
+        (d * h - e * g) / determinant,
+        (b * g - a * h) / determinant,
+        (a * e - b * d) / determinant,
+    ];
+
+    let t = [m[12], m[13], m[14]];
+    let translation = [0, 1, 2].map(|row| -(0..3).map(|k| r[row * 3 + k] * t[k]).sum::<f32>());
+
+    [
+        r[0],
+        r[3],
+        r[6],
+        0.0,
+        r[1],
+        r[4],
+        r[7],
+        0.0,
+        r[2],
+        r[5],
+        r[8],
+        0.0,
+        translation[0],
+        translation[1],
+        translation[2],
+        1.0,
+    ]
+}
+
+fn name(name: Option<&Vec<u8>>, kind: &str, index: usize) -> String {
+    match name {
+        Some(name) if !name.is_empty() => String::from_utf8_lossy(name).into_owned(),
+        _ => format!("{}_{}", kind, index),
+    }
+}
+
+fn extras(value: serde_json::Value) -> json::Extras {
+    RawValue::from_string(value.to_string()).ok()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{x:02x}")).collect()
+}
+
+/// An animation's tracks, with the bytes of their `AMSK` and `XSEQ` chunks in hex, as the layout
+/// of the keyframes isn't known. A track is matched to the bone with its name, or to the bone at
+/// its position when it has none.
+fn animation_extras(name: &str, bba: &Bba, bbm: &Bbm) -> anyhow::Result<serde_json::Value> {
+    let bones = bba
+        .tracks
+        .iter()
+        .enumerate()
+        .map(|(index, track)| match &track.name {
+            Some(name) => bbm.bones.iter().find(|x| x.name == *name),
+            None => bbm.bones.get(index),
+        })
+        .collect::<Vec<_>>();
+
+    if bones.iter().all(Option::is_none) {
+        return Err(anyhow!("{} animates none of the bones.", name));
+    }
+
+    let tracks = bba
+        .tracks
+        .iter()
+        .zip(bones)
+        .map(|(track, bone)| {
+            json!({
+                "name": track.name.as_ref().map(|x| String::from_utf8_lossy(x)),
+                "bone": bone.map(|x| String::from_utf8_lossy(&x.name)),
+                "masks": track.masks.iter().map(|x| hex(x)).collect::<Vec<_>>(),
+                "sequences": track.sequences.iter().map(|x| hex(x)).collect::<Vec<_>>(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Ok(json!({ "name": name, "tracks": tracks }))
+}
+
+/// A cloth's decoded data, or its bytes in hex when they can't be decoded.
+fn cloth_extras(cloth: &BbmCloth) -> serde_json::Value {
+    let mut value = json!({
+        "id": cloth.id,
+        "material_id": cloth.material_id,
+    });
+
+    match cloth.decode() {
+        Ok(data) => value["cloth"] = json!(data),
+        Err(_) => value["data"] = json!(hex(&cloth.data)),
+    }
+
+    value
+}
+
+/// Reads a cloth written by [`cloth_extras`].
+fn cloth_from_extras(value: &serde_json::Value) -> anyhow::Result<BbmCloth> {
+    let id = serde_json::from_value(value["id"].clone())?;
+    let material_id = serde_json::from_value(value["material_id"].clone())?;
+
+    let data = if let Some(hex) = value["data"].as_str() {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                hex.get(i..i + 2)
+                    .and_then(|x| u8::from_str_radix(x, 16).ok())
+            })
+            .collect::<Option<_>>()
+            .ok_or_else(|| anyhow!("cloth {id} has invalid data."))?
+    } else {
+        serde_json::from_value::<BbmClothData>(value["cloth"].clone())?.compile()
+    };
+
+    Ok(BbmCloth {
+        id,
+        material_id,
+        data,
+    })
+}
+
+/// Reads a field of `extras` written by [`extras`].
+fn extra<T: DeserializeOwned>(extras: &json::Extras, key: &str) -> Option<T> {
+    let extras = serde_json::from_str::<serde_json::Value>(extras.as_ref()?.get()).ok()?;
+    serde_json::from_value(extras.get(key)?.clone()).ok()
+}
+
+fn fable_type(extras: &json::Extras) -> Option<String> {
+    extra(extras, "fable_type")
+}
+
+/// Converts weights to bytes out of 255, giving the rounding error to the largest.
+fn quantize_weights(weights: [f32; 4]) -> [u8; 4] {
+    let sum = weights.iter().map(|x| x.max(0.0)).sum::<f32>();
+
+    if sum <= 0.0 {
+        return [255, 0, 0, 0];
+    }
+
+    let mut bytes = weights.map(|x| (x.max(0.0) / sum * 255.0).round() as i32);
+    let largest = (0..4).max_by_key(|&i| bytes[i]).unwrap_or(0);
+    bytes[largest] += 255 - bytes.iter().sum::<i32>();
+
+    bytes.map(|x| x.clamp(0, 255) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+    use ::gltf::Gltf;
+    use fable_format::{bba::BbaTrack, bbm::BbmVertexLayout};
+
+    /// The 4x3 transform of a translation.
+    fn translation_4x3([x, y, z]: [f32; 3]) -> [f32; 12] {
+        [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, x, y, z]
+    }
+
+    fn bone(name: &str, index: i32, parent: i32, translation: [f32; 3]) -> BbmBone {
+        BbmBone {
+            name: name.as_bytes().to_vec(),
+            index,
+            parent,
+            child_count: 0,
+            transform: translation_4x3(translation),
+            world_transform: translation_4x3(translation),
+            inverse_bind: invert_affine(&matrix_4x3(&translation_4x3(translation))),
+        }
+    }
+
+    /// A model of a root bone and an arm bone one unit above it.
+    fn skeleton() -> Bbm {
+        Bbm {
+            name: b"MODEL".to_vec(),
+            has_skeleton: 1,
+            bones: vec![
+                bone("ROOT", 0, -1, [0.0; 3]),
+                bone("ARM", 1, 0, [0.0, 1.0, 0.0]),
+            ],
+            transform: translation_4x3([0.0; 3]),
+            ..Default::default()
+        }
+    }
+
+    /// The skeleton with a triangle weighted to its bones.
+    fn skinned_model() -> Bbm {
+        let vertex = |position, bone_indices, bone_weights| BbmVertex {
+            position,
+            normal: [0.0, 0.0, 1.0],
+            uv: [position[0], position[1]],
+            bone_indices,
+            bone_weights,
+            ..Default::default()
+        };
+
+        Bbm {
+            materials: vec![BbmMaterial {
+                index: 0,
+                name: b"SKIN".to_vec(),
+                base_texture_id: 7,
+                max_texture_layers: 1,
+                ..Default::default()
+            }],
+            sub_meshes: vec![BbmSubMesh {
+                source_vertex_count: 3,
+                primitives: vec![BbmPrimitive {
+                    material_index: 0,
+                    repeating_mesh_reps: 0,
+                    start_index: 0,
+                    index_count: 3,
+                }],
+                vertices: vec![
+                    vertex([0.0, 0.0, 0.0], [0, 0, 0, 0], [255, 0, 0, 0]),
+                    vertex([1.0, 0.0, 0.0], [1, 0, 0, 0], [255, 0, 0, 0]),
+                    vertex([0.0, 1.0, 0.0], [0, 1, 0, 0], [128, 127, 0, 0]),
+                ],
+                vertex_layout: BbmVertexLayout::FloatSkinned.code(),
+                indices: vec![0, 1, 2],
+                ..Default::default()
+            }],
+            ..skeleton()
+        }
+    }
+
+    /// Writes `glb` to a directory of its own and imports it.
+    fn import(name: &str, glb: &[u8]) -> anyhow::Result<Bbm> {
+        let dir = temp_dir(&format!("gltf_{}", name));
+        let path = dir.join("model.glb");
+        std::fs::write(&path, glb).unwrap();
+
+        let bbm = gltf_to_bbm(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+        bbm
+    }
+
+    #[test]
+    fn export_import_and_compile() {
+        let original = skinned_model();
+
+        let glb = bbm_to_glb(&original, &HashMap::new(), &[]).unwrap();
+        let mut imported = import("round_trip", &glb).unwrap();
+
+        imported.sub_meshes[0].set_layout(BbmVertexLayout::FloatSkinned);
+
+        let compiled = imported.compile().unwrap();
+        let bbm = Bbm::parse(&mut &compiled[..]).unwrap();
+
+        assert_eq!(bbm.name, original.name);
+        assert_eq!(
+            bbm.bones
+                .iter()
+                .map(|x| (&x.name[..], x.parent))
+                .collect::<Vec<_>>(),
+            [(&b"ROOT"[..], -1), (b"ARM", 0)]
+        );
+        assert_eq!(bbm.bones[1].transform, original.bones[1].transform);
+
+        assert_eq!(bbm.materials.len(), 1);
+        assert_eq!(bbm.materials[0].name, b"SKIN");
+        assert_eq!(bbm.materials[0].base_texture_id, 7);
+
+        let (sub_mesh, original) = (&bbm.sub_meshes[0], &original.sub_meshes[0]);
+
+        assert_eq!(sub_mesh.indices, original.indices);
+        assert_eq!(sub_mesh.primitives, original.primitives);
+
+        for (vertex, original) in sub_mesh.vertices.iter().zip(&original.vertices) {
+            assert_eq!(vertex.position, original.position);
+            assert_eq!(vertex.bone_indices, original.bone_indices);
+            assert_eq!(vertex.bone_weights, original.bone_weights);
+            assert!((vertex.normal[2] - 1.0).abs() < 0.01, "{:?}", vertex.normal);
+        }
+    }
+
+    #[test]
+    fn skin_inverse_binds() {
+        let mut bbm = skinned_model();
+
+        // Bound a unit higher than it rests, and without an inverse bind.
+        bbm.bones[1].inverse_bind = invert_affine(&matrix_4x3(&translation_4x3([0.0, 2.0, 0.0])));
+        bbm.bones[0].inverse_bind = [0.0; 16];
+
+        let glb = bbm_to_glb(&bbm, &HashMap::new(), &[]).unwrap();
+        let gltf = Gltf::from_slice(&glb).unwrap();
+
+        let skin = gltf.skins().next().unwrap();
+
+        let joints = skin.joints().map(|x| x.name()).collect::<Vec<_>>();
+        assert_eq!(joints, [Some("ROOT"), Some("ARM")]);
+
+        let inverse_binds = skin
+            .reader(|_| gltf.blob.as_deref())
+            .read_inverse_bind_matrices()
+            .unwrap()
+            .map(bytemuck::cast::<_, [f32; 16]>)
+            .collect::<Vec<_>>();
+
+        assert_eq!(inverse_binds, [IDENTITY, bbm.bones[1].inverse_bind]);
+
+        let arm = skin.joints().nth(1).unwrap();
+        let inverse_bind = extra::<[f32; 16]>(arm.extras(), "inverse_bind").unwrap();
+        assert_eq!(inverse_bind, bbm.bones[1].inverse_bind);
+
+        let imported = import("inverse_binds", &glb).unwrap();
+
+        for (bone, original) in imported.bones.iter().zip(&bbm.bones) {
+            assert_eq!(bone.inverse_bind, original.inverse_bind);
+            assert_eq!(bone.world_transform, original.world_transform);
+        }
+
+        let primitive = gltf.meshes().next().unwrap().primitives().next().unwrap();
+        let reader = primitive.reader(|_| gltf.blob.as_deref());
+
+        let joints = reader
+            .read_joints(0)
+            .unwrap()
+            .into_u16()
+            .collect::<Vec<_>>();
+        assert_eq!(joints, [[0, 0, 0, 0], [1, 0, 0, 0], [0, 1, 0, 0]]);
+
+        let weights = reader
+            .read_weights(0)
+            .unwrap()
+            .into_f32()
+            .collect::<Vec<_>>();
+        assert_eq!(weights[2][0] + weights[2][1], 1.0);
+        assert!((weights[2][0] - 128.0 / 255.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn import_errors() {
+        let mut bbm = skinned_model();
+        bbm.sub_meshes[0].indices = vec![0, 1, 3];
+
+        let glb = bbm_to_glb(&bbm, &HashMap::new(), &[]).unwrap();
+
+        assert_eq!(
+            import("index_range", &glb).unwrap_err().to_string(),
+            "sub-mesh 0 has index 3, but its primitive only has 3 vertices."
+        );
+    }
+
+    #[test]
+    fn vertex_bones_are_checked() {
+        let skin_bones = [Some(0), Some(1), None, Some(256)];
+
+        assert_eq!(
+            vertex_bones(0, &skin_bones, [1, 0, 0, 0]).unwrap(),
+            [1, 0, 0, 0]
+        );
+
+        let error = |joints| {
+            vertex_bones(2, &skin_bones, joints)
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert_eq!(
+            error([4, 0, 0, 0]),
+            "sub-mesh 2 is weighted to joint 4, which its skin doesn't have."
+        );
+        assert_eq!(
+            error([0, 2, 0, 0]),
+            "sub-mesh 2 is weighted to joint 2, which is not a bone of the model."
+        );
+        assert_eq!(
+            error([0, 0, 0, 3]),
+            "sub-mesh 2 is weighted to bone 256, but vertices can only refer to the first 256 bones."
+        );
+    }
+
+    #[test]
+    fn animation() {
+        let bbm = skeleton();
+
+        let arm = BbaTrack {
+            name: Some(b"ARM".to_vec()),
+            masks: vec![vec![1, 2]],
+            sequences: vec![vec![3, 4, 5]],
+            ..Default::default()
+        };
+
+        let unnamed = BbaTrack {
+            sequences: vec![vec![6]],
+            ..Default::default()
+        };
+
+        let missing = BbaTrack {
+            name: Some(b"MISSING".to_vec()),
+            sequences: vec![vec![0xff]],
+            ..Default::default()
+        };
+
+        let bba = Bba {
+            tracks: vec![arm, unnamed, missing],
+            ..Default::default()
+        };
+
+        let glb = bbm_to_glb(&bbm, &HashMap::new(), &[("wave".to_owned(), bba)]).unwrap();
+        let gltf = Gltf::from_slice(&glb).unwrap();
+
+        assert_eq!(gltf.animations().count(), 0);
+
+        let model = gltf
+            .nodes()
+            .find(|x| fable_type(x.extras()).as_deref() == Some("model"))
+            .unwrap();
+
+        let animations = extra::<serde_json::Value>(model.extras(), "animations").unwrap();
+        assert_eq!(
+            animations,
+            json!([{
+                "name": "wave",
+                "tracks": [
+                    { "name": "ARM", "bone": "ARM", "masks": ["0102"], "sequences": ["030405"] },
+                    { "name": null, "bone": "ARM", "masks": [], "sequences": ["06"] },
+                    { "name": "MISSING", "bone": null, "masks": [], "sequences": ["ff"] },
+                ],
+            }])
+        );
+    }
+
+    #[test]
+    fn animation_errors() {
+        let bbm = skeleton();
+
+        let unmatched = Bba {
+            tracks: vec![BbaTrack {
+                name: Some(b"MISSING".to_vec()),
+                sequences: vec![vec![0xff]],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let error = bbm_to_glb(&bbm, &HashMap::new(), &[("idle".to_owned(), unmatched)])
+            .unwrap_err()
+            .to_string();
+        assert_eq!(error, "idle animates none of the bones.");
+    }
+}
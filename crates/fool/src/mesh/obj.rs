@@ -0,0 +1,636 @@
+//! Conversion of compiled `.bbm` meshes to Wavefront OBJ and MTL.
+//!
+//! Only geometry and materials are written; bones, weights and helpers have no place in OBJ. Each
+//! sub-mesh is a group and each primitive switches material. Texture coordinates are flipped
+//! vertically, since OBJ's origin is the bottom left.
+//!
+//! [`obj_to_bbm`] reads such files back, and any other OBJ file of polygons.
+
+use super::{primitive_material, unit_normal};
+use anyhow::anyhow;
+use fable_format::bbm::{Bbm, BbmMaterial, BbmPrimitive, BbmSubMesh, BbmVertex};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    fs,
+    path::Path,
+};
+
+/// Converts a model to an OBJ file and the MTL file it uses, which should be saved as
+/// `mtl_file_name`.
+///
+/// `texture_uris` maps texture ids, as used by materials, to the paths of exported images.
+/// Texture ids are also written to the MTL as comments.
+pub fn bbm_to_obj(
+    bbm: &Bbm,
+    mtl_file_name: &str,
+    texture_uris: &HashMap<u32, String>,
+) -> (String, String) {
+    let material_names = material_names(bbm);
+
+    let mut obj = String::new();
+    let mut mtl = String::new();
+
+    writeln!(obj, "# {}", String::from_utf8_lossy(&bbm.name)).unwrap();
+    writeln!(obj, "mtllib {}", mtl_file_name).unwrap();
+
+    // OBJ indices are 1 based and count across the whole file.
+    let mut first_vertex = 1;
+
+    for (index, sub_mesh) in bbm.sub_meshes.iter().enumerate() {
+        writeln!(obj, "g sub_mesh_{}", index).unwrap();
+
+        for vertex in &sub_mesh.vertices {
+            let [x, y, z] = vertex.position;
+            writeln!(obj, "v {} {} {}", x, y, z).unwrap();
+        }
+
+        for vertex in &sub_mesh.vertices {
+            let [u, v] = vertex.uv;
+            writeln!(obj, "vt {} {}", u, 1.0 - v).unwrap();
+        }
+
+        for vertex in &sub_mesh.vertices {
+            let [x, y, z] = unit_normal(vertex.normal);
+            writeln!(obj, "vn {} {} {}", x, y, z).unwrap();
+        }
+
+        let ranges = if sub_mesh.primitives.is_empty() {
+            vec![(None, 0, sub_mesh.indices.len())]
+        } else {
+            sub_mesh
+                .primitives
+                .iter()
+                .map(|primitive| {
+                    let material = primitive_material(bbm, primitive);
+                    let start = primitive.start_index as usize;
+                    let count = primitive.index_count as usize;
+                    (material, start, count)
+                })
+                .collect()
+        };
+
+        for (material, start, count) in ranges {
+            let Some(indices) = sub_mesh.indices.get(start..start.saturating_add(count)) else {
+                continue;
+            };
+
+            if let Some(material) = material {
+                writeln!(obj, "usemtl {}", material_names[material]).unwrap();
+            }
+
+            for face in indices.chunks_exact(3) {
+                write!(obj, "f").unwrap();
+
+                for &index in face {
+                    let index = first_vertex + index as usize;
+                    write!(obj, " {0}/{0}/{0}", index).unwrap();
+                }
+
+                writeln!(obj).unwrap();
+            }
+        }
+
+        first_vertex += sub_mesh.vertices.len();
+    }
+
+    for (material, name) in bbm.materials.iter().zip(&material_names) {
+        writeln!(mtl, "newmtl {}", name).unwrap();
+        writeln!(mtl, "# base_texture_id {}", material.base_texture_id).unwrap();
+        writeln!(
+            mtl,
+            "# bump_map_texture_id {}",
+            material.bump_map_texture_id
+        )
+        .unwrap();
+        writeln!(
+            mtl,
+            "# reflection_texture_id {}",
+            material.reflection_texture_id
+        )
+        .unwrap();
+        writeln!(mtl, "Kd 1 1 1").unwrap();
+
+        let base = texture_uris.get(&material.base_texture_id);
+
+        if let Some(uri) = base {
+            writeln!(mtl, "map_Kd {}", uri).unwrap();
+        }
+
+        if material.alpha_enabled != 0 {
+            if let Some(uri) = base {
+                writeln!(mtl, "map_d {}", uri).unwrap();
+            }
+        }
+
+        if let Some(uri) = texture_uris.get(&material.bump_map_texture_id) {
+            writeln!(mtl, "map_bump {}", uri).unwrap();
+        }
+
+        writeln!(mtl).unwrap();
+    }
+
+    (obj, mtl)
+}
+
+/// Unique MTL names for the model's materials, without whitespace.
+fn material_names(bbm: &Bbm) -> Vec<String> {
+    let mut seen = HashSet::new();
+
+    bbm.materials
+        .iter()
+        .enumerate()
+        .map(|(index, material)| {
+            let name = String::from_utf8_lossy(&material.name)
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join("_");
+
+            if !name.is_empty() && seen.insert(name.clone()) {
+                name
+            } else {
+                format!("material_{}", index)
+            }
+        })
+        .collect()
+}
+
+/// Reads an `.obj` file, and the `.mtl` file it uses, as a model without bones, leaving the
+/// sub-meshes' vertex layouts unset.
+///
+/// Every group or object becomes a sub-mesh, polygons are split into triangle fans and faces are
+/// gathered by material. Texture ids are read from the comments [`bbm_to_obj`] writes.
+pub fn obj_to_bbm(path: &Path) -> anyhow::Result<Bbm> {
+    let source = fs::read_to_string(path).map_err(|_| anyhow!("failed to read {:?}.", path))?;
+
+    let mut bbm = Bbm {
+        name: path
+            .file_stem()
+            .and_then(|x| x.to_str())
+            .unwrap_or_default()
+            .as_bytes()
+            .to_vec(),
+        transform: [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+        ..Default::default()
+    };
+
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    let mut normals = Vec::new();
+
+    let mut group = ObjGroup::default();
+    let mut material = None;
+
+    for (line_number, line) in source.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        let keyword = words.next();
+
+        let error = || {
+            anyhow!(
+                "{:?} line {}: could not read {:?}.",
+                path,
+                line_number + 1,
+                line
+            )
+        };
+
+        let mut floats = |count: usize| {
+            let floats = words
+                .by_ref()
+                .take(count)
+                .map(str::parse::<f32>)
+                .collect::<Result<Vec<_>, _>>()
+                .ok()
+                .filter(|x| x.len() == count);
+
+            floats.ok_or_else(error)
+        };
+
+        match keyword {
+            Some("v") => {
+                let v = floats(3)?;
+                positions.push([v[0], v[1], v[2]]);
+            }
+            Some("vt") => {
+                let v = floats(2)?;
+                uvs.push([v[0], 1.0 - v[1]]);
+            }
+            Some("vn") => {
+                let v = floats(3)?;
+                normals.push(unit_normal([v[0], v[1], v[2]]));
+            }
+            Some("f") => {
+                let corners = words
+                    .map(|word| obj_corner(word, [positions.len(), uvs.len(), normals.len()]))
+                    .collect::<Option<Vec<_>>>()
+                    .filter(|x| x.len() >= 3)
+                    .ok_or_else(error)?;
+
+                let material = match material {
+                    Some(material) => material,
+                    None => *material.insert(obj_material(&mut bbm, "default")),
+                };
+
+                let corners = corners
+                    .into_iter()
+                    .map(|corner| group.vertex(corner, &positions, &uvs, &normals))
+                    .collect::<Vec<_>>();
+
+                let faces = group.faces(material);
+
+                for i in 1..corners.len() - 1 {
+                    faces.extend([corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            Some("g" | "o") => {
+                if let Some(sub_mesh) = group.finish(bbm.sub_meshes.len())? {
+                    bbm.sub_meshes.push(sub_mesh);
+                }
+            }
+            Some("usemtl") => {
+                let name = words.next().unwrap_or_default();
+                material = Some(obj_material(&mut bbm, name));
+            }
+            Some("mtllib") => {
+                let mtl_name = line.trim_start()["mtllib".len()..].trim();
+                let mtl_path = path.with_file_name(mtl_name);
+
+                if let Ok(mtl) = fs::read_to_string(&mtl_path) {
+                    read_mtl(&mut bbm, &mtl);
+                } else {
+                    eprintln!("could not read materials {:?}.", mtl_path);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(sub_mesh) = group.finish(bbm.sub_meshes.len())? {
+        bbm.sub_meshes.push(sub_mesh);
+    }
+
+    Ok(bbm)
+}
+
+/// The faces and unique vertices of a group read so far.
+#[derive(Default)]
+struct ObjGroup {
+    vertices: Vec<BbmVertex>,
+    /// Vertex index of each position, texture coordinate and normal index triple.
+    indices: HashMap<[Option<usize>; 3], usize>,
+    /// Triangles by material index, in the order the materials are first used.
+    faces: Vec<(u32, Vec<usize>)>,
+}
+
+impl ObjGroup {
+    fn vertex(
+        &mut self,
+        corner: [Option<usize>; 3],
+        positions: &[[f32; 3]],
+        uvs: &[[f32; 2]],
+        normals: &[[f32; 3]],
+    ) -> usize {
+        *self.indices.entry(corner).or_insert_with(|| {
+            let [position, uv, normal] = corner;
+
+            self.vertices.push(BbmVertex {
+                position: position.map_or([0.0; 3], |x| positions[x]),
+                uv: uv.map_or([0.0; 2], |x| uvs[x]),
+                normal: normal.map_or([0.0, 0.0, 1.0], |x| normals[x]),
+                bone_weights: [255, 0, 0, 0],
+                ..Default::default()
+            });
+
+            self.vertices.len() - 1
+        })
+    }
+
+    fn faces(&mut self, material: u32) -> &mut Vec<usize> {
+        let position = match self.faces.iter().position(|(x, _)| *x == material) {
+            Some(position) => position,
+            None => {
+                self.faces.push((material, Vec::new()));
+                self.faces.len() - 1
+            }
+        };
+
+        &mut self.faces[position].1
+    }
+
+    /// Turns the group into a sub-mesh, if it has any faces, and starts the next.
+    fn finish(&mut self, index: usize) -> anyhow::Result<Option<BbmSubMesh>> {
+        let group = std::mem::take(self);
+
+        if group.faces.is_empty() {
+            return Ok(None);
+        }
+
+        if group.vertices.len() > u16::MAX as usize + 1 {
+            return Err(anyhow!(
+                "sub-mesh {} has more than {} vertices.",
+                index,
+                u16::MAX as usize + 1
+            ));
+        }
+
+        let mut sub_mesh = BbmSubMesh {
+            source_vertex_count: group.vertices.len() as u32,
+            vertices: group.vertices,
+            ..Default::default()
+        };
+
+        for (material_index, faces) in group.faces {
+            sub_mesh.primitives.push(BbmPrimitive {
+                material_index,
+                repeating_mesh_reps: 0,
+                start_index: sub_mesh.indices.len() as u32,
+                index_count: faces.len() as u32,
+            });
+
+            sub_mesh.indices.extend(faces.into_iter().map(|x| x as u16));
+        }
+
+        Ok(Some(sub_mesh))
+    }
+}
+
+/// Reads a face corner, `v`, `v/vt`, `v//vn` or `v/vt/vn`, as 0 based indices. `counts` are the
+/// numbers of positions, texture coordinates and normals so far, which negative indices count
+/// back from.
+fn obj_corner(word: &str, counts: [usize; 3]) -> Option<[Option<usize>; 3]> {
+    let mut parts = word.split('/');
+    let mut corner = [None; 3];
+
+    for (i, count) in counts.into_iter().enumerate() {
+        let part = parts.next().unwrap_or_default();
+
+        if part.is_empty() {
+            continue;
+        }
+
+        let index = part.parse::<isize>().ok()?;
+
+        let index = if index < 0 {
+            count.checked_sub(index.unsigned_abs())?
+        } else {
+            (index as usize).checked_sub(1)?
+        };
+
+        corner[i] = Some(index).filter(|&x| x < count);
+        corner[i]?;
+    }
+
+    corner[0]?;
+
+    Some(corner)
+}
+
+/// The index of the material named `name`, added if there's none.
+fn obj_material(bbm: &mut Bbm, name: &str) -> u32 {
+    if let Some(material) = bbm.materials.iter().find(|x| x.name == name.as_bytes()) {
+        return material.index;
+    }
+
+    let index = bbm.materials.len() as u32;
+
+    bbm.materials.push(BbmMaterial {
+        index,
+        name: name.as_bytes().to_vec(),
+        max_texture_layers: 1,
+        ..Default::default()
+    });
+
+    index
+}
+
+/// Adds the materials of an `.mtl` file. Materials with partial transparency are alpha enabled.
+fn read_mtl(bbm: &mut Bbm, source: &str) {
+    let mut current = None;
+
+    for line in source.lines() {
+        let mut words = line.split_whitespace();
+
+        let (Some(keyword), value) = (words.next(), words.next()) else {
+            continue;
+        };
+
+        if keyword == "newmtl" {
+            let index = obj_material(bbm, value.unwrap_or_default());
+            current = bbm.materials.iter().position(|x| x.index == index);
+            continue;
+        }
+
+        let Some(material) = current.map(|x| &mut bbm.materials[x]) else {
+            continue;
+        };
+
+        let mut id = || {
+            words
+                .next()
+                .and_then(|x| x.parse().ok())
+                .unwrap_or_default()
+        };
+
+        match (keyword, value) {
+            ("#", Some("base_texture_id")) => material.base_texture_id = id(),
+            ("#", Some("bump_map_texture_id")) => material.bump_map_texture_id = id(),
+            ("#", Some("reflection_texture_id")) => material.reflection_texture_id = id(),
+            ("d", Some(d)) if d.parse::<f32>().is_ok_and(|x| x < 1.0) => {
+                material.alpha_enabled = 1;
+            }
+            ("Tr", Some(tr)) if tr.parse::<f32>().is_ok_and(|x| x > 0.0) => {
+                material.alpha_enabled = 1;
+            }
+            ("map_d", _) => {
+                material.alpha_enabled = 1;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+    use fable_format::bbm::BbmVertexLayout;
+    use std::path::PathBuf;
+
+    fn material(index: u32, name: &str, base_texture_id: u32) -> BbmMaterial {
+        BbmMaterial {
+            index,
+            name: name.as_bytes().to_vec(),
+            base_texture_id,
+            max_texture_layers: 1,
+            ..Default::default()
+        }
+    }
+
+    /// A unit square of two triangles, each with a material of its own.
+    fn square() -> Bbm {
+        let vertex = |position: [f32; 3]| BbmVertex {
+            position,
+            normal: [0.0, 0.0, 1.0],
+            uv: [position[0], position[1]],
+            bone_weights: [255, 0, 0, 0],
+            ..Default::default()
+        };
+
+        let primitive = |material_index, start_index| BbmPrimitive {
+            material_index,
+            repeating_mesh_reps: 0,
+            start_index,
+            index_count: 3,
+        };
+
+        Bbm {
+            name: b"SQUARE".to_vec(),
+            materials: vec![material(0, "STONE", 5), material(1, "WOOD", 6)],
+            sub_meshes: vec![BbmSubMesh {
+                source_vertex_count: 4,
+                primitives: vec![primitive(0, 0), primitive(1, 3)],
+                vertices: vec![
+                    vertex([0.0, 0.0, 0.0]),
+                    vertex([1.0, 0.0, 0.0]),
+                    vertex([1.0, 1.0, 0.0]),
+                    vertex([0.0, 1.0, 0.0]),
+                ],
+                indices: vec![0, 1, 2, 0, 2, 3],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn export_obj() {
+        let mut bbm = square();
+
+        // A second sub-mesh of one triangle, whose indices continue from the first's.
+        let mut triangle = bbm.sub_meshes[0].clone();
+        triangle.vertices.truncate(3);
+        triangle.indices.truncate(3);
+        triangle.primitives.truncate(1);
+        bbm.sub_meshes.push(triangle);
+
+        let (obj, _) = bbm_to_obj(&bbm, "square.mtl", &HashMap::new());
+        let lines = obj.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines[..2], ["# SQUARE", "mtllib square.mtl"]);
+        assert_eq!(lines.iter().filter(|x| x.starts_with("v ")).count(), 7);
+
+        let uvs = lines
+            .iter()
+            .filter(|x| x.starts_with("vt "))
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(uvs[..3], ["vt 0 1", "vt 1 1", "vt 1 0"]);
+
+        let groups = lines
+            .iter()
+            .filter(|x| x.starts_with("g ") || x.starts_with("usemtl ") || x.starts_with("f "))
+            .copied()
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            groups,
+            [
+                "g sub_mesh_0",
+                "usemtl STONE",
+                "f 1/1/1 2/2/2 3/3/3",
+                "usemtl WOOD",
+                "f 1/1/1 3/3/3 4/4/4",
+                "g sub_mesh_1",
+                "usemtl STONE",
+                "f 5/5/5 6/6/6 7/7/7",
+            ]
+        );
+    }
+
+    #[test]
+    fn export_mtl() {
+        let mut bbm = square();
+
+        bbm.materials[0].alpha_enabled = 1;
+        bbm.materials[0].bump_map_texture_id = 8;
+        bbm.materials[1].name = b"OLD WOOD".to_vec();
+        bbm.materials.push(material(2, "OLD_WOOD", 0));
+
+        let texture_uris = HashMap::from([
+            (5, "textures/stone.png".to_owned()),
+            (8, "textures/stone_bump.png".to_owned()),
+        ]);
+
+        let (_, mtl) = bbm_to_obj(&bbm, "square.mtl", &texture_uris);
+
+        let materials = mtl
+            .split("newmtl ")
+            .skip(1)
+            .map(|x| x.lines().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            materials[0],
+            [
+                "STONE",
+                "# base_texture_id 5",
+                "# bump_map_texture_id 8",
+                "# reflection_texture_id 0",
+                "Kd 1 1 1",
+                "map_Kd textures/stone.png",
+                "map_d textures/stone.png",
+                "map_bump textures/stone_bump.png",
+                "",
+            ]
+        );
+
+        // Whitespace is replaced and names that are taken fall back to the material's position.
+        assert_eq!(materials[1][..2], ["OLD_WOOD", "# base_texture_id 6"]);
+        assert_eq!(materials[2][0], "material_2");
+        assert!(!materials[1].iter().any(|x| x.starts_with("map_")));
+    }
+
+    #[test]
+    fn export_import_and_compile() {
+        let dir = temp_dir("obj_round_trip");
+        let original = square();
+
+        let (obj, mtl) = bbm_to_obj(&original, "square.mtl", &HashMap::new());
+        fs::write(dir.join("square.obj"), obj).unwrap();
+        fs::write(dir.join("square.mtl"), mtl).unwrap();
+
+        let mut imported = obj_to_bbm(&dir.join("square.obj")).unwrap();
+
+        imported.sub_meshes[0].set_layout(BbmVertexLayout::Float);
+
+        let compiled = imported.compile().unwrap();
+        let bbm = Bbm::parse(&mut &compiled[..]).unwrap();
+
+        assert_eq!(bbm.name, b"square");
+
+        let materials = bbm
+            .materials
+            .iter()
+            .map(|x| (x.index, &x.name[..], x.base_texture_id))
+            .collect::<Vec<_>>();
+
+        assert_eq!(materials, [(0, &b"STONE"[..], 5), (1, b"WOOD", 6)]);
+
+        let (sub_mesh, original) = (&bbm.sub_meshes[0], &original.sub_meshes[0]);
+
+        assert_eq!(sub_mesh.indices, original.indices);
+        assert_eq!(sub_mesh.primitives, original.primitives);
+
+        for (vertex, original) in sub_mesh.vertices.iter().zip(&original.vertices) {
+            assert_eq!(vertex.position, original.position);
+
+            for (a, b) in vertex.uv.into_iter().zip(original.uv) {
+                assert!((a - b).abs() < 1e-3, "{:?} {:?}", vertex.uv, original.uv);
+            }
+
+            assert!((vertex.normal[2] - 1.0).abs() < 0.01, "{:?}", vertex.normal);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
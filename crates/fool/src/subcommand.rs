@@ -1,7 +1,11 @@
+pub mod bba;
+pub mod big;
 pub mod lev;
 pub mod tng;
 pub mod wad;
 
+pub use bba::*;
+pub use big::*;
 pub use lev::*;
 pub use tng::*;
 pub use wad::*;
@@ -0,0 +1,76 @@
+use crate::mesh::gltf::bbm_to_glb;
+use anyhow::anyhow;
+use clap::{Args, Subcommand};
+use fable_format::{bba::Bba, bbm::Bbm};
+use std::{collections::HashMap, fs};
+use typed_path::Utf8PathBuf;
+
+#[derive(Args, Debug, Clone)]
+pub struct BbaArgs {
+    #[command(subcommand)]
+    command: Option<BbaCommand>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum BbaCommand {
+    #[command(about = "Export a mesh's skeleton as glTF, keeping the tracks of .bba files in it.")]
+    Export {
+        /// A mesh entry extracted with `big extract`, whose bones are animated
+        mesh: String,
+
+        #[arg(required = true)]
+        animations: Vec<String>,
+
+        /// The .glb file to write. Defaults to the mesh's path with a .glb extension
+        #[arg(long, short)]
+        output: Option<String>,
+    },
+}
+
+pub fn handle(args: BbaArgs) -> anyhow::Result<()> {
+    match args.command {
+        None => Ok(()),
+        Some(BbaCommand::Export {
+            mesh,
+            animations,
+            output,
+        }) => export(mesh, animations, output),
+    }
+}
+
+fn export(mesh: String, animations: Vec<String>, output: Option<String>) -> anyhow::Result<()> {
+    let mesh_path = Utf8PathBuf::from(mesh);
+    let bytes = fs::read(&mesh_path).map_err(|_| anyhow!("file not found."))?;
+
+    let bbm = Bbm::parse(&mut &bytes[..]).map_err(|e| anyhow!("could not decode mesh. {:?}", e))?;
+
+    if bbm.bones.is_empty() {
+        return Err(anyhow!("the mesh has no bones."));
+    }
+
+    let animations = animations
+        .into_iter()
+        .map(|path| {
+            let path = Utf8PathBuf::from(path);
+            let bytes = fs::read(&path).map_err(|_| anyhow!("{} not found.", path))?;
+
+            let bba = Bba::parse(&mut &bytes[..])
+                .map_err(|e| anyhow!("could not decode {}. {:?}", path, e))?;
+
+            let name = path.file_stem().unwrap_or(path.as_str()).to_owned();
+
+            Ok((name, bba))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let glb = bbm_to_glb(&bbm, &HashMap::new(), &animations)?;
+
+    let output_path = match output {
+        Some(output) => Utf8PathBuf::from(output),
+        None => mesh_path.with_extension("glb"),
+    };
+
+    fs::write(&output_path, glb).map_err(|_| anyhow!("failed to write mesh."))?;
+
+    Ok(())
+}
@@ -0,0 +1,1326 @@
+use crate::mesh::{
+    gltf::{bbm_to_glb, gltf_to_bbm},
+    obj::{bbm_to_obj, obj_to_bbm},
+};
+use anyhow::anyhow;
+use clap::{Args, Subcommand};
+use fable_format::{
+    bbm::{Bbm, BbmVertexLayout},
+    big::{
+        BigBankIndexOwned, BigBankMetadata, BigFileEntryOwned, BigFileIndexOwned, BigHeader,
+        BigSubHeader, BigSubHeaderMesh, BigSubHeaderTexture, BigWriter,
+    },
+    dds::{dds_to_texture, texture_to_dds},
+    texture::{decode_mip_levels, encode_texture, TextureFormat, TextureImage},
+};
+use fable_io::{
+    big_file::{BigFile, BigFileError},
+    check::check_big,
+    mmap::{BigArchive, BigArchiveError, MmapFile},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+};
+use typed_path::Utf8PathBuf;
+
+#[derive(Args, Debug, Clone)]
+pub struct BigArgs {
+    #[command(subcommand)]
+    command: Option<BigCommand>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum BigCommand {
+    #[command(about = "Extract a .big file into a directory with one folder per bank.")]
+    Extract {
+        file: String,
+
+        #[arg(long, short)]
+        output: Option<String>,
+    },
+
+    #[command(about = "Pack a directory extracted with `big extract` into a .big file.")]
+    Pack {
+        directory: String,
+
+        #[arg(long, short)]
+        output: Option<String>,
+    },
+
+    #[command(about = "Decode the textures of a .big file and save them as PNG images.")]
+    ExportTextures {
+        file: String,
+
+        #[arg(long, short)]
+        output: Option<String>,
+
+        /// Also save every mip level, not only the largest
+        #[arg(long, short)]
+        mip_maps: bool,
+
+        /// Save DDS files holding the original data instead of decoded PNG images
+        #[arg(long)]
+        dds: bool,
+    },
+
+    #[command(
+        about = "Decode the meshes of a .big file and save them as glTF binaries or OBJ files."
+    )]
+    ExportMeshes {
+        file: String,
+
+        #[arg(long, short)]
+        output: Option<String>,
+
+        /// A textures .big file to export the meshes' textures from, as PNG images
+        #[arg(long, short)]
+        textures: Option<String>,
+
+        /// Also save every level of detail, not only the largest
+        #[arg(long, short)]
+        lods: bool,
+
+        /// Save Wavefront .obj and .mtl files instead of .glb, without skeletons
+        #[arg(long)]
+        obj: bool,
+    },
+
+    #[command(about = "Replace a texture in a directory extracted with `big extract`.")]
+    ImportTexture {
+        directory: String,
+
+        /// Symbol name of the texture entry, e.g. `TEX_HERO_SKIN`
+        symbol_name: String,
+
+        /// A .dds file, copied as is, or a .png image, which is encoded
+        image: String,
+
+        /// Format to encode a .png image as: dxt1, dxt3, dxt5, bgra8888, bgra4444 or bgr565.
+        /// Defaults to the format of the replaced texture
+        #[arg(long, short)]
+        format: Option<String>,
+
+        /// Number of mip levels to generate for a .png image. Defaults to the replaced texture's
+        #[arg(long, short)]
+        mip_maps: Option<u8>,
+    },
+
+    #[command(about = "Replace a mesh in a directory extracted with `big extract`.")]
+    ImportMesh {
+        directory: String,
+
+        /// Symbol name of the mesh entry, e.g. `MESH_OBJECT_BARREL`
+        symbol_name: String,
+
+        /// A .glb, .gltf or .obj file
+        model: String,
+
+        /// Vertex layout to compile: float, float-extra, packed, packed-extra or full. Defaults
+        /// to float. Layouts with bones are used for models with a skin
+        #[arg(long, short)]
+        layout: Option<String>,
+    },
+
+    #[command(about = "List the entries of a .big file.")]
+    List { file: String },
+
+    #[command(about = "Check a .big file for corrupt headers, indexes or data ranges.")]
+    Check { file: String },
+
+    #[command(about = "Print information about a .big file as JSON.")]
+    Inspect {
+        file: String,
+
+        /// Compress the JSON
+        #[arg(long, short)]
+        compress: bool,
+    },
+}
+
+pub fn handle(args: BigArgs) -> anyhow::Result<()> {
+    match args.command {
+        None => Ok(()),
+        Some(BigCommand::Extract { file, output }) => extract(file, output),
+        Some(BigCommand::Pack { directory, output }) => pack(directory, output),
+        Some(BigCommand::ExportTextures {
+            file,
+            output,
+            mip_maps,
+            dds,
+        }) => export_textures(file, output, mip_maps, dds),
+        Some(BigCommand::ExportMeshes {
+            file,
+            output,
+            textures,
+            lods,
+            obj,
+        }) => export_meshes(file, output, textures, lods, obj),
+        Some(BigCommand::ImportTexture {
+            directory,
+            symbol_name,
+            image,
+            format,
+            mip_maps,
+        }) => import_texture(directory, symbol_name, image, format, mip_maps),
+        Some(BigCommand::ImportMesh {
+            directory,
+            symbol_name,
+            model,
+            layout,
+        }) => import_mesh(directory, symbol_name, model, layout),
+        Some(BigCommand::List { file }) => list(file),
+        Some(BigCommand::Check { file }) => check(file),
+        Some(BigCommand::Inspect { file, compress }) => inspect(file, compress),
+    }
+}
+
+/// Name of the file `extract` writes next to the banks so that `pack` can restore the archive.
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+fn extract(file_path: String, output_path: Option<String>) -> anyhow::Result<()> {
+    let file_path = Utf8PathBuf::from(file_path);
+    // Safety: the archive is expected not to change while it's read.
+    let file = unsafe { MmapFile::open(&file_path) }.map_err(|_| anyhow!("file not found."))?;
+    let big = file.big().map_err(big_archive_error)?;
+
+    let output_path = output_path
+        .map(Utf8PathBuf::from)
+        .or_else(|| {
+            let file_stem = file_path.file_stem()?;
+
+            file_path
+                .parent()
+                .map(|x| x.to_path_buf())
+                .map(|x| x.join(file_stem))
+        })
+        .ok_or_else(|| anyhow!("could not determine output path."))?;
+
+    let output_path = PathBuf::from(output_path.as_str());
+
+    for bank in &big.banks {
+        let bank_path = output_path.join(bank_dir_name(bank.bank.name, bank.bank.bank_id));
+
+        fs::create_dir_all(&bank_path)
+            .map_err(|_| anyhow!("failed to establish bank directory."))?;
+
+        let entries = &bank.index.entries;
+        let names = entries.iter().map(|entry| (entry.symbol_name, entry.id));
+
+        for (entry, file_name) in entries.iter().zip(entry_file_names(names)) {
+            let data = big
+                .entry_data(entry)
+                .ok_or_else(|| anyhow!("entry {} lies past the end of the file.", entry.id))?;
+
+            fs::write(bank_path.join(file_name), data)
+                .map_err(|_| anyhow!("failed to write entry."))?;
+        }
+    }
+
+    let manifest = big_json(&big);
+
+    let manifest_str =
+        serde_json::to_string_pretty(&manifest).map_err(|_| anyhow!("failed to serialize JSON"))?;
+
+    fs::write(output_path.join(MANIFEST_FILE_NAME), manifest_str)
+        .map_err(|_| anyhow!("failed to write manifest."))?;
+
+    Ok(())
+}
+
+/// The header and indexes of an archive, as written to the manifest by `extract`.
+fn big_json(big: &BigArchive) -> serde_json::Value {
+    let banks = big
+        .banks
+        .iter()
+        .map(|bank| serde_json::json!({ "bank": bank.bank.to_owned(), "index": bank.index.to_owned() }))
+        .collect::<Vec<_>>();
+
+    serde_json::json!({ "header": big.header, "banks": banks })
+}
+
+fn open_big<P: AsRef<Path>>(file_path: P) -> anyhow::Result<BigFile> {
+    BigFile::open(file_path).map_err(big_file_error)
+}
+
+fn big_file_error(error: BigFileError) -> anyhow::Error {
+    match error {
+        BigFileError::Open(_) => anyhow!("file not found."),
+        BigFileError::ReadHeader(_) => anyhow!("could not read file."),
+        BigFileError::Header(_) => anyhow!("could not parse header."),
+        BigFileError::ReadBanks(_) => anyhow!("failed to read banks."),
+        BigFileError::Banks(e) => anyhow!("failed to parse banks. {:?}", e),
+        BigFileError::IndexPastEnd { bank, end } => {
+            anyhow!(
+                "index of bank {} ends at {}, past the end of the file.",
+                bank,
+                end
+            )
+        }
+        BigFileError::ReadIndex { bank, .. } => anyhow!("failed to read index of bank {}.", bank),
+        BigFileError::Index { bank, error } => {
+            anyhow!("failed to parse index of bank {}. {:?}", bank, error)
+        }
+    }
+}
+
+fn big_archive_error(error: BigArchiveError) -> anyhow::Error {
+    match error {
+        BigArchiveError::Header(_) => anyhow!("could not parse header."),
+        BigArchiveError::BankAddress(_) => anyhow!("bank address lies past the end of the file."),
+        BigArchiveError::Banks(e) => anyhow!("failed to parse banks. {:?}", e),
+        BigArchiveError::IndexRange { bank } => {
+            anyhow!("index of bank {} lies past the end of the file.", bank)
+        }
+        BigArchiveError::Index { bank, error } => {
+            anyhow!("failed to parse index of bank {}. {:?}", bank, error)
+        }
+    }
+}
+
+/// Returns `name` if it can be used as a file name on any platform.
+fn file_name(name: &[u8]) -> Option<&str> {
+    let name = std::str::from_utf8(name).ok()?;
+
+    let is_reserved = |c: char| c.is_control() || r#"/\:*?"<>|"#.contains(c);
+
+    if name.is_empty() || name == "." || name == ".." || name.contains(is_reserved) {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+fn bank_dir_name(name: &[u8], bank_id: u32) -> String {
+    match file_name(name) {
+        Some(name) => name.to_owned(),
+        None => bank_id.to_string(),
+    }
+}
+
+/// Names the file of each entry of a bank after its symbol name, given the symbol name and id of
+/// each entry.
+///
+/// Entries whose symbol name can't be a file name are named by their id instead, and repeated
+/// names get the id appended, so `pack` can always find the file again.
+fn entry_file_names<'a>(entries: impl IntoIterator<Item = (&'a [u8], u32)>) -> Vec<String> {
+    let mut seen = HashSet::new();
+
+    entries
+        .into_iter()
+        .map(|(symbol_name, id)| {
+            let name = match file_name(symbol_name) {
+                Some(name) => name.to_owned(),
+                None => id.to_string(),
+            };
+
+            if seen.insert(name.to_ascii_lowercase()) {
+                name
+            } else {
+                format!("{}.{}", name, id)
+            }
+        })
+        .collect()
+}
+
+/// The symbol names and ids of owned entries, for [`entry_file_names`].
+fn owned_entry_names(entries: &[BigFileEntryOwned]) -> impl Iterator<Item = (&[u8], u32)> {
+    entries
+        .iter()
+        .map(|entry| (entry.symbol_name.as_slice(), entry.id))
+}
+
+struct PackBank {
+    bank: BigBankIndexOwned,
+    index: BigFileIndexOwned,
+    sources: Vec<PathBuf>,
+}
+
+/// Rebuilds an archive from a directory and manifest written by `extract`.
+///
+/// When no entry changed size, no index changed size and everything still fits where it was, the
+/// original layout is kept so the output is identical to the extracted archive. Otherwise entries
+/// and indexes are laid out again in manifest order.
+///
+/// An entry replaced with `import-texture` or `import-mesh` has its new size in the manifest, so
+/// it's the fit check that lays the archive out again when it grew.
+fn pack(directory: String, output_path: Option<String>) -> anyhow::Result<()> {
+    let directory = PathBuf::from(directory);
+
+    let output_path = output_path
+        .map(PathBuf::from)
+        .or_else(|| {
+            let file_name = directory.file_name()?.to_str()?;
+            Some(directory.with_file_name(format!("{}.big", file_name)))
+        })
+        .ok_or_else(|| anyhow!("could not determine output path."))?;
+
+    let manifest_source = fs::read_to_string(directory.join(MANIFEST_FILE_NAME))
+        .map_err(|_| anyhow!("failed to read manifest."))?;
+
+    let mut manifest: serde_json::Value = serde_json::from_str(&manifest_source)
+        .map_err(|e| anyhow!("failed to parse manifest. {}", e))?;
+
+    let header: BigHeader = serde_json::from_value(manifest["header"].take())
+        .map_err(|e| anyhow!("failed to parse manifest header. {}", e))?;
+
+    let manifest_banks: Vec<serde_json::Value> =
+        serde_json::from_value(manifest["banks"].take())
+            .map_err(|e| anyhow!("failed to parse manifest banks. {}", e))?;
+
+    let mut banks = Vec::with_capacity(manifest_banks.len());
+    let mut layout_changed = false;
+    let mut regions = vec![(0, BigHeader::byte_size() as u64)];
+
+    for mut manifest_bank in manifest_banks {
+        let bank: BigBankIndexOwned = serde_json::from_value(manifest_bank["bank"].take())
+            .map_err(|e| anyhow!("failed to parse manifest bank. {}", e))?;
+
+        let index: BigFileIndexOwned = serde_json::from_value(manifest_bank["index"].take())
+            .map_err(|e| anyhow!("failed to parse manifest index. {}", e))?;
+
+        let index_size = index.as_file_index().byte_size();
+
+        if index_size != bank.index_size as usize {
+            layout_changed = true;
+        }
+
+        regions.push((bank.index_start as u64, index_size as u64));
+
+        let bank_path = directory.join(bank_dir_name(&bank.name, bank.bank_id));
+
+        let mut sources = Vec::with_capacity(index.entries.len());
+
+        for (entry, file_name) in index
+            .entries
+            .iter()
+            .zip(entry_file_names(owned_entry_names(&index.entries)))
+        {
+            let source = bank_path.join(file_name);
+
+            let size = fs::metadata(&source)
+                .map_err(|_| anyhow!("failed to read {:?}.", source))?
+                .len();
+
+            if entry.size as u64 != size {
+                layout_changed = true;
+            }
+
+            regions.push((entry.start as u64, size));
+            sources.push(source);
+        }
+
+        banks.push(PackBank {
+            bank,
+            index,
+            sources,
+        });
+    }
+
+    if !regions_fit(&mut regions) {
+        layout_changed = true;
+    }
+
+    let file = File::create(&output_path).map_err(|_| anyhow!("failed to create output file."))?;
+
+    let mut writer = BigWriter::with_header(BufWriter::new(file), header);
+
+    for PackBank {
+        bank,
+        index,
+        sources,
+    } in &banks
+    {
+        writer.add_bank(BigBankMetadata {
+            name: bank.name.clone(),
+            bank_id: bank.bank_id,
+            block_size: bank.block_size,
+            file_type: index.file_type,
+            types_map: index.types_map.clone(),
+            index_start: (!layout_changed).then_some(bank.index_start),
+        });
+
+        for (entry, source) in index.entries.iter().zip(sources) {
+            let mut entry_file =
+                File::open(source).map_err(|_| anyhow!("failed to open {:?}.", source))?;
+
+            writer
+                .add_entry(
+                    &mut entry_file,
+                    entry,
+                    (!layout_changed).then_some(entry.start),
+                )
+                .map_err(|e| anyhow!("failed to write entry {:?}. {:?}", source, e))?;
+        }
+    }
+
+    writer
+        .finish()
+        .map_err(|e| anyhow!("failed to write big file. {:?}", e))?;
+
+    Ok(())
+}
+
+/// Whether no two of the `(start, size)` regions overlap. Regions repeated exactly, as when entries
+/// share their data, count once.
+fn regions_fit(regions: &mut Vec<(u64, u64)>) -> bool {
+    regions.retain(|&(_, size)| size > 0);
+    regions.sort_unstable();
+    regions.dedup();
+
+    let mut end = 0;
+
+    regions.iter().all(|&(start, size)| {
+        let fits = start >= end;
+        end = end.max(start + size);
+        fits
+    })
+}
+
+/// Writes each texture as `<bank>/<symbol name>.png`. Animated textures get one image per frame
+/// and, with `mip_maps`, every mip level is written as well. With `dds`, each texture is written
+/// whole as `<bank>/<symbol name>.dds` instead.
+fn export_textures(
+    file_path: String,
+    output_path: Option<String>,
+    mip_maps: bool,
+    dds: bool,
+) -> anyhow::Result<()> {
+    let file_path = Utf8PathBuf::from(file_path);
+    let big = open_big(&file_path)?;
+
+    let output_path = output_path
+        .map(Utf8PathBuf::from)
+        .or_else(|| {
+            let file_stem = file_path.file_stem()?;
+
+            file_path
+                .parent()
+                .map(|x| x.to_path_buf())
+                .map(|x| x.join(format!("{}_textures", file_stem)))
+        })
+        .ok_or_else(|| anyhow!("could not determine output path."))?;
+
+    let output_path = PathBuf::from(output_path.as_str());
+
+    let mut failures = 0;
+
+    for bank in big.banks() {
+        let bank_path = output_path.join(bank_dir_name(&bank.bank.name, bank.bank.bank_id));
+        let entries = &bank.index.entries;
+
+        for (entry, file_name) in entries
+            .iter()
+            .zip(entry_file_names(owned_entry_names(entries)))
+        {
+            let BigSubHeader::Texture(header) = &entry.sub_header else {
+                continue;
+            };
+
+            let payload = big
+                .read_entry(entry)
+                .map_err(|_| anyhow!("failed to read entry {}.", file_name))?;
+
+            if dds {
+                let dds_bytes = match texture_to_dds(header, &payload) {
+                    Ok(dds_bytes) => dds_bytes,
+                    Err(e) => {
+                        eprintln!("{}: could not convert texture. {:?}", file_name, e);
+                        failures += 1;
+                        continue;
+                    }
+                };
+
+                fs::create_dir_all(&bank_path)
+                    .map_err(|_| anyhow!("failed to establish bank directory."))?;
+
+                fs::write(bank_path.join(file_name + ".dds"), dds_bytes)
+                    .map_err(|_| anyhow!("failed to write texture."))?;
+
+                continue;
+            }
+
+            let levels = match decode_mip_levels(header, &payload) {
+                Ok(levels) => levels,
+                Err(e) => {
+                    eprintln!("{}: could not decode texture. {:?}", file_name, e);
+                    failures += 1;
+                    continue;
+                }
+            };
+
+            fs::create_dir_all(&bank_path)
+                .map_err(|_| anyhow!("failed to establish bank directory."))?;
+
+            let level_count = if mip_maps { levels.len() } else { 1 };
+
+            for (level, image) in levels.iter().take(level_count).enumerate() {
+                let frames = image.frames(
+                    (header.frame_width as u32 >> level).max(1),
+                    (header.frame_height as u32 >> level).max(1),
+                    header.frame_count as u32,
+                );
+
+                for (frame, image) in frames.iter().enumerate() {
+                    let mut image_name = file_name.clone();
+
+                    if frames.len() > 1 {
+                        image_name += &format!("_frame{}", frame);
+                    }
+
+                    if level > 0 {
+                        image_name += &format!("_mip{}", level);
+                    }
+
+                    write_png(&bank_path.join(image_name + ".png"), image)?;
+                }
+            }
+        }
+    }
+
+    if failures == 0 {
+        Ok(())
+    } else {
+        Err(anyhow!("failed to decode {} textures.", failures))
+    }
+}
+
+/// Name of the directory `export-meshes` writes the meshes' textures to, next to the banks.
+const MESH_TEXTURES_DIR_NAME: &str = "textures";
+
+fn export_meshes(
+    file_path: String,
+    output_path: Option<String>,
+    textures_path: Option<String>,
+    lods: bool,
+    obj: bool,
+) -> anyhow::Result<()> {
+    let file_path = Utf8PathBuf::from(file_path);
+    let big = open_big(&file_path)?;
+
+    let textures = textures_path.map(open_big).transpose()?;
+
+    let output_path = output_path
+        .map(Utf8PathBuf::from)
+        .or_else(|| {
+            let file_stem = file_path.file_stem()?;
+
+            file_path
+                .parent()
+                .map(|x| x.to_path_buf())
+                .map(|x| x.join(format!("{}_meshes", file_stem)))
+        })
+        .ok_or_else(|| anyhow!("could not determine output path."))?;
+
+    let output_path = PathBuf::from(output_path.as_str());
+
+    let mut texture_uris = HashMap::new();
+    let mut exported_textures = HashSet::new();
+    let mut failures = 0;
+
+    for bank in big.banks() {
+        let bank_path = output_path.join(bank_dir_name(&bank.bank.name, bank.bank.bank_id));
+        let entries = &bank.index.entries;
+
+        for (entry, file_name) in entries
+            .iter()
+            .zip(entry_file_names(owned_entry_names(entries)))
+        {
+            let BigSubHeader::Mesh(header) = &entry.sub_header else {
+                continue;
+            };
+
+            let payload = big
+                .read_entry(entry)
+                .map_err(|_| anyhow!("failed to read entry {}.", file_name))?;
+
+            let Some(lod_payloads) = Bbm::lods(header, &payload) else {
+                eprintln!("{}: levels of detail run past the entry.", file_name);
+                failures += 1;
+                continue;
+            };
+
+            let lod_count = if lods { lod_payloads.len() } else { 1 };
+
+            for (lod, mut lod_payload) in lod_payloads.into_iter().take(lod_count).enumerate() {
+                let bbm = match Bbm::parse(&mut lod_payload) {
+                    Ok(bbm) => bbm,
+                    Err(e) => {
+                        eprintln!("{}: could not decode mesh. {:?}", file_name, e);
+                        failures += 1;
+                        continue;
+                    }
+                };
+
+                if let Some(textures) = &textures {
+                    let ids = bbm
+                        .materials
+                        .iter()
+                        .flat_map(|x| [x.base_texture_id, x.bump_map_texture_id]);
+
+                    for id in ids {
+                        if id == 0 || !exported_textures.insert(id) {
+                            continue;
+                        }
+
+                        match export_mesh_texture(textures, id, &output_path)? {
+                            Some(uri) => {
+                                texture_uris.insert(id, uri);
+                            }
+                            None => {
+                                eprintln!("{}: could not export texture {}.", file_name, id);
+                            }
+                        }
+                    }
+                }
+
+                let mut mesh_name = file_name.clone();
+
+                if lod > 0 {
+                    mesh_name += &format!("_lod{}", lod);
+                }
+
+                fs::create_dir_all(&bank_path)
+                    .map_err(|_| anyhow!("failed to establish bank directory."))?;
+
+                if obj {
+                    let mtl_name = format!("{}.mtl", mesh_name);
+                    let (obj, mtl) = bbm_to_obj(&bbm, &mtl_name, &texture_uris);
+
+                    fs::write(bank_path.join(format!("{}.obj", mesh_name)), obj)
+                        .map_err(|_| anyhow!("failed to write mesh."))?;
+
+                    fs::write(bank_path.join(mtl_name), mtl)
+                        .map_err(|_| anyhow!("failed to write materials."))?;
+                } else {
+                    let glb = bbm_to_glb(&bbm, &texture_uris, &[])?;
+
+                    fs::write(bank_path.join(mesh_name + ".glb"), glb)
+                        .map_err(|_| anyhow!("failed to write mesh."))?;
+                }
+            }
+        }
+    }
+
+    if failures == 0 {
+        Ok(())
+    } else {
+        Err(anyhow!("failed to decode {} meshes.", failures))
+    }
+}
+
+/// Saves the top mip level of a mesh's texture as a PNG image in the textures directory and
+/// returns its URI relative to the bank directories, or `None` if it can't be found or decoded.
+fn export_mesh_texture(
+    textures: &BigFile,
+    id: u32,
+    output_path: &Path,
+) -> anyhow::Result<Option<String>> {
+    let Some(entry) = textures.entry_by_id(id) else {
+        return Ok(None);
+    };
+
+    let BigSubHeader::Texture(header) = &entry.sub_header else {
+        return Ok(None);
+    };
+
+    let payload = textures
+        .read_entry(entry)
+        .map_err(|_| anyhow!("failed to read texture {}.", id))?;
+
+    let Some(image) = decode_mip_levels(header, &payload)
+        .ok()
+        .and_then(|levels| levels.into_iter().next())
+    else {
+        return Ok(None);
+    };
+
+    let image = image
+        .frames(
+            header.frame_width as u32,
+            header.frame_height as u32,
+            header.frame_count as u32,
+        )
+        .into_iter()
+        .next()
+        .unwrap_or(image);
+
+    let image_name = match file_name(&entry.symbol_name) {
+        Some(name) => format!("{}.png", name),
+        None => format!("{}.png", id),
+    };
+
+    let textures_path = output_path.join(MESH_TEXTURES_DIR_NAME);
+
+    fs::create_dir_all(&textures_path)
+        .map_err(|_| anyhow!("failed to establish textures directory."))?;
+
+    write_png(&textures_path.join(&image_name), &image)?;
+
+    Ok(Some(format!(
+        "../{}/{}",
+        MESH_TEXTURES_DIR_NAME, image_name
+    )))
+}
+
+/// Replaces a texture's payload file and sub-header in an extracted directory, ready for `pack`.
+///
+/// The frame layout is kept when the size is unchanged, and the sub-header fields whose meaning is
+/// unknown are always kept.
+fn import_texture(
+    directory: String,
+    symbol_name: String,
+    image_path: String,
+    format: Option<String>,
+    mip_maps: Option<u8>,
+) -> anyhow::Result<()> {
+    replace_entry(&directory, &symbol_name, |entry| {
+        let BigSubHeader::Texture(original) = &entry.sub_header else {
+            return Err(anyhow!("{} is not a texture.", symbol_name));
+        };
+
+        let (texture, payload) = read_texture(&image_path, original, format, mip_maps)?;
+
+        let size_unchanged = texture.width == original.width && texture.height == original.height;
+
+        let texture = BigSubHeaderTexture {
+            depth: original.depth,
+            frame_width: if size_unchanged {
+                original.frame_width
+            } else {
+                texture.frame_width
+            },
+            frame_height: if size_unchanged {
+                original.frame_height
+            } else {
+                texture.frame_height
+            },
+            frame_count: if size_unchanged {
+                original.frame_count
+            } else {
+                1
+            },
+            unknown1: original.unknown1,
+            unknown2: original.unknown2,
+            unknown3: original.unknown3,
+            unknown4: original.unknown4,
+            ..texture
+        };
+
+        Ok((BigSubHeader::Texture(texture), payload))
+    })
+}
+
+/// Replaces a mesh's payload file and sub-header in an extracted directory, ready for `pack`,
+/// with a model compiled from a glTF or OBJ file as the only level of detail.
+///
+/// The physics mesh of the replaced mesh is kept.
+fn import_mesh(
+    directory: String,
+    symbol_name: String,
+    model_path: String,
+    layout: Option<String>,
+) -> anyhow::Result<()> {
+    let model_path = Path::new(&model_path);
+
+    let is_obj = model_path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("obj"));
+
+    let mut bbm = if is_obj {
+        obj_to_bbm(model_path)?
+    } else {
+        gltf_to_bbm(model_path)?
+    };
+
+    let layout = vertex_layout(layout.as_deref(), !bbm.bones.is_empty())?;
+
+    for sub_mesh in &mut bbm.sub_meshes {
+        sub_mesh.set_layout(layout);
+    }
+
+    let payload = bbm
+        .compile()
+        .map_err(|e| anyhow!("failed to compile mesh. {:?}", e))?;
+
+    replace_entry(&directory, &symbol_name, |entry| {
+        let BigSubHeader::Mesh(original) = &entry.sub_header else {
+            return Err(anyhow!("{} is not a mesh.", symbol_name));
+        };
+
+        let size = u32::try_from(payload.len()).map_err(|_| anyhow!("mesh is too large."))?;
+
+        let mesh = BigSubHeaderMesh {
+            physics_mesh: original.physics_mesh,
+            ..bbm.sub_header(vec![size])
+        };
+
+        Ok((BigSubHeader::Mesh(mesh), payload))
+    })
+}
+
+/// The vertex layout named by `layout`, in its skinned form when the model has bones. Defaults to
+/// float coordinates.
+fn vertex_layout(layout: Option<&str>, skinned: bool) -> anyhow::Result<BbmVertexLayout> {
+    match (layout.unwrap_or("float"), skinned) {
+        ("float", false) => Ok(BbmVertexLayout::Float),
+        ("float", true) => Ok(BbmVertexLayout::FloatSkinned),
+        ("float-extra", true) => Ok(BbmVertexLayout::FloatSkinnedExtra),
+        ("packed", false) => Ok(BbmVertexLayout::Packed),
+        ("packed", true) => Ok(BbmVertexLayout::PackedSkinned),
+        ("packed-extra", true) => Ok(BbmVertexLayout::PackedSkinnedExtra),
+        ("full", false) => Ok(BbmVertexLayout::Full),
+        (layout @ ("float-extra" | "packed-extra"), false) => {
+            Err(anyhow!("vertex layout {} needs a skinned model.", layout))
+        }
+        ("full", true) => Err(anyhow!("vertex layout full has no bones.")),
+        (layout, _) => Err(anyhow!("unknown vertex layout {}.", layout)),
+    }
+}
+
+/// Replaces the payload file and sub-header of the entry named `symbol_name` in an extracted
+/// directory with what `replace` makes from the entry.
+fn replace_entry(
+    directory: &str,
+    symbol_name: &str,
+    replace: impl FnOnce(&BigFileEntryOwned) -> anyhow::Result<(BigSubHeader<'static>, Vec<u8>)>,
+) -> anyhow::Result<()> {
+    let directory = PathBuf::from(directory);
+    let manifest_path = directory.join(MANIFEST_FILE_NAME);
+
+    let manifest_source =
+        fs::read_to_string(&manifest_path).map_err(|_| anyhow!("failed to read manifest."))?;
+
+    let mut manifest: serde_json::Value = serde_json::from_str(&manifest_source)
+        .map_err(|e| anyhow!("failed to parse manifest. {}", e))?;
+
+    let banks = manifest["banks"]
+        .as_array_mut()
+        .ok_or_else(|| anyhow!("manifest has no banks."))?;
+
+    for manifest_bank in banks {
+        let bank: BigBankIndexOwned = serde_json::from_value(manifest_bank["bank"].clone())
+            .map_err(|e| anyhow!("failed to parse manifest bank. {}", e))?;
+
+        let manifest_entries = &mut manifest_bank["index"]["entries"];
+
+        let mut entries: Vec<BigFileEntryOwned> = serde_json::from_value(manifest_entries.clone())
+            .map_err(|e| anyhow!("failed to parse manifest entries. {}", e))?;
+
+        let Some(position) = entries
+            .iter()
+            .position(|entry| entry.symbol_name == symbol_name.as_bytes())
+        else {
+            continue;
+        };
+
+        let file_name = entry_file_names(owned_entry_names(&entries)).swap_remove(position);
+        let entry = &mut entries[position];
+
+        let (sub_header, payload) = replace(entry)?;
+
+        entry.size = u32::try_from(payload.len()).map_err(|_| anyhow!("entry is too large."))?;
+        entry.sub_header = sub_header;
+
+        let entry_path = directory
+            .join(bank_dir_name(&bank.name, bank.bank_id))
+            .join(file_name);
+
+        fs::write(&entry_path, payload)
+            .map_err(|_| anyhow!("failed to write {:?}.", entry_path))?;
+
+        *manifest_entries = serde_json::to_value(&entries)
+            .map_err(|_| anyhow!("failed to serialize manifest entries."))?;
+
+        let manifest_str = serde_json::to_string_pretty(&manifest)
+            .map_err(|_| anyhow!("failed to serialize JSON"))?;
+
+        fs::write(&manifest_path, manifest_str)
+            .map_err(|_| anyhow!("failed to write manifest."))?;
+
+        return Ok(());
+    }
+
+    Err(anyhow!("no entry with symbol name {}.", symbol_name))
+}
+
+/// Reads a .dds file as is, or encodes a .png image like the `original` texture unless `format`
+/// or `mip_maps` say otherwise.
+fn read_texture(
+    image_path: &str,
+    original: &BigSubHeaderTexture,
+    format: Option<String>,
+    mip_maps: Option<u8>,
+) -> anyhow::Result<(BigSubHeaderTexture, Vec<u8>)> {
+    let image_path = Path::new(image_path);
+
+    let is_dds = image_path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("dds"));
+
+    if is_dds {
+        let bytes =
+            fs::read(image_path).map_err(|_| anyhow!("failed to read {:?}.", image_path))?;
+
+        let (texture, payload) =
+            dds_to_texture(&bytes).map_err(|e| anyhow!("failed to read DDS file. {:?}", e))?;
+
+        return Ok((texture, payload.to_vec()));
+    }
+
+    let format = match format.as_deref() {
+        None => TextureFormat::from_sub_header(original)
+            .ok_or_else(|| anyhow!("could not determine the replaced texture's format."))?,
+        Some("dxt1") => TextureFormat::Dxt1,
+        Some("dxt3") => TextureFormat::Dxt3,
+        Some("dxt5") => TextureFormat::Dxt5,
+        Some("bgra8888") => TextureFormat::Bgra8888,
+        Some("bgra4444") => TextureFormat::Bgra4444,
+        Some("bgr565") => TextureFormat::Bgr565,
+        Some(format) => Err(anyhow!("unknown texture format {}.", format))?,
+    };
+
+    let image = read_png(image_path)?;
+
+    encode_texture(&image, format, mip_maps.unwrap_or(original.mip_maps))
+        .map_err(|e| anyhow!("failed to encode texture. {:?}", e))
+}
+
+fn read_png(path: &Path) -> anyhow::Result<TextureImage> {
+    let file = File::open(path).map_err(|_| anyhow!("failed to open {:?}.", path))?;
+
+    let mut decoder = png::Decoder::new(io::BufReader::new(file));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| anyhow!("failed to read {:?}. {}", path, e))?;
+
+    let mut buf = vec![0; reader.output_buffer_size()];
+
+    let info = reader
+        .next_frame(&mut buf)
+        .map_err(|e| anyhow!("failed to read {:?}. {}", path, e))?;
+
+    let pixels = &buf[..info.buffer_size()];
+
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|&p| [p, p, p, 255]).collect(),
+        png::ColorType::Indexed => Err(anyhow!("failed to expand palette of {:?}.", path))?,
+    };
+
+    Ok(TextureImage {
+        width: info.width,
+        height: info.height,
+        rgba,
+    })
+}
+
+fn write_png(path: &Path, image: &TextureImage) -> anyhow::Result<()> {
+    let file = File::create(path).map_err(|_| anyhow!("failed to create {:?}.", path))?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), image.width, image.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&image.rgba))
+        .map_err(|e| anyhow!("failed to write {:?}. {}", path, e))?;
+
+    Ok(())
+}
+
+fn list(file_path: String) -> anyhow::Result<()> {
+    let file_path = Utf8PathBuf::from(file_path);
+    let big = open_big(&file_path)?;
+
+    for (bank, entry) in big.entries() {
+        let kind = bank.bank.kind();
+        let entry = entry.as_entry();
+
+        println!(
+            "{}\t{}\t{:?}\t{:?}\t{}\t{}",
+            String::from_utf8_lossy(&bank.bank.name),
+            entry.id,
+            entry.file_type(kind),
+            entry.file_type_dev(kind),
+            entry.size,
+            String::from_utf8_lossy(entry.symbol_name),
+        );
+    }
+
+    Ok(())
+}
+
+fn check(file_path: String) -> anyhow::Result<()> {
+    let file_path = Utf8PathBuf::from(file_path);
+    let mut file = File::open(&file_path).map_err(|_e| anyhow!("file not found."))?;
+
+    let issues = check_big(&mut file).map_err(|_| anyhow!("could not read file."))?;
+
+    for issue in &issues {
+        println!("{}", issue);
+    }
+
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("found {} problems.", issues.len()))
+    }
+}
+
+fn inspect(file_path: String, compress: bool) -> anyhow::Result<()> {
+    let file_path = Utf8PathBuf::from(file_path);
+    // Safety: the archive is expected not to change while it's read.
+    let file = unsafe { MmapFile::open(&file_path) }.map_err(|_| anyhow!("file not found."))?;
+    let big = file.big().map_err(big_archive_error)?;
+
+    let json = big_json(&big);
+
+    let json_str = if compress {
+        serde_json::to_string(&json)
+            .map_err(|_| anyhow!("failed to serialize JSON (compressed)."))?
+    } else {
+        serde_json::to_string_pretty(&json).map_err(|_| anyhow!("failed to serialize JSON"))?
+    };
+
+    println!("{}", json_str);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+    use fable_format::big::BigSubHeaderTexture;
+
+    fn entry(
+        id: u32,
+        file_type: u32,
+        name: &str,
+        sub_header: BigSubHeader<'static>,
+    ) -> BigFileEntryOwned {
+        BigFileEntryOwned {
+            magic: 42,
+            id,
+            file_type,
+            size: 0,
+            start: 0,
+            file_type_dev: file_type,
+            symbol_name: name.as_bytes().to_vec(),
+            crc: 0,
+            files: Vec::new(),
+            sub_header,
+        }
+    }
+
+    /// Writes a bank of 2048 byte entries, each filling one block, so any entry that grows runs
+    /// into the next.
+    fn write_big(path: &Path, bank_name: &str, entries: &[BigFileEntryOwned]) {
+        let file = File::create(path).unwrap();
+        let mut writer = BigWriter::new(BufWriter::new(file));
+
+        writer.add_bank(BigBankMetadata {
+            name: bank_name.as_bytes().to_vec(),
+            bank_id: 1,
+            block_size: 2048,
+            file_type: entries[0].file_type,
+            types_map: vec![[entries[0].file_type, entries.len() as u32]],
+            index_start: None,
+        });
+
+        for (index, entry) in entries.iter().enumerate() {
+            writer
+                .add_entry_bytes(&[index as u8 + 1; 2048], entry, None)
+                .unwrap();
+        }
+
+        writer.finish().unwrap();
+    }
+
+    /// Packs `directory`, checks the archive and returns the payloads of its entries by symbol
+    /// name.
+    fn pack_and_check(directory: &Path, output: &Path) -> HashMap<String, Vec<u8>> {
+        pack(
+            directory.to_str().unwrap().to_owned(),
+            Some(output.to_str().unwrap().to_owned()),
+        )
+        .unwrap();
+
+        let issues = check_big(&mut File::open(output).unwrap()).unwrap();
+        assert!(issues.is_empty(), "{:?}", issues);
+
+        let big = open_big(output).unwrap();
+
+        big.entries()
+            .map(|(_, entry)| {
+                let name = String::from_utf8(entry.symbol_name.clone()).unwrap();
+                (name, big.read_entry(entry).unwrap())
+            })
+            .collect()
+    }
+
+    fn extract_to(file: &Path, directory: &Path) {
+        extract(
+            file.to_str().unwrap().to_owned(),
+            Some(directory.to_str().unwrap().to_owned()),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn regions_overlap() {
+        assert!(regions_fit(&mut vec![(0, 32), (2048, 2048), (4096, 10)]));
+        assert!(regions_fit(&mut vec![(2048, 10), (2048, 10), (4096, 0)]));
+        assert!(!regions_fit(&mut vec![(2048, 2049), (4096, 10)]));
+        assert!(!regions_fit(&mut vec![(0, 8192), (2048, 10), (6144, 10)]));
+    }
+
+    #[test]
+    fn import_larger_texture() {
+        let dir = temp_dir("big_import_larger_texture");
+        let original_path = dir.join("textures.big");
+
+        let (header, _) = encode_texture(
+            &TextureImage {
+                width: 64,
+                height: 64,
+                rgba: vec![0; 64 * 64 * 4],
+            },
+            TextureFormat::Dxt1,
+            1,
+        )
+        .unwrap();
+
+        let texture = |id, name| entry(id, 0, name, BigSubHeader::Texture(header.clone()));
+        write_big(
+            &original_path,
+            "GBANK_TEST",
+            &[texture(1, "TEX_A"), texture(2, "TEX_B")],
+        );
+
+        let extracted = dir.join("textures");
+        extract_to(&original_path, &extracted);
+
+        let image = TextureImage {
+            width: 64,
+            height: 64,
+            rgba: (0..64 * 64 * 4).map(|x| x as u8).collect(),
+        };
+        let image_path = dir.join("image.png");
+        write_png(&image_path, &image).unwrap();
+
+        import_texture(
+            extracted.to_str().unwrap().to_owned(),
+            "TEX_A".to_owned(),
+            image_path.to_str().unwrap().to_owned(),
+            Some("bgra8888".to_owned()),
+            Some(1),
+        )
+        .unwrap();
+
+        let payloads = pack_and_check(&extracted, &dir.join("packed.big"));
+
+        assert_eq!(payloads["TEX_A"], image.encode(TextureFormat::Bgra8888));
+        assert_eq!(payloads["TEX_B"], [2; 2048]);
+
+        let big = open_big(dir.join("packed.big")).unwrap();
+        let (_, tex_a) = big.entries().next().unwrap();
+
+        assert!(matches!(
+            &tex_a.sub_header,
+            BigSubHeader::Texture(BigSubHeaderTexture {
+                width: 64,
+                top_mip_map_size: 16384,
+                ..
+            })
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn import_larger_mesh() {
+        let dir = temp_dir("big_import_larger_mesh");
+        let original_path = dir.join("graphics.big");
+
+        let mesh = |id, name| {
+            let sub_header = BigSubHeaderMesh {
+                physics_mesh: 7,
+                unknown1: [0.0; 10],
+                size_compressed_lod: vec![2048],
+                padding: 0,
+                unknown2: Vec::new(),
+                texture_ids: Vec::new(),
+            };
+
+            entry(id, 1, name, BigSubHeader::Mesh(sub_header))
+        };
+
+        write_big(
+            &original_path,
+            "MBANK_TEST",
+            &[mesh(1, "MESH_A"), mesh(2, "MESH_B")],
+        );
+
+        let extracted = dir.join("graphics");
+        extract_to(&original_path, &extracted);
+
+        // A strip of triangles with irregular coordinates, too large for one block.
+        let mut obj = String::new();
+
+        for i in 0..400 {
+            let x = i as f32 * 0.37;
+            obj += &format!("v {} {} {}\n", x, (x * 1.7).sin(), (x * 0.3).cos());
+        }
+
+        for i in 1..399 {
+            obj += &format!("f {} {} {}\n", i, i + 1, i + 2);
+        }
+
+        let obj_path = dir.join("model.obj");
+        fs::write(&obj_path, obj).unwrap();
+
+        import_mesh(
+            extracted.to_str().unwrap().to_owned(),
+            "MESH_A".to_owned(),
+            obj_path.to_str().unwrap().to_owned(),
+            None,
+        )
+        .unwrap();
+
+        let payloads = pack_and_check(&extracted, &dir.join("packed.big"));
+
+        assert!(payloads["MESH_A"].len() > 2048);
+        assert_eq!(payloads["MESH_B"], [2; 2048]);
+
+        let bbm = Bbm::parse(&mut &payloads["MESH_A"][..]).unwrap();
+        assert_eq!(bbm.sub_meshes[0].vertices.len(), 400);
+
+        let big = open_big(dir.join("packed.big")).unwrap();
+        let (_, mesh_a) = big.entries().next().unwrap();
+
+        assert!(matches!(
+            &mesh_a.sub_header,
+            BigSubHeader::Mesh(BigSubHeaderMesh {
+                physics_mesh: 7,
+                ..
+            })
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,11 +1,18 @@
 use anyhow::anyhow;
 use clap::{Args, Subcommand};
-use fable_format::wad::{WadEntry, WadHeader};
+use fable_format::wad::{WadEntryMetadata, WadEntryOwned, WadHeader, WadTimestamp, WadWriter};
+use fable_io::{
+    check::check_wad,
+    mmap::{MmapFile, WadArchiveError},
+    wad_file::{WadFile, WadFileError, WadReplaceError},
+};
 use std::{
     fs::{self, File},
-    io::{BufReader, Read, Seek, SeekFrom},
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    time::SystemTime,
 };
-use typed_path::{Utf8PathBuf, Utf8WindowsEncoding};
+use typed_path::{Utf8PathBuf, Utf8WindowsComponent, Utf8WindowsEncoding};
 
 #[derive(Args, Debug, Clone)]
 pub struct WadArgs {
@@ -29,8 +36,26 @@ enum WadCommand {
 
         #[arg(long, short)]
         output: Option<String>,
+
+        /// Windows path prepended to every entry, e.g. `Data\Levels\FinalAlbion`
+        #[arg(long, short)]
+        prefix: Option<String>,
     },
 
+    #[command(about = "Replace the data of one entry in a .wad file without repacking it.")]
+    Replace {
+        file: String,
+
+        /// Windows path of the entry, e.g. `Data\Levels\FinalAlbion\LookoutPoint.tng`
+        entry_path: String,
+
+        /// File with the new entry data
+        input: String,
+    },
+
+    #[command(about = "Check a .wad file for corrupt headers, entries or data ranges.")]
+    Check { file: String },
+
     #[command(about = "Print information about a .wad file as JSON.")]
     Inspect {
         file: String,
@@ -45,25 +70,29 @@ pub fn handle(args: WadArgs) -> anyhow::Result<()> {
     match args.command {
         None => Ok(()),
         Some(WadCommand::Extract { file, output }) => extract(file, output),
-        Some(WadCommand::Pack { directory, output }) => pack(directory, output),
+        Some(WadCommand::Pack {
+            directory,
+            output,
+            prefix,
+        }) => pack(directory, output, prefix),
+        Some(WadCommand::Replace {
+            file,
+            entry_path,
+            input,
+        }) => replace(file, entry_path, input),
+        Some(WadCommand::Check { file }) => check(file),
         Some(WadCommand::Inspect { file, compress }) => inspect(file, compress),
     }
 }
 
 fn extract(file_path: String, output_path: Option<String>) -> anyhow::Result<()> {
     let file_path = Utf8PathBuf::from(file_path);
-    let file = File::open(&file_path).map_err(|_e| anyhow!("file not found."))?;
-    let mut buf = BufReader::new(file);
-    let mut header_bytes = vec![0; WadHeader::byte_size()];
-
-    buf.read_exact(&mut header_bytes)
-        .map_err(|_e| anyhow!("could not read file."))?;
-
-    let header =
-        WadHeader::from_bytes(&header_bytes).map_err(|_e| anyhow!("could not parse header."))?;
+    // Safety: the archive is expected not to change while it's read.
+    let file = unsafe { MmapFile::open(&file_path) }.map_err(|_| anyhow!("file not found."))?;
+    let wad = file.wad().map_err(wad_archive_error)?;
 
     let output_path = output_path
-        .map(|x| Utf8PathBuf::from(x))
+        .map(Utf8PathBuf::from)
         .or_else(|| {
             let file_stem = file_path.file_stem()?;
 
@@ -77,86 +106,322 @@ fn extract(file_path: String, output_path: Option<String>) -> anyhow::Result<()>
     fs::create_dir_all(&output_path)
         .map_err(|_e| anyhow!("failed to establish output directory"))?;
 
-    let entry_count = header.entry_count as usize;
-    let mut current_position = header.first_entry_position as usize;
-    let mut entry_bytes = vec![0; 1 << 13]; // 8KiB
-    let mut entries = Vec::with_capacity(entry_count);
+    for entry in &wad.entries {
+        let entry_output_path = entry_output_path(output_path.as_ref(), entry.path)?;
 
-    for _ in 0..entry_count {
-        buf.seek(SeekFrom::Start(current_position as u64))
-            .map_err(|_e| anyhow!("failed to seek through wad file."))?;
+        if let Some(parent) = entry_output_path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|_| anyhow!("failed to establish entry directory."))?;
+        }
 
-        buf.read(&mut entry_bytes)
-            .map_err(|_e| anyhow!("failed to read entry."))?;
+        let data = wad
+            .entry_data(entry)
+            .ok_or_else(|| anyhow!("{} lies past the end of the file.", entry.path))?;
 
-        let entry = WadEntry::from_bytes(&entry_bytes)
-            .map_err(|e| anyhow!("failed to parse entry. {:?}", e))?;
+        fs::write(entry_output_path, data).map_err(|_| anyhow!("failed to write entry."))?;
+    }
+
+    let manifest = serde_json::json!({ "header": wad.header, "entries": wad.entries });
+
+    let manifest_str =
+        serde_json::to_string_pretty(&manifest).map_err(|_| anyhow!("failed to serialize JSON"))?;
 
-        current_position += entry.byte_size();
+    fs::write(output_path.join(MANIFEST_FILE_NAME), manifest_str)
+        .map_err(|_| anyhow!("failed to write manifest."))?;
+
+    Ok(())
+}
 
-        entries.push(entry.to_owned());
+fn wad_file_error(error: WadFileError) -> anyhow::Error {
+    match error {
+        WadFileError::Open(_) => anyhow!("file not found."),
+        WadFileError::ReadHeader(_) => anyhow!("could not read file."),
+        WadFileError::Header(_) => anyhow!("could not parse header."),
+        WadFileError::ReadEntries(_) => anyhow!("failed to read entries."),
+        WadFileError::Entry(e) => anyhow!("failed to parse entry. {:?}", e),
     }
+}
 
-    for entry in &entries {
-        let entry_path = Utf8PathBuf::<Utf8WindowsEncoding>::from(&entry.path);
+fn wad_archive_error(error: WadArchiveError) -> anyhow::Error {
+    match error {
+        WadArchiveError::Header(_) => anyhow!("could not parse header."),
+        WadArchiveError::EntriesPosition(_) => anyhow!("entries lie past the end of the file."),
+        WadArchiveError::Entry(e) => anyhow!("failed to parse entry. {:?}", e),
+    }
+}
 
-        let entry_file_name = entry_path
-            .file_name()
-            .ok_or_else(|| anyhow!("failed to determine entry file name."))?;
+/// Name of the file `extract` writes next to the entries so that `pack` can restore the archive.
+const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+struct PackEntry {
+    path: String,
+    source: PathBuf,
+    metadata: WadEntryMetadata,
+}
+
+fn pack(
+    directory: String,
+    output_path: Option<String>,
+    prefix: Option<String>,
+) -> anyhow::Result<()> {
+    let directory = PathBuf::from(directory);
 
-        let entry_output_path = output_path.join(entry_file_name);
+    let output_path = output_path
+        .map(PathBuf::from)
+        .or_else(|| {
+            let file_name = directory.file_name()?.to_str()?;
+            Some(directory.with_file_name(format!("{}.wad", file_name)))
+        })
+        .ok_or_else(|| anyhow!("could not determine output path."))?;
 
-        buf.seek(SeekFrom::Start(entry.offset as u64))
-            .map_err(|_| anyhow!("failed to seek to entry data."))?;
+    let manifest_path = directory.join(MANIFEST_FILE_NAME);
 
-        let mut entry_file_bytes = vec![0; entry.length as usize];
+    let (header, entries) = if manifest_path.is_file() {
+        if prefix.is_some() {
+            return Err(anyhow!(
+                "--prefix can't be used with a manifest, whose entry paths are used as they are."
+            ));
+        }
 
-        buf.read_exact(&mut entry_file_bytes)
-            .map_err(|_| anyhow!("failed to read entry data."))?;
+        pack_entries_from_manifest(&directory, &manifest_path)?
+    } else {
+        pack_entries_from_directory(&directory, prefix.as_deref())?
+    };
+
+    let file = File::create(&output_path).map_err(|_| anyhow!("failed to create output file."))?;
+
+    let mut writer = match header {
+        Some(header) => WadWriter::with_header(BufWriter::new(file), header),
+        None => WadWriter::new(BufWriter::new(file)),
+    };
 
-        fs::write(entry_output_path, &entry_file_bytes)
-            .map_err(|_| anyhow!("failed to write entry."))?;
+    for PackEntry {
+        path,
+        source,
+        metadata,
+    } in &entries
+    {
+        let mut entry_file =
+            File::open(source).map_err(|_| anyhow!("failed to open {:?}.", source))?;
+
+        writer
+            .add_entry(path, &mut entry_file, metadata)
+            .map_err(|e| anyhow!("failed to write entry {:?}. {:?}", path, e))?;
     }
 
+    writer
+        .finish()
+        .map_err(|e| anyhow!("failed to write wad file. {:?}", e))?;
+
     Ok(())
 }
 
-fn pack(_directory: String, _output: Option<String>) -> anyhow::Result<()> {
+/// Builds entries for every file under `directory`, using defaults for the metadata.
+fn pack_entries_from_directory(
+    directory: &Path,
+    prefix: Option<&str>,
+) -> anyhow::Result<(Option<WadHeader>, Vec<PackEntry>)> {
+    let mut file_paths = Vec::new();
+
+    collect_files(directory, &mut file_paths)
+        .map_err(|_| anyhow!("failed to read input directory."))?;
+
+    file_paths.sort();
+
+    let mut entries = Vec::with_capacity(file_paths.len());
+
+    for (id, source) in file_paths.into_iter().enumerate() {
+        let path = source
+            .strip_prefix(directory)
+            .ok()
+            .and_then(to_windows_path)
+            .ok_or_else(|| anyhow!("failed to determine entry path of {:?}.", source))?;
+
+        let path = match prefix {
+            Some(prefix) => format!("{}\\{}", prefix.trim_end_matches('\\'), path),
+            None => path,
+        };
+
+        let file_metadata =
+            fs::metadata(&source).map_err(|_| anyhow!("failed to read {:?}.", source))?;
+
+        let modified = file_metadata
+            .modified()
+            .unwrap_or_else(|_| SystemTime::now());
+        let created = file_metadata.created().unwrap_or(modified);
+        let accessed = file_metadata.accessed().unwrap_or(modified);
+
+        let metadata = WadEntryMetadata {
+            id: u32::try_from(id).map_err(|_| anyhow!("too many files to pack."))?,
+            created: WadTimestamp::from_system_time(created),
+            accessed: WadTimestamp::from_system_time(accessed),
+            modified: WadTimestamp::from_system_time(modified),
+            ..Default::default()
+        };
+
+        entries.push(PackEntry {
+            path,
+            source,
+            metadata,
+        });
+    }
+
+    Ok((None, entries))
+}
+
+/// Builds entries from a manifest written by `extract`.
+///
+/// When none of the files changed size, the original offsets are kept so the output is identical
+/// to the extracted archive. Otherwise the entries are laid out again in manifest order.
+fn pack_entries_from_manifest(
+    directory: &Path,
+    manifest_path: &Path,
+) -> anyhow::Result<(Option<WadHeader>, Vec<PackEntry>)> {
+    let manifest_source =
+        fs::read_to_string(manifest_path).map_err(|_| anyhow!("failed to read manifest."))?;
+
+    let mut manifest: serde_json::Value = serde_json::from_str(&manifest_source)
+        .map_err(|e| anyhow!("failed to parse manifest. {}", e))?;
+
+    let header: WadHeader = serde_json::from_value(manifest["header"].take())
+        .map_err(|e| anyhow!("failed to parse manifest header. {}", e))?;
+
+    let manifest_entries: Vec<WadEntryOwned> =
+        serde_json::from_value(manifest["entries"].take())
+            .map_err(|e| anyhow!("failed to parse manifest entries. {}", e))?;
+
+    let mut entries = Vec::with_capacity(manifest_entries.len());
+    let mut layout_changed = false;
+
+    for entry in manifest_entries {
+        let source = entry_output_path(directory, &entry.path)?;
+
+        let length = fs::metadata(&source)
+            .map_err(|_| anyhow!("failed to read {:?}.", source))?
+            .len();
+
+        if entry.length as u64 != length {
+            layout_changed = true;
+        }
+
+        entries.push(PackEntry {
+            metadata: entry.metadata(),
+            path: entry.path,
+            source,
+        });
+    }
+
+    if layout_changed {
+        for entry in &mut entries {
+            entry.metadata.offset = None;
+        }
+    }
+
+    Ok((Some(header), entries))
+}
+
+fn collect_files(directory: &Path, out: &mut Vec<PathBuf>) -> io::Result<()> {
+    for dir_entry in fs::read_dir(directory)? {
+        let path = dir_entry?.path();
+
+        if path.is_dir() {
+            collect_files(&path, out)?;
+        } else {
+            out.push(path);
+        }
+    }
+
     Ok(())
 }
 
-fn inspect(file_path: String, compress: bool) -> anyhow::Result<()> {
+fn to_windows_path(path: &Path) -> Option<String> {
+    let components = path
+        .components()
+        .map(|x| x.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(components.join("\\"))
+}
+
+/// Resolves an entry's Windows path under `directory`.
+///
+/// Drive prefixes and leading separators are dropped, so `C:\Data\A.tng` resolves like
+/// `Data\A.tng`. Paths that would escape `directory` or land on the manifest are refused.
+fn entry_output_path(directory: &Path, entry_path: &str) -> anyhow::Result<PathBuf> {
+    let entry_path = Utf8PathBuf::<Utf8WindowsEncoding>::from(entry_path);
+    let mut output_path = directory.to_path_buf();
+
+    for component in entry_path.components() {
+        match component {
+            Utf8WindowsComponent::Normal(name) => output_path.push(name),
+            Utf8WindowsComponent::Prefix(_)
+            | Utf8WindowsComponent::RootDir
+            | Utf8WindowsComponent::CurDir => {}
+            Utf8WindowsComponent::ParentDir => {
+                Err(anyhow!("entry path {:?} leaves the directory.", entry_path))?
+            }
+        }
+    }
+
+    let is_manifest = output_path.parent() == Some(directory)
+        && output_path
+            .file_name()
+            .and_then(|x| x.to_str())
+            .is_some_and(|x| x.eq_ignore_ascii_case(MANIFEST_FILE_NAME));
+
+    if is_manifest {
+        Err(anyhow!(
+            "entry path {:?} collides with the manifest.",
+            entry_path
+        ))?
+    }
+
+    if output_path == directory {
+        Err(anyhow!("entry path {:?} has no file name.", entry_path))?
+    }
+
+    Ok(output_path)
+}
+
+fn replace(file_path: String, entry_path: String, input_path: String) -> anyhow::Result<()> {
     let file_path = Utf8PathBuf::from(file_path);
-    let file = File::open(&file_path).map_err(|_e| anyhow!("file not found."))?;
-    let mut buf = BufReader::new(file);
-    let mut header_bytes = vec![0; WadHeader::byte_size()];
 
-    buf.read_exact(&mut header_bytes)
-        .map_err(|_e| anyhow!("could not read file."))?;
+    let data = fs::read(&input_path).map_err(|_| anyhow!("failed to read {}.", input_path))?;
 
-    let header =
-        WadHeader::from_bytes(&header_bytes).map_err(|_e| anyhow!("could not parse header."))?;
-    let entry_count = header.entry_count as usize;
-    let mut current_position = header.first_entry_position as usize;
-    let mut entry_bytes = vec![0; 1 << 13]; // 8KiB
-    let mut entries = Vec::with_capacity(entry_count);
+    let mut wad = WadFile::open_writable(&file_path).map_err(wad_file_error)?;
 
-    for _ in 0..entry_count {
-        buf.seek(SeekFrom::Start(current_position as u64))
-            .map_err(|_e| anyhow!("failed to seek through wad file."))?;
+    wad.replace_entry(&entry_path, &data).map_err(|e| match e {
+        WadReplaceError::NotFound => anyhow!("no entry with path {}.", entry_path),
+        e => anyhow!("failed to replace entry. {:?}", e),
+    })?;
 
-        buf.read(&mut entry_bytes)
-            .map_err(|_e| anyhow!("failed to read entry."))?;
+    Ok(())
+}
 
-        let entry = WadEntry::from_bytes(&entry_bytes)
-            .map_err(|e| anyhow!("failed to parse entry. {:?}", e))?;
+fn check(file_path: String) -> anyhow::Result<()> {
+    let file_path = Utf8PathBuf::from(file_path);
+    let mut file = File::open(&file_path).map_err(|_e| anyhow!("file not found."))?;
 
-        current_position += entry.byte_size();
+    let issues = check_wad(&mut file).map_err(|_| anyhow!("could not read file."))?;
 
-        entries.push(entry.to_owned());
+    for issue in &issues {
+        println!("{}", issue);
+    }
+
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("found {} problems.", issues.len()))
     }
+}
+
+fn inspect(file_path: String, compress: bool) -> anyhow::Result<()> {
+    let file_path = Utf8PathBuf::from(file_path);
+    // Safety: the archive is expected not to change while it's read.
+    let file = unsafe { MmapFile::open(&file_path) }.map_err(|_| anyhow!("file not found."))?;
+    let wad = file.wad().map_err(wad_archive_error)?;
 
-    let json = serde_json::json!({ "header": header, "entries": entries });
+    let json = serde_json::json!({ "header": wad.header, "entries": wad.entries });
 
     let json_str = if compress {
         serde_json::to_string(&json)
@@ -169,3 +434,121 @@ fn inspect(file_path: String, compress: bool) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    fn write_wad(path: &Path, entries: &[(&str, &[u8])]) {
+        let file = File::create(path).unwrap();
+        let mut writer = WadWriter::new(BufWriter::new(file));
+
+        for (id, (entry_path, data)) in entries.iter().enumerate() {
+            let metadata = WadEntryMetadata {
+                id: id as u32,
+                ..Default::default()
+            };
+
+            writer.add_entry_bytes(entry_path, data, &metadata).unwrap();
+        }
+
+        writer.finish().unwrap();
+    }
+
+    fn path_string(path: &Path) -> String {
+        path.to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn entry_output_paths() {
+        let directory = Path::new("out");
+
+        assert_eq!(
+            entry_output_path(directory, r"Data\Levels\A.tng").unwrap(),
+            directory.join("Data").join("Levels").join("A.tng")
+        );
+        assert_eq!(
+            entry_output_path(directory, r"C:\Data\A.tng").unwrap(),
+            directory.join("Data").join("A.tng")
+        );
+        assert_eq!(
+            entry_output_path(directory, r"\Data\.\A.tng").unwrap(),
+            directory.join("Data").join("A.tng")
+        );
+        assert_eq!(
+            entry_output_path(directory, r"Data\manifest.json").unwrap(),
+            directory.join("Data").join("manifest.json")
+        );
+
+        assert!(entry_output_path(directory, r"Data\..\..\A.tng").is_err());
+        assert!(entry_output_path(directory, "manifest.json").is_err());
+        assert!(entry_output_path(directory, r"C:\MANIFEST.JSON").is_err());
+        assert!(entry_output_path(directory, r"C:\").is_err());
+    }
+
+    #[test]
+    fn extract_and_pack() {
+        let dir = temp_dir("wad_extract_and_pack");
+        let wad_path = dir.join("Levels.wad");
+
+        write_wad(
+            &wad_path,
+            &[
+                (r"C:\Data\Levels\A.tng", b"first"),
+                (r"Data\manifest.json", &[7; 3000]),
+            ],
+        );
+
+        let extracted = dir.join("Levels");
+        extract(path_string(&wad_path), Some(path_string(&extracted))).unwrap();
+
+        assert_eq!(
+            fs::read(extracted.join("Data").join("Levels").join("A.tng")).unwrap(),
+            b"first"
+        );
+
+        let packed = dir.join("Packed.wad");
+        pack(path_string(&extracted), Some(path_string(&packed)), None).unwrap();
+
+        assert_eq!(fs::read(&packed).unwrap(), fs::read(&wad_path).unwrap());
+
+        // A grown entry moves the data and the entry table, which must still be readable.
+        fs::write(
+            extracted.join("Data").join("Levels").join("A.tng"),
+            [1; 5000],
+        )
+        .unwrap();
+        pack(path_string(&extracted), Some(path_string(&packed)), None).unwrap();
+
+        assert!(check_wad(&mut File::open(&packed).unwrap())
+            .unwrap()
+            .is_empty());
+
+        let wad = WadFile::open(&packed).unwrap();
+        assert_eq!(wad.entries()[0].length, 5000);
+        assert_eq!(wad.entries()[1].path, r"Data\manifest.json");
+
+        let prefixed = pack(
+            path_string(&extracted),
+            Some(path_string(&packed)),
+            Some("Data".to_owned()),
+        );
+        assert!(prefixed.is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn manifest_entry_is_refused() {
+        let dir = temp_dir("wad_manifest_entry");
+        let wad_path = dir.join("Bad.wad");
+
+        write_wad(&wad_path, &[("manifest.json", b"{}")]);
+
+        let extracted = dir.join("Bad");
+        assert!(extract(path_string(&wad_path), Some(path_string(&extracted))).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,11 @@
+//! Helpers shared by the tests of the crate.
+
+use std::{fs, path::PathBuf};
+
+/// An empty directory of its own under the system's temporary directory.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fool_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}